@@ -0,0 +1,186 @@
+use crate::class_index::{demangle, get_thunk_target};
+use crate::hierarchy::Hierarchy;
+use crate::library::Library;
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct SlotReference {
+    pub class_name: String,
+    pub vtable_address: u32,
+    /// offset-to-top of the vtable group holding the slot
+    pub offset_to_top: i32,
+    pub slot: usize,
+    /// byte offset of the slot from the vptr
+    pub vptr_offset: u32,
+    /// the thunk the slot points at, `None` if it points at the address directly
+    pub thunk: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct AddressInfo {
+    pub address: u32,
+    /// (symbol, offset of the address inside it), a `sub_` name for the
+    /// functions only known from the unwind tables
+    pub enclosing_symbol: Option<(String, u32)>,
+    /// the DT_NEEDED library providing the function of a PLT stub
+    pub import_library: Option<String>,
+    /// the file holding the address, when it is a dependency loaded after
+    /// the library
+    pub module: Option<String>,
+    /// the function the address jumps to when it is a thunk
+    pub thunk_target: Option<String>,
+    /// thunks adjusting `this` before jumping to the function
+    pub thunks: Vec<(u32, String)>,
+    pub references: Vec<SlotReference>,
+}
+
+pub fn get_address_info(library: &Library, hierarchy: &Hierarchy, address: u32) -> AddressInfo {
+    let enclosing_symbol = library
+        .get_enclosing_symbol(address)
+        .map(|(symbol, offset)| (symbol.clone(), offset))
+        .or_else(|| {
+            library
+                .plt_symbols
+                .contains_key(&(address & !1))
+                .then(|| (library.get_symbol_name(address), 0))
+        })
+        .or_else(|| {
+            library
+                .get_enclosing_function(address)
+                .map(|(start, offset)| (library.get_symbol_name(start), offset))
+        });
+
+    let import_library = enclosing_symbol
+        .as_ref()
+        .filter(|(symbol, _)| symbol.ends_with("@plt"))
+        .and_then(|(symbol, _)| library.get_import_library(symbol))
+        .map(str::to_string);
+
+    let module = library
+        .get_module(address)
+        .filter(|module| module.base != 0)
+        .map(|module| module.path.display().to_string());
+
+    let thunk_target = match &enclosing_symbol {
+        Some((symbol, 0)) => get_thunk_target(symbol),
+        _ => None,
+    };
+
+    // thunks are only related to the function as a whole
+    let mut thunks: Vec<(u32, String)> = match &enclosing_symbol {
+        Some((function, 0)) => library
+            .addr_to_sym
+            .iter()
+            .filter(|(_, symbol)| get_thunk_target(symbol).as_ref() == Some(function))
+            .map(|(addr, symbol)| (*addr, symbol.clone()))
+            .collect(),
+        _ => Vec::new(),
+    };
+    thunks.sort();
+
+    let mut references = Vec::new();
+    for (vtable_address, _) in library.get_vtables() {
+        let class_name = hierarchy.get_class_name(library.get_vtable_typeinfo(vtable_address));
+
+        for (offset_to_top, functions) in library.get_class_vtable(vtable_address) {
+            for (slot, addr) in functions.iter().enumerate() {
+                let thunk = if addr & !1 == address & !1 {
+                    None
+                } else if let Some((_, symbol)) = thunks
+                    .iter()
+                    .find(|(thunk_addr, _)| thunk_addr & !1 == addr & !1)
+                {
+                    Some(symbol.clone())
+                } else {
+                    continue;
+                };
+
+                references.push(SlotReference {
+                    class_name: class_name.clone(),
+                    vtable_address,
+                    offset_to_top,
+                    slot,
+                    vptr_offset: library.pointer_size * slot as u32,
+                    thunk,
+                });
+            }
+        }
+    }
+
+    references.sort_by(|a, b| {
+        (&a.class_name, -a.offset_to_top, a.slot).cmp(&(&b.class_name, -b.offset_to_top, b.slot))
+    });
+
+    AddressInfo {
+        address,
+        enclosing_symbol,
+        import_library,
+        module,
+        thunk_target,
+        thunks,
+        references,
+    }
+}
+
+impl AddressInfo {
+    pub fn get_display(&self) -> String {
+        let mut lines = vec![match &self.enclosing_symbol {
+            Some((symbol, offset)) => format!(
+                "0x{:x}  {}+0x{:x}",
+                self.address,
+                demangle(symbol).unwrap_or(symbol.clone()),
+                offset
+            ),
+            None => format!("0x{:x}  (no enclosing symbol)", self.address),
+        }];
+
+        if let Some(library) = &self.import_library {
+            lines.push(format!("imported from {}", library));
+        }
+
+        if let Some(module) = &self.module {
+            lines.push(format!("in {}", module));
+        }
+
+        if let Some(target) = &self.thunk_target {
+            lines.push(format!(
+                "thunk to {}",
+                demangle(target).unwrap_or(target.clone())
+            ));
+        }
+
+        if !self.thunks.is_empty() {
+            lines.push("thunks:".to_string());
+            for (addr, symbol) in &self.thunks {
+                lines.push(format!(
+                    "    0x{:x}  {}",
+                    addr,
+                    demangle(symbol).unwrap_or(symbol.clone())
+                ));
+            }
+        }
+
+        if self.references.is_empty() {
+            lines.push("not referenced by any vtable".to_string());
+        } else {
+            lines.push("referenced by:".to_string());
+        }
+
+        for reference in &self.references {
+            lines.push(format!(
+                "    {}  vtable 0x{:x}  [offset-to-top {}, slot {} (vptr+0x{:x})]{}",
+                reference.class_name,
+                reference.vtable_address,
+                reference.offset_to_top,
+                reference.slot,
+                reference.vptr_offset,
+                match &reference.thunk {
+                    Some(symbol) => format!("  via {}", symbol),
+                    None => String::new(),
+                }
+            ));
+        }
+
+        lines.join("\n")
+    }
+}
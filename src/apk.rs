@@ -0,0 +1,298 @@
+use std::path::Path;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+/// A file of a ZIP archive, from the central directory.
+struct Entry {
+    name: String,
+    method: u16,
+    compressed_size: u32,
+    size: u32,
+    header_offset: u32,
+}
+
+/// Reads a library from disk, or from inside an APK with the path syntax of
+/// the Android dynamic linker: `app.apk!/lib/armeabi-v7a/libcocos2dcpp.so`.
+/// Split APK bundles (.apks, .xapk) are searched for the entry, or can name
+/// the inner APK: `app.apks!/split_config.arm64_v8a.apk!/lib/arm64-v8a/libfoo.so`.
+pub fn read_library(path: &Path) -> Result<Vec<u8>, String> {
+    let path_string = path.to_string_lossy();
+    let (file, entries) = match split_archive_path(&path_string) {
+        Some((file, entries)) => (file, entries),
+        None => (path_string.as_ref(), Vec::new()),
+    };
+
+    let mut data =
+        std::fs::read(file).map_err(|error| format!("failed to read {:?}: {}", file, error))?;
+
+    for (index, name) in entries.iter().enumerate() {
+        let is_last = index == entries.len() - 1;
+        data = match find_entry(&data, name, is_last) {
+            Some(entry) => entry,
+            None => {
+                return Err(format!(
+                    "no {} in {:?}, its native libraries are:\n{}",
+                    name,
+                    path,
+                    get_library_entries(&data).join("\n")
+                ));
+            }
+        };
+    }
+
+    if is_archive(&data) {
+        return Err(format!(
+            "{:?} is an archive, select a library inside it with {}!/<entry>:\n{}",
+            path,
+            path.display(),
+            get_library_entries(&data).join("\n")
+        ));
+    }
+    Ok(data)
+}
+
+/// `app.apk!/lib/x86/libfoo.so` -> (`app.apk`, [`lib/x86/libfoo.so`]), with
+/// the archive being the first prefix that is a file so that `!` can appear
+/// in directory names.
+fn split_archive_path(path: &str) -> Option<(&str, Vec<&str>)> {
+    path.match_indices('!')
+        .map(|(index, _)| index)
+        .find(|index| Path::new(&path[..*index]).is_file())
+        .map(|index| {
+            let entries = path[index + 1..]
+                .split('!')
+                .map(|entry| entry.trim_start_matches('/'))
+                .collect();
+            (&path[..index], entries)
+        })
+}
+
+fn is_archive(data: &[u8]) -> bool {
+    read_u32(data, 0) == Some(LOCAL_HEADER_SIGNATURE)
+}
+
+/// The data of the entry `name`. When `search_nested` is set and the archive
+/// has no such entry, the APKs it holds are searched, like the
+/// `split_config.<abi>.apk` of a split APK bundle.
+fn find_entry(archive: &[u8], name: &str, search_nested: bool) -> Option<Vec<u8>> {
+    let entries = get_entries(archive)?;
+    if let Some(entry) = entries.iter().find(|entry| entry.name == name) {
+        return read_entry(archive, entry);
+    }
+    if !search_nested {
+        return None;
+    }
+
+    entries
+        .iter()
+        .filter(|entry| entry.name.ends_with(".apk"))
+        .filter_map(|entry| read_entry(archive, entry))
+        .find_map(|apk| find_entry(&apk, name, false))
+}
+
+/// The `.so` entries of an archive and of the APKs inside it, spelled the way
+/// `read_library` selects them.
+fn get_library_entries(archive: &[u8]) -> Vec<String> {
+    let Some(entries) = get_entries(archive) else {
+        return Vec::new();
+    };
+
+    let mut libraries = Vec::new();
+    for entry in &entries {
+        if entry.name.ends_with(".so") {
+            libraries.push(format!("    {}", entry.name));
+        } else if entry.name.ends_with(".apk")
+            && let Some(apk) = read_entry(archive, entry)
+        {
+            libraries.extend(
+                get_library_entries(&apk)
+                    .into_iter()
+                    .map(|library| format!("    {}!/{}", entry.name, library.trim_start())),
+            );
+        }
+    }
+    libraries
+}
+
+/// Reads the central directory, found through the end of central directory
+/// record at the end of the archive (before a comment of up to 64 KiB).
+fn get_entries(archive: &[u8]) -> Option<Vec<Entry>> {
+    let search_start = archive.len().saturating_sub(22 + 0xffff);
+    let end = (search_start..=archive.len().checked_sub(22)?)
+        .rev()
+        .find(|offset| read_u32(archive, *offset) == Some(END_OF_CENTRAL_DIRECTORY_SIGNATURE))?;
+
+    let count = read_u16(archive, end + 10)?;
+    let mut offset = read_u32(archive, end + 16)? as usize;
+    let mut entries = Vec::new();
+
+    for _ in 0..count {
+        if read_u32(archive, offset)? != CENTRAL_HEADER_SIGNATURE {
+            return None;
+        }
+        let name_length = read_u16(archive, offset + 28)? as usize;
+        let extra_length = read_u16(archive, offset + 30)? as usize;
+        let comment_length = read_u16(archive, offset + 32)? as usize;
+        let name = archive.get(offset + 46..offset + 46 + name_length)?;
+
+        entries.push(Entry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method: read_u16(archive, offset + 10)?,
+            compressed_size: read_u32(archive, offset + 20)?,
+            size: read_u32(archive, offset + 24)?,
+            header_offset: read_u32(archive, offset + 42)?,
+        });
+        offset += 46 + name_length + extra_length + comment_length;
+    }
+
+    Some(entries)
+}
+
+/// The uncompressed data of an entry. The sizes come from the central
+/// directory since local headers can leave them to a data descriptor.
+fn read_entry(archive: &[u8], entry: &Entry) -> Option<Vec<u8>> {
+    let header = entry.header_offset as usize;
+    if read_u32(archive, header)? != LOCAL_HEADER_SIGNATURE {
+        return None;
+    }
+    let name_length = read_u16(archive, header + 26)? as usize;
+    let extra_length = read_u16(archive, header + 28)? as usize;
+    let start = header + 30 + name_length + extra_length;
+    let data = archive.get(start..start + entry.compressed_size as usize)?;
+
+    match entry.method {
+        METHOD_STORED => Some(data.to_vec()),
+        METHOD_DEFLATED => {
+            miniz_oxide::inflate::decompress_to_vec_with_limit(data, entry.size as usize).ok()
+        }
+        _ => None,
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A ZIP archive of (name, method, data) entries, without checksums.
+    fn build_archive(files: &[(&str, u16, &[u8])]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        let mut central_directory = Vec::new();
+        for (name, method, data) in files {
+            let compressed = match *method {
+                METHOD_DEFLATED => miniz_oxide::deflate::compress_to_vec(data, 6),
+                _ => data.to_vec(),
+            };
+            let header_offset = archive.len() as u32;
+
+            archive.extend(LOCAL_HEADER_SIGNATURE.to_le_bytes());
+            archive.extend([20, 0, 0, 0]);
+            archive.extend(method.to_le_bytes());
+            archive.extend([0; 8]);
+            archive.extend((compressed.len() as u32).to_le_bytes());
+            archive.extend((data.len() as u32).to_le_bytes());
+            archive.extend((name.len() as u16).to_le_bytes());
+            archive.extend([0, 0]);
+            archive.extend(name.as_bytes());
+            archive.extend(&compressed);
+
+            central_directory.extend(CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+            central_directory.extend([20, 0, 20, 0, 0, 0]);
+            central_directory.extend(method.to_le_bytes());
+            central_directory.extend([0; 8]);
+            central_directory.extend((compressed.len() as u32).to_le_bytes());
+            central_directory.extend((data.len() as u32).to_le_bytes());
+            central_directory.extend((name.len() as u16).to_le_bytes());
+            central_directory.extend([0; 12]);
+            central_directory.extend(header_offset.to_le_bytes());
+            central_directory.extend(name.as_bytes());
+        }
+
+        let central_directory_offset = archive.len() as u32;
+        archive.extend(&central_directory);
+        archive.extend(END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+        archive.extend([0; 4]);
+        archive.extend((files.len() as u16).to_le_bytes());
+        archive.extend((files.len() as u16).to_le_bytes());
+        archive.extend((central_directory.len() as u32).to_le_bytes());
+        archive.extend(central_directory_offset.to_le_bytes());
+        archive.extend([0, 0]);
+        archive
+    }
+
+    #[test]
+    fn stored_and_deflated_entries() {
+        let library = b"\x7fELF library data library data library data".repeat(8);
+        let apk = build_archive(&[
+            ("AndroidManifest.xml", METHOD_STORED, b"manifest"),
+            ("lib/arm64-v8a/libfoo.so", METHOD_DEFLATED, &library),
+            ("lib/armeabi-v7a/libfoo.so", METHOD_STORED, &library),
+        ]);
+
+        assert!(is_archive(&apk));
+        let names: Vec<String> = get_entries(&apk)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "AndroidManifest.xml",
+                "lib/arm64-v8a/libfoo.so",
+                "lib/armeabi-v7a/libfoo.so"
+            ]
+        );
+        assert_eq!(
+            find_entry(&apk, "lib/arm64-v8a/libfoo.so", false),
+            Some(library.clone())
+        );
+        assert_eq!(
+            find_entry(&apk, "lib/armeabi-v7a/libfoo.so", false),
+            Some(library)
+        );
+        assert_eq!(find_entry(&apk, "lib/x86/libfoo.so", false), None);
+    }
+
+    #[test]
+    fn nested_apks_are_searched() {
+        let split = build_archive(&[("lib/arm64-v8a/libfoo.so", METHOD_DEFLATED, b"library")]);
+        let bundle = build_archive(&[
+            ("base.apk", METHOD_STORED, &build_archive(&[])),
+            ("split_config.arm64_v8a.apk", METHOD_DEFLATED, &split),
+        ]);
+
+        assert_eq!(
+            find_entry(&bundle, "lib/arm64-v8a/libfoo.so", true),
+            Some(b"library".to_vec())
+        );
+        assert_eq!(find_entry(&bundle, "lib/arm64-v8a/libfoo.so", false), None);
+        assert_eq!(
+            get_library_entries(&bundle),
+            ["    split_config.arm64_v8a.apk!/lib/arm64-v8a/libfoo.so"]
+        );
+    }
+
+    #[test]
+    fn truncated_archives() {
+        let apk = build_archive(&[("lib/arm64-v8a/libfoo.so", METHOD_STORED, b"library")]);
+
+        assert!(get_entries(&apk[..apk.len() - 1]).is_none());
+        assert!(get_entries(&apk[..10]).is_none());
+    }
+}
@@ -1,4 +1,4 @@
-use std::io::{Cursor, Read, Seek};
+use std::io::{Cursor, Read};
 
 pub struct BinReader<'a> {
     cursor: Cursor<&'a Vec<u8>>,
@@ -54,6 +54,25 @@ impl<'a> BinReader<'a> {
         }
     }
 
+    /// Reads a pointer sized word (4 or 8 bytes). Addresses in a shared
+    /// library fit in 32 bits, so 64-bit words are truncated.
+    pub fn read_word(&mut self, pointer_size: u32) -> Option<u32> {
+        self.read_signed_word(pointer_size).map(|word| word as u32)
+    }
+
+    /// Reads a pointer sized signed word (`ptrdiff_t`), such as an offset-to-top.
+    pub fn read_signed_word(&mut self, pointer_size: u32) -> Option<i32> {
+        if pointer_size == 4 {
+            return self.read_i32();
+        }
+
+        let mut buffer = [0u8; 8];
+        match self.cursor.read(&mut buffer) {
+            Ok(8) => Some(i64::from_le_bytes(buffer) as i32),
+            _ => None,
+        }
+    }
+
     pub fn read_u8(&mut self) -> Option<u8> {
         let mut buffer = [0u8; 1];
         match self.cursor.read(&mut buffer) {
@@ -73,10 +92,7 @@ impl<'a> BinReader<'a> {
 
         let return_offset = self.get_position() + 4;
 
-        let mut position_to_string = match self.read_u32() {
-            Some(offset) => offset,
-            None => return None,
-        };
+        let mut position_to_string = self.read_u32()?;
 
         if let Some(adjust_fn) = adjust {
             position_to_string = adjust_fn(position_to_string);
@@ -93,10 +109,7 @@ impl<'a> BinReader<'a> {
 
         self.set_position(return_offset as u32);
 
-        match String::from_utf8(buffer) {
-            Ok(str) => Some(str),
-            Err(_) => None,
-        }
+        String::from_utf8(buffer).ok()
     }
 
     pub fn get_data(&self) -> &'a Vec<u8> {
@@ -0,0 +1,377 @@
+use std::collections::HashMap;
+
+pub struct ClassIndex {
+    // normalized demangled class name -> (demangled class name, vtable symbol)
+    vtables: HashMap<String, (String, String)>,
+    // vtable symbol -> demangled class name
+    vtable_classes: HashMap<String, String>,
+    // (start address, end address, vtable symbol), sorted by start address
+    vtable_ranges: Vec<(u32, u32, String)>,
+    // normalized demangled class name -> (demangled class name, typeinfo symbol)
+    typeinfos: HashMap<String, (String, String)>,
+}
+
+impl ClassIndex {
+    pub fn new(sym_to_addr: &HashMap<String, u32>, sym_to_size: &HashMap<String, u32>) -> Self {
+        let mut vtables = HashMap::new();
+        let mut vtable_classes = HashMap::new();
+        let mut vtable_ranges = Vec::new();
+        let mut typeinfos = HashMap::new();
+
+        for symbol in sym_to_addr
+            .keys()
+            .filter(|symbol| symbol.starts_with("_ZTI"))
+        {
+            if let Some(class_name) = get_class_name_from_symbol(symbol) {
+                typeinfos.insert(
+                    normalize_class_name(&class_name),
+                    (class_name, symbol.to_owned()),
+                );
+            }
+        }
+
+        // vtables imported from a library that is not loaded are left at 0
+        for (symbol, start) in sym_to_addr
+            .iter()
+            .filter(|(symbol, addr)| symbol.starts_with("_ZTV") && **addr != 0)
+        {
+            let Some(class_name) = get_class_name_from_symbol(symbol) else {
+                continue;
+            };
+
+            let start = *start;
+            vtable_ranges.push((start, start + sym_to_size[symbol], symbol.to_owned()));
+            vtable_classes.insert(symbol.to_owned(), class_name.clone());
+            vtables.insert(
+                normalize_class_name(&class_name),
+                (class_name, symbol.to_owned()),
+            );
+        }
+
+        vtable_ranges.sort();
+
+        Self {
+            vtables,
+            vtable_classes,
+            vtable_ranges,
+            typeinfos,
+        }
+    }
+
+    /// Returns the demangled name of the class as the library spells it and
+    /// the symbol of its vtable.
+    pub fn get_vtable(&self, class_name: &str) -> Option<(&String, &String)> {
+        self.vtables
+            .get(&normalize_class_name(class_name))
+            .map(|(name, symbol)| (name, symbol))
+    }
+
+    /// Resolves a class selector, which is either a class name, a `_ZTV`,
+    /// `_ZTI` or `_ZTS` symbol, or the address of a vtable (any of its groups)
+    /// or of a typeinfo.
+    pub fn resolve(
+        &self,
+        selector: &str,
+        addr_to_sym: &HashMap<u32, String>,
+    ) -> Option<(&String, &String)> {
+        if let Some(hex) = selector
+            .strip_prefix("0x")
+            .or_else(|| selector.strip_prefix("0X"))
+        {
+            let address = u32::from_str_radix(hex, 16).ok()?;
+            return self.get_vtable_by_address(address, addr_to_sym);
+        }
+
+        if let Some(symbol) = self.get_vtable_symbol(selector) {
+            return self
+                .vtable_classes
+                .get_key_value(&symbol)
+                .map(|(s, n)| (n, s));
+        }
+
+        self.get_vtable(selector)
+    }
+
+    fn get_vtable_by_address(
+        &self,
+        address: u32,
+        addr_to_sym: &HashMap<u32, String>,
+    ) -> Option<(&String, &String)> {
+        if let Some(symbol) = addr_to_sym
+            .get(&address)
+            .and_then(|symbol| self.get_vtable_symbol(symbol))
+        {
+            return self
+                .vtable_classes
+                .get_key_value(&symbol)
+                .map(|(s, n)| (n, s));
+        }
+
+        // an address point or a secondary vtable group lands in the middle of
+        // the _ZTV symbol.
+        let symbol = self.get_vtable_symbol_containing(address)?;
+        self.vtable_classes
+            .get_key_value(symbol)
+            .map(|(s, n)| (n, s))
+    }
+
+    pub fn is_in_vtable_symbol(&self, address: u32) -> bool {
+        self.get_vtable_symbol_containing(address).is_some()
+    }
+
+    fn get_vtable_symbol_containing(&self, address: u32) -> Option<&String> {
+        let index = self
+            .vtable_ranges
+            .partition_point(|(start, _, _)| *start <= address);
+        let (_, end, symbol) = self.vtable_ranges.get(index.checked_sub(1)?)?;
+
+        (address < *end).then_some(symbol)
+    }
+
+    /// Every class with a vtable, as (demangled class name, vtable symbol),
+    /// sorted by name.
+    pub fn get_classes(&self) -> Vec<(&String, &String)> {
+        let mut classes: Vec<(&String, &String)> = self
+            .vtables
+            .values()
+            .map(|(name, symbol)| (name, symbol))
+            .collect();
+        classes.sort();
+        classes
+    }
+
+    /// Every class whose demangled name satisfies `matcher`, sorted by name.
+    pub fn find_classes(&self, matcher: impl Fn(&str) -> bool) -> Vec<(&String, &String)> {
+        self.get_classes()
+            .into_iter()
+            .filter(|(name, _)| matcher(name) || matcher(&normalize_class_name(name)))
+            .collect()
+    }
+
+    /// Explains why `selector` could not be resolved, listing the closest
+    /// class names.
+    pub fn get_unknown_class_message(&self, selector: &str) -> String {
+        let mut lines = vec![format!("unknown class: {:?}", selector)];
+        let normalized = normalize_class_name(selector);
+
+        if let Some((class_name, typeinfo_symbol)) = self.typeinfos.get(&normalized)
+            && !self.vtables.contains_key(&normalized)
+        {
+            lines.push(format!(
+                "{} only has a typeinfo ({}), no vtable symbol was found for it",
+                class_name, typeinfo_symbol
+            ));
+            return lines.join("\n");
+        }
+
+        let suggestions = self.get_suggestions(selector);
+
+        if !suggestions.is_empty() {
+            lines.push("did you mean:".to_string());
+            for suggestion in suggestions {
+                let normalized = normalize_class_name(suggestion);
+                if self.vtables.contains_key(&normalized) {
+                    lines.push(format!("    {}", suggestion));
+                } else {
+                    lines.push(format!("    {} (typeinfo only)", suggestion));
+                }
+            }
+        }
+
+        lines.join("\n")
+    }
+
+    /// Class names (with a vtable or only a typeinfo) that are either the
+    /// selector in another namespace, or close to it by edit distance.
+    fn get_suggestions(&self, selector: &str) -> Vec<&String> {
+        const MAX_SUGGESTIONS: usize = 5;
+
+        let normalized = normalize_class_name(selector);
+        let unqualified = normalize_class_name(split_scopes(selector).pop().unwrap());
+        let max_distance = (normalized.len() / 4).max(2);
+
+        let mut candidates: Vec<(usize, &String)> = Vec::new();
+
+        for (key, (class_name, _)) in self.vtables.iter().chain(
+            self.typeinfos
+                .iter()
+                .filter(|(key, _)| !self.vtables.contains_key(*key)),
+        ) {
+            let scopes = split_scopes(key);
+
+            let last_scope = scopes.last().unwrap().to_lowercase();
+
+            // `CCNode` -> `cocos2d::CCNode`, `TouchDelegate` -> `cocos2d::CCTouchDelegate`
+            let distance = if last_scope == unqualified.to_lowercase() {
+                0
+            } else if last_scope.ends_with(&unqualified.to_lowercase()) {
+                1
+            } else {
+                get_edit_distance(&normalized.to_lowercase(), &key.to_lowercase())
+            };
+
+            if distance <= max_distance {
+                candidates.push((distance, class_name));
+            }
+        }
+
+        candidates.sort();
+        candidates
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, class_name)| class_name)
+            .collect()
+    }
+
+    // `_ZTI`/`_ZTS` share the mangled type with the class's `_ZTV`
+    fn get_vtable_symbol(&self, symbol: &str) -> Option<String> {
+        let mangled_type = symbol
+            .strip_prefix("_ZTV")
+            .or_else(|| symbol.strip_prefix("_ZTI"))
+            .or_else(|| symbol.strip_prefix("_ZTS"))?;
+        let vtable_symbol = format!("_ZTV{}", mangled_type);
+
+        self.vtable_classes
+            .contains_key(&vtable_symbol)
+            .then_some(vtable_symbol)
+    }
+}
+
+pub fn demangle(symbol: &str) -> Option<String> {
+    if let Some(symbol) = symbol.strip_suffix("@plt") {
+        return demangle(symbol).map(|demangled| format!("{}@plt", demangled));
+    }
+    cpp_demangle::Symbol::new(symbol).ok()?.demangle().ok()
+}
+
+/// The function a `this` adjusting thunk jumps to, e.g.
+/// `_ZThn256_N7cocos2d7CCLayerD1Ev` -> `_ZN7cocos2d7CCLayerD1Ev`.
+pub fn get_thunk_target(symbol: &str) -> Option<String> {
+    // <call-offset> ::= h <nv-offset> _ | v <v-offset> _ <virtual offset> _
+    fn skip_number(mangled: &str) -> Option<&str> {
+        let mangled = mangled.strip_prefix('n').unwrap_or(mangled);
+        let digits = mangled.find(|c: char| !c.is_ascii_digit())?;
+        (digits > 0).then(|| &mangled[digits..])?.strip_prefix('_')
+    }
+
+    fn skip_call_offset(mangled: &str) -> Option<&str> {
+        if let Some(rest) = mangled.strip_prefix('h') {
+            skip_number(rest)
+        } else {
+            skip_number(skip_number(mangled.strip_prefix('v')?)?)
+        }
+    }
+
+    let mangled = symbol.strip_prefix("_ZT")?;
+    let encoding = match mangled.strip_prefix('c') {
+        // covariant return thunks have a call-offset for `this` and one for the result
+        Some(covariant) => skip_call_offset(skip_call_offset(covariant)?)?,
+        None => skip_call_offset(mangled)?,
+    };
+
+    Some(format!("_Z{}", encoding))
+}
+
+/// Extracts the class name out of a `_ZTV`, `_ZTI` or `_ZTS` symbol.
+pub fn get_class_name_from_symbol(symbol: &str) -> Option<String> {
+    let demangled = demangle(symbol)?;
+
+    if let Some(vtable) = demangled.strip_prefix("{vtable(") {
+        vtable.strip_suffix(")}").map(str::to_string)
+    } else if let Some(typeinfo_name) = demangled.strip_prefix("typeinfo name for ") {
+        Some(typeinfo_name.to_string())
+    } else {
+        demangled.strip_prefix("typeinfo for ").map(str::to_string)
+    }
+}
+
+// the demangler prints `std::vector<int, std::allocator<int> >` while users
+// will usually type `std::vector<int, std::allocator<int>>`.
+pub fn normalize_class_name(class_name: &str) -> String {
+    class_name.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+/// Splits a qualified name on the `::` that are not part of a template
+/// argument list or of `(anonymous namespace)`.
+pub fn split_scopes(class_name: &str) -> Vec<&str> {
+    let mut scopes = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let bytes = class_name.as_bytes();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'<' | b'(' => depth += 1,
+            b'>' | b')' => depth -= 1,
+            b':' if depth == 0 && bytes.get(i + 1) == Some(&b':') => {
+                scopes.push(&class_name[start..i]);
+                start = i + 2;
+                i += 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    scopes.push(&class_name[start..]);
+    scopes
+}
+
+/// `cocos2d::CCArray` -> `CCArray`, `std::vector<int>` -> `vector`
+pub fn get_unqualified_name(class_name: &str) -> &str {
+    let name = split_scopes(class_name).pop().unwrap();
+    match name.find('<') {
+        Some(template_start) => &name[..template_start],
+        None => name,
+    }
+}
+
+fn get_edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + (ca != *cb) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+pub fn is_glob(selector: &str) -> bool {
+    selector.contains(['*', '?'])
+}
+
+/// Matches `*` (any run of characters, including `::`) and `?` (any single
+/// character) against the whole of `text`.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
@@ -0,0 +1,332 @@
+use crate::class_index::{self, demangle};
+use crate::fields::Field;
+use crate::library::Library;
+use crate::returns::{ReturnType, ReturnTypes};
+use crate::trivial::{self, Implementation};
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Serialize)]
+pub struct DumpVtableJSONOutput {
+    name: String,
+    address: u32,
+    offset: u32,
+    implementation: Implementation,
+    return_type: ReturnType,
+}
+
+pub fn get_inheritance(library: &Library, vtable_symbol: &str, size: Option<u32>) -> String {
+    let display = library
+        .get_inheritance(library.sym_to_addr[vtable_symbol])
+        .get_display();
+    let mut lines: Vec<String> = display.lines().map(String::from).collect();
+
+    // on the line of the class itself, its bases follow
+    if let (Some(size), Some(class_line)) = (size, lines.first_mut()) {
+        class_line.push_str(&format!(" [sizeof=0x{:x}]", size));
+    }
+    lines.join("\n")
+}
+
+/// The slots of the primary vtable. The document stays a bare array for the
+/// scripts reading it, the size of the class is in `classes --format json`.
+pub fn dump_vtable_json(
+    library: &Library,
+    vtable_symbol: &str,
+    return_types: &ReturnTypes,
+) -> Vec<DumpVtableJSONOutput> {
+    let dump = library.get_class_vtable(library.sym_to_addr[vtable_symbol]);
+
+    dump[0]
+        .1
+        .iter()
+        .enumerate()
+        .map(|(i, addr)| DumpVtableJSONOutput {
+            name: library.get_symbol_name(*addr),
+            address: *addr,
+            offset: library.pointer_size * (2 + i as u32),
+            implementation: trivial::classify_implementation(library, *addr),
+            return_type: return_types.get(library, *addr),
+        })
+        .collect()
+}
+
+pub fn create_vtable_cpp(
+    library: &Library,
+    class_name: &str,
+    vtable_symbol: &str,
+    size: Option<u32>,
+    fields: &[Field],
+    return_types: &ReturnTypes,
+) -> String {
+    let mut lines: Vec<String> = Vec::new();
+
+    if let Some(size) = size {
+        lines.push(format!("// sizeof({}) == 0x{:x}", class_name, size));
+    }
+    let dump = library.get_class_vtable(library.sym_to_addr[vtable_symbol]);
+    let inherit_info = library.get_inheritance(library.sym_to_addr[vtable_symbol]);
+
+    if !inherit_info.base.is_empty() {
+        let base = inherit_info
+            .base
+            .iter()
+            .map(|class| {
+                format!(
+                    "public {}",
+                    demangle(&class.name).unwrap_or(class.name.clone())
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        lines.push(format!("class {} : {} {{", class_name, base));
+    } else {
+        lines.push(format!("class {} {{", class_name));
+    }
+
+    lines.push("public:".to_string());
+
+    let mut declarations: Vec<(String, u32, ReturnType)> = Vec::new();
+    let mut virtual_dtor: Option<u32> = None;
+
+    for addr in &dump[0].1 {
+        let symbol = library.get_symbol_name(*addr);
+        let demangled = demangle(&symbol).unwrap_or(symbol.clone());
+
+        if symbol.ends_with("D0Ev") || symbol.ends_with("D1Ev") {
+            // the complete object destructor runs the body, the deleting one
+            // only adds operator delete
+            if virtual_dtor.is_none() || symbol.ends_with("D1Ev") {
+                virtual_dtor = Some(*addr);
+            }
+        } else if let Some(method) = demangled.strip_prefix(&format!("{}::", class_name)) {
+            let return_type = return_types.get(library, *addr);
+            declarations.push((
+                format!("{} {}", return_type.type_name, method),
+                *addr,
+                return_type,
+            ));
+        }
+    }
+
+    if let Some(addr) = virtual_dtor {
+        lines.push(add_comments(
+            format!(
+                "    virtual ~{}();",
+                class_index::get_unqualified_name(class_name)
+            ),
+            library,
+            addr,
+            None,
+        ));
+    }
+
+    for (declaration, addr, return_type) in declarations {
+        lines.push(add_comments(
+            format!("    virtual {};", declaration),
+            library,
+            addr,
+            Some(&return_type),
+        ));
+    }
+
+    // the vptrs are implicit, they only take up their space
+    let mut members: Vec<(i32, i32, Option<String>)> = dump
+        .iter()
+        .map(|(offset_to_top, _)| (offset_to_top.abs(), library.pointer_size as i32, None))
+        .collect();
+    members.extend(get_field_members(fields));
+
+    let members = get_padded_members(members, size);
+    if !members.is_empty() {
+        lines.push(String::new());
+        lines.push("protected:".to_string());
+        for (offset, declaration) in members {
+            lines.push(format!("    {}; // 0x{:x}", declaration, offset));
+        }
+    }
+
+    lines.push("};".to_string());
+    lines.join("\n")
+}
+
+pub fn create_vtable_ida(
+    library: &Library,
+    class_name: &str,
+    vtable_symbol: &str,
+    size: Option<u32>,
+    fields: &[Field],
+    return_types: &ReturnTypes,
+) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    // (offset, size, declaration) of the vptrs and the known data members
+    let mut members: Vec<(i32, i32, Option<String>)> = Vec::new();
+
+    for table in library.get_class_vtable(library.sym_to_addr[vtable_symbol]) {
+        let offset_to_this = table.0.abs();
+        let vft_struct_name = format!("{}_{}_vft", class_name, offset_to_this);
+
+        members.push((
+            offset_to_this,
+            library.pointer_size as i32,
+            Some(format!("{}* __vtable_{}", vft_struct_name, offset_to_this)),
+        ));
+
+        let mut function_name_counter: HashMap<String, u32> = HashMap::new();
+
+        lines.push(format!("struct {} {{", vft_struct_name));
+
+        table.1.iter().for_each(|addr| {
+            let symbol = library.get_symbol_name(*addr);
+
+            if symbol.ends_with("D1Ev") {
+                lines.push(add_comments(
+                    format!("    void (*__dtor)({}*);", class_name),
+                    library,
+                    *addr,
+                    None,
+                ));
+            } else if symbol.ends_with("D0Ev") {
+                lines.push(add_comments(
+                    format!("    void (*__delete)({}*);", class_name),
+                    library,
+                    *addr,
+                    None,
+                ));
+            } else {
+                // thunks (covariant ones included) are named after their
+                // target, PLT stubs after the imported function
+                let symbol = symbol.strip_suffix("@plt").unwrap_or(&symbol);
+                let target = class_index::get_thunk_target(symbol).unwrap_or(symbol.to_string());
+                let demangled = demangle(&target).unwrap_or(target);
+
+                // unmangled names such as __cxa_pure_virtual have no argument list
+                let start_of_args = demangled.find("(").unwrap_or(demangled.len());
+
+                let mut name = demangled[0..start_of_args]
+                    .split("::")
+                    .last()
+                    .unwrap()
+                    .to_string();
+
+                if function_name_counter.contains_key(&name) {
+                    function_name_counter.insert(name.clone(), function_name_counter[&name] + 1);
+                    name = format!("{}_{}", name, function_name_counter[&name]);
+                } else {
+                    function_name_counter.insert(name.to_owned(), 1);
+                }
+
+                let mut sig = demangled[start_of_args..].to_string();
+
+                if sig.is_empty() || sig.starts_with("()") {
+                    sig = format!("({}*){}", class_name, sig.get(2..).unwrap_or_default());
+                } else {
+                    sig = format!("({}*, {}", class_name, &sig[1..]);
+                }
+
+                if sig.ends_with("const") {
+                    sig = sig[0..sig.len() - 5].to_string();
+                    sig = sig.trim_end().to_string();
+                }
+
+                let return_type = return_types.get(library, *addr);
+                lines.push(add_comments(
+                    format!("    {} (*{}){};", return_type.type_name, name, sig),
+                    library,
+                    *addr,
+                    Some(&return_type),
+                ));
+            }
+        });
+
+        lines.push("};".to_string());
+    }
+
+    members.extend(get_field_members(fields));
+
+    lines.push(format!("struct {} {{", class_name));
+    for (_, declaration) in get_padded_members(members, size) {
+        lines.push(format!("    {};", declaration));
+    }
+    lines.push("};".to_string());
+    lines.join("\n")
+}
+
+fn get_field_members(fields: &[Field]) -> impl Iterator<Item = (i32, i32, Option<String>)> + '_ {
+    fields.iter().map(|field| {
+        (
+            field.offset as i32,
+            field.size as i32,
+            Some(format!("{} {}", field.type_name, field.name)),
+        )
+    })
+}
+
+/// Lays out (offset, size, declaration) members in order, with `char
+/// fill_N[]` arrays in the gaps and up to the size passed to operator new.
+/// Members overlapping an earlier one are skipped, vptrs come first when a
+/// field overlaps one; members without a declaration only take up space.
+fn get_padded_members(
+    mut members: Vec<(i32, i32, Option<String>)>,
+    size: Option<u32>,
+) -> Vec<(i32, String)> {
+    members.sort_by_key(|(offset, _, _)| *offset);
+
+    let mut padded_members: Vec<(i32, String)> = Vec::new();
+    let mut fields_end = 0;
+    let mut filler_counter = 0;
+
+    let mut add_filler = |padded_members: &mut Vec<(i32, String)>, start: i32, end: i32| {
+        padded_members.push((
+            start,
+            format!("char fill_{}[{}]", filler_counter, end - start),
+        ));
+        filler_counter += 1;
+    };
+
+    for (offset, member_size, declaration) in members {
+        if offset < fields_end {
+            continue;
+        }
+        if offset > fields_end {
+            add_filler(&mut padded_members, fields_end, offset);
+        }
+        if let Some(declaration) = declaration {
+            padded_members.push((offset, declaration));
+        }
+        fields_end = offset + member_size;
+    }
+
+    if let Some(size) = size
+        && size as i32 > fields_end
+    {
+        add_filler(&mut padded_members, fields_end, size as i32);
+    }
+
+    padded_members
+}
+
+/// Appends what a trivial slot target does and how sure the guessed return
+/// type is as a comment, e.g. `virtual bool init(); // returns 1, medium
+/// confidence return type`.
+fn add_comments(
+    line: String,
+    library: &Library,
+    addr: u32,
+    return_type: Option<&ReturnType>,
+) -> String {
+    let comments: Vec<String> = trivial::classify_implementation(library, addr)
+        .get_comment()
+        .into_iter()
+        .chain(
+            return_type
+                .map(|return_type| format!("{} confidence return type", return_type.confidence)),
+        )
+        .collect();
+
+    if comments.is_empty() {
+        line
+    } else {
+        format!("{} // {}", line, comments.join(", "))
+    }
+}
@@ -0,0 +1,168 @@
+use crate::class_index::{demangle, split_scopes};
+use crate::library::Library;
+use crate::rtti::TypeInfoKind;
+use crate::sizes::ClassSizes;
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct ClassSummary {
+    pub name: String,
+    pub vtable_address: u32,
+    /// `None` for vtables found by `Library::discover_vtables`
+    pub vtable_symbol: Option<String>,
+    pub typeinfo_kind: TypeInfoKind,
+    pub bases: Vec<String>,
+    pub vtable_groups: usize,
+    /// slots across every vtable group
+    pub slots: usize,
+    pub is_abstract: bool,
+    /// `sizeof` inferred from the class's `create()` helpers
+    pub size: Option<u32>,
+}
+
+pub fn get_class_summaries(library: &Library, sizes: &ClassSizes) -> Vec<ClassSummary> {
+    let vtables = library.get_vtables();
+
+    // libc++_shared.so defines __cxa_pure_virtual, the slots of a library
+    // loaded without it point at its PLT stub or import it
+    let pure_virtual = library.get_function_addresses("__cxa_pure_virtual");
+    let is_pure_virtual = |slot_address: u32, function: u32| {
+        pure_virtual.contains(&(function & !1))
+            || library
+                .unresolved_imports
+                .get(&slot_address)
+                .is_some_and(|import| import == "__cxa_pure_virtual")
+    };
+
+    let mut summaries: Vec<ClassSummary> = vtables
+        .into_iter()
+        .filter(|(vtable_address, _)| {
+            let typeinfo = library.get_vtable_typeinfo(*vtable_address);
+            let is_local = library.is_data_rel_ro_address(typeinfo);
+            if !is_local {
+                eprintln!(
+                    "skipping vtable at 0x{:x}: its typeinfo (0x{:x}) is not in .data.rel.ro",
+                    vtable_address, typeinfo
+                );
+            }
+            is_local
+        })
+        .map(|(vtable_address, vtable_symbol)| {
+            let inherit_info = library.get_inheritance(vtable_address);
+            let groups = library.get_vtable_groups(vtable_address);
+            let name = demangle(&inherit_info.name).unwrap_or(inherit_info.name.clone());
+
+            ClassSummary {
+                size: sizes.get_size(&name),
+                name,
+                vtable_address,
+                vtable_symbol,
+                typeinfo_kind: library
+                    .get_typeinfo_kind(library.get_vtable_typeinfo(vtable_address)),
+                bases: inherit_info
+                    .base
+                    .iter()
+                    .map(|base| demangle(&base.name).unwrap_or(base.name.clone()))
+                    .collect(),
+                vtable_groups: groups.len(),
+                slots: groups.iter().map(|(_, _, slots)| slots.len()).sum(),
+                is_abstract: groups.iter().any(|(group_address, _, slots)| {
+                    slots.iter().enumerate().any(|(index, function)| {
+                        let slot_address =
+                            group_address + (2 + index as u32) * library.pointer_size;
+                        is_pure_virtual(slot_address, *function)
+                    })
+                }),
+            }
+        })
+        .collect();
+
+    summaries.sort_by(|a, b| a.name.cmp(&b.name));
+    summaries
+}
+
+/// Whether `class_name` is declared in `namespace` or in one of the
+/// namespaces nested in it. An empty namespace (or `::`) selects the global
+/// namespace only.
+pub fn is_in_namespace(class_name: &str, namespace: &str) -> bool {
+    let mut class_scopes = split_scopes(class_name);
+    class_scopes.pop();
+
+    let namespace = namespace.trim_start_matches("::");
+    if namespace.is_empty() {
+        return class_scopes.is_empty();
+    }
+
+    let namespace_scopes = split_scopes(namespace);
+    class_scopes.starts_with(&namespace_scopes)
+}
+
+pub fn get_text(summaries: &[ClassSummary]) -> String {
+    let name_width = summaries
+        .iter()
+        .map(|summary| summary.name.len())
+        .max()
+        .unwrap_or(0)
+        .max("CLASS".len());
+
+    let mut lines = vec![format!(
+        "{:<name_width$}  {:<10}  {:>6}  {:>5}  {:>6}  {:<8}  BASES",
+        "CLASS", "TYPEINFO", "GROUPS", "SLOTS", "SIZE", "ABSTRACT"
+    )];
+
+    for summary in summaries {
+        lines.push(format!(
+            "{:<name_width$}  {:<10}  {:>6}  {:>5}  {:>6}  {:<8}  {}",
+            summary.name,
+            summary.typeinfo_kind.get_name(),
+            summary.vtable_groups,
+            summary.slots,
+            summary
+                .size
+                .map(|size| format!("0x{:x}", size))
+                .unwrap_or("?".to_string()),
+            if summary.is_abstract { "yes" } else { "no" },
+            summary.bases.join(", ")
+        ));
+    }
+
+    lines.join("\n")
+}
+
+pub fn get_csv(summaries: &[ClassSummary]) -> String {
+    let mut lines = vec![
+        "name,vtable_address,vtable_symbol,typeinfo_kind,bases,vtable_groups,slots,is_abstract,size"
+            .to_string(),
+    ];
+
+    for summary in summaries {
+        lines.push(
+            [
+                escape_csv(&summary.name),
+                format!("0x{:x}", summary.vtable_address),
+                escape_csv(summary.vtable_symbol.as_deref().unwrap_or_default()),
+                summary.typeinfo_kind.get_name().to_string(),
+                escape_csv(&summary.bases.join(";")),
+                summary.vtable_groups.to_string(),
+                summary.slots.to_string(),
+                summary.is_abstract.to_string(),
+                summary
+                    .size
+                    .map(|size| size.to_string())
+                    .unwrap_or_default(),
+            ]
+            .join(","),
+        );
+    }
+
+    lines.join("\n")
+}
+
+// template arguments are separated by commas
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
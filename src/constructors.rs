@@ -0,0 +1,367 @@
+use crate::class_index::{demangle, get_unqualified_name, normalize_class_name};
+use crate::disasm::{self, InstructionSet, Operation, RegisterState};
+use crate::hierarchy::Hierarchy;
+use crate::library::Library;
+use crate::overrides::split_method;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+// large constructors inline the construction of their members, keep going
+// well past the first vptr stores
+const MAX_FUNCTION_SIZE: u32 = 0x2000;
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Constructor,
+    Destructor,
+    /// an object constructed inside another function, usually a `create()`
+    /// helper that inlines the constructor after calling operator new
+    InlinedConstruction,
+}
+
+/// The object a vptr is stored into.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Object {
+    /// the first argument of the function
+    This,
+    /// the result of the operator new call at this address
+    Allocation(u32),
+    Unknown,
+}
+
+#[derive(Serialize)]
+pub struct VptrStore {
+    /// the store instruction
+    pub address: u32,
+    /// offset of the vptr from the start of the object, when the register
+    /// holding the object could be followed
+    pub object_offset: Option<i64>,
+    pub class_name: String,
+    pub vtable_address: u32,
+    pub address_point: u32,
+    pub offset_to_top: i32,
+}
+
+#[derive(Serialize)]
+pub struct VptrWriter {
+    pub function: u32,
+    pub symbol: String,
+    /// `X::X` / `X::~X` for functions without a symbol
+    pub suggested_name: Option<String>,
+    pub role: Role,
+    pub class_name: String,
+    pub object: Object,
+    pub stores: Vec<VptrStore>,
+}
+
+struct AddressPoint {
+    class_name: String,
+    vtable_address: u32,
+    offset_to_top: i32,
+}
+
+/// Finds every function storing the address point of a vtable into an
+/// object: constructors and destructors (which reset the vptr before running
+/// the base destructors), and the constructions inlined into other
+/// functions.
+pub fn find_vptr_writers(library: &Library, hierarchy: &Hierarchy) -> Vec<VptrWriter> {
+    let mut address_points: HashMap<u32, AddressPoint> = HashMap::new();
+    // function -> classes whose vtables reference it
+    let mut vtable_functions: HashMap<u32, HashSet<String>> = HashMap::new();
+
+    for (vtable_address, _) in library.get_vtables() {
+        let class_name = hierarchy.get_class_name(library.get_vtable_typeinfo(vtable_address));
+        for (group_address, offset_to_top, functions) in library.get_vtable_groups(vtable_address) {
+            address_points.insert(
+                group_address + 2 * library.pointer_size,
+                AddressPoint {
+                    class_name: class_name.clone(),
+                    vtable_address,
+                    offset_to_top,
+                },
+            );
+
+            for function in functions {
+                vtable_functions
+                    .entry(function)
+                    .or_default()
+                    .insert(class_name.clone());
+            }
+        }
+    }
+
+    let operator_new: HashSet<u32> = ["_Znwj", "_Znwm"]
+        .iter()
+        .flat_map(|symbol| library.get_function_addresses(symbol))
+        .collect();
+
+    // every function with a symbol or an unwind table entry, plus the ones
+    // without either that are called by them or referenced by a vtable
+    // (stripped constructors and destructors)
+    let mut pending: Vec<u32> = library
+        .addr_to_sym
+        .keys()
+        .chain(vtable_functions.keys())
+        .copied()
+        .chain(library.get_unwind_functions())
+        .filter(|addr| *addr != 0 && library.is_executable_address(*addr))
+        .collect();
+    pending.sort();
+    let mut visited: HashSet<u32> = HashSet::new();
+    let mut writers = Vec::new();
+
+    while let Some(function) = pending.pop() {
+        if !visited.insert(function) {
+            continue;
+        }
+
+        let (stores, callees) = scan_function(library, function, &address_points, &operator_new);
+        for callee in callees {
+            if !library.addr_to_sym.contains_key(&callee)
+                && library.is_executable_address(callee)
+                && !library.plt_symbols.contains_key(&(callee & !1))
+            {
+                pending.push(callee);
+            }
+        }
+
+        for (object, stores) in stores {
+            writers.push(get_writer(
+                library,
+                function,
+                object,
+                stores,
+                &vtable_functions,
+            ));
+        }
+    }
+
+    writers.sort_by(|a, b| {
+        (&a.class_name, a.role as u8, a.function, a.object).cmp(&(
+            &b.class_name,
+            b.role as u8,
+            b.function,
+            b.object,
+        ))
+    });
+    writers
+}
+
+/// The vptr stores of a function grouped by object, and its direct callees.
+fn scan_function(
+    library: &Library,
+    function: u32,
+    address_points: &HashMap<u32, AddressPoint>,
+    operator_new: &HashSet<u32>,
+) -> (BTreeMap<Object, Vec<VptrStore>>, Vec<u32>) {
+    let set = InstructionSet::of(library, function);
+    let mut state = RegisterState::new(set);
+    // the object (and offset into it) each register points at
+    let mut objects: [Option<(Object, i64)>; 32] = [None; 32];
+    objects[0] = Some((Object::This, 0));
+
+    let mut stores: BTreeMap<Object, Vec<VptrStore>> = BTreeMap::new();
+    let mut callees = Vec::new();
+
+    for instruction in disasm::decode_function(library, function, MAX_FUNCTION_SIZE) {
+        if let Operation::Store { rt, rn, offset, .. } = instruction.operation
+            && let Some(value) = state.get(rt)
+            && let Some(address_point) = address_points.get(&(value as u32))
+        {
+            let target = objects[rn as usize].map(|(object, base)| (object, base + offset));
+            stores
+                .entry(target.map_or(Object::Unknown, |(object, _)| object))
+                .or_default()
+                .push(VptrStore {
+                    address: instruction.address,
+                    object_offset: target.map(|(_, offset)| offset),
+                    class_name: address_point.class_name.clone(),
+                    vtable_address: address_point.vtable_address,
+                    address_point: value as u32,
+                    offset_to_top: address_point.offset_to_top,
+                });
+        }
+
+        match instruction.operation {
+            Operation::MoveRegister { rd, rm } => {
+                objects[rd as usize] = objects[rm as usize].filter(|_| !instruction.conditional);
+            }
+            Operation::AddImmediate { rd, rn, imm } => {
+                objects[rd as usize] = objects[rn as usize]
+                    .filter(|_| !instruction.conditional)
+                    .map(|(object, offset)| (object, offset + imm));
+            }
+            Operation::Branch { target, link: true } => {
+                callees.push(target);
+                clear_caller_saved(&mut objects, set);
+                if operator_new.contains(&(target & !1)) {
+                    objects[0] = Some((Object::Allocation(instruction.address), 0));
+                }
+            }
+            Operation::BranchRegister { link: true, .. } => {
+                clear_caller_saved(&mut objects, set);
+            }
+            _ => {
+                if let Some(register) = disasm::get_written_register(library, &instruction, set)
+                    && let Some(slot) = objects.get_mut(register as usize)
+                {
+                    *slot = None;
+                }
+            }
+        }
+
+        state.step(library, &instruction);
+    }
+
+    (stores, callees)
+}
+
+fn clear_caller_saved(objects: &mut [Option<(Object, i64)>; 32], set: InstructionSet) {
+    for register in 0..32u8 {
+        if set.is_caller_saved(register) {
+            objects[register as usize] = None;
+        }
+    }
+}
+
+fn get_writer(
+    library: &Library,
+    function: u32,
+    object: Object,
+    stores: Vec<VptrStore>,
+    vtable_functions: &HashMap<u32, HashSet<String>>,
+) -> VptrWriter {
+    let symbol = library.get_symbol_name(function);
+    let has_symbol = library.addr_to_sym.contains_key(&function);
+
+    // the vptr of the whole object; a constructor stores the most derived
+    // one last, a destructor first
+    let primary_stores: Vec<&VptrStore> = stores
+        .iter()
+        .filter(|store| store.offset_to_top == 0 && store.object_offset.unwrap_or(0) == 0)
+        .collect();
+    let first_class = primary_stores
+        .first()
+        .map_or(&stores[0].class_name, |store| &store.class_name);
+    let last_class = primary_stores
+        .last()
+        .map_or(&stores[0].class_name, |store| &store.class_name);
+
+    let role = if matches!(object, Object::Allocation(_)) {
+        Role::InlinedConstruction
+    } else if has_symbol {
+        match demangle(&symbol).as_deref().and_then(split_method) {
+            Some((_, signature)) if signature.starts_with('~') => Role::Destructor,
+            Some((class_name, signature))
+                if signature.starts_with(&format!("{}(", get_constructor_name(&class_name))) =>
+            {
+                Role::Constructor
+            }
+            _ => Role::InlinedConstruction,
+        }
+    } else if vtable_functions
+        .get(&function)
+        .is_some_and(|classes| classes.contains(first_class))
+    {
+        // destructors are the only vptr writers a vtable points at
+        Role::Destructor
+    } else {
+        Role::Constructor
+    };
+
+    let class_name = match role {
+        Role::Destructor => first_class.clone(),
+        _ => last_class.clone(),
+    };
+    let suggested_name = (!has_symbol && role != Role::InlinedConstruction).then(|| {
+        let constructor_name = get_constructor_name(&class_name);
+        match role {
+            Role::Destructor => format!("{}::~{}", class_name, constructor_name),
+            _ => format!("{}::{}", class_name, constructor_name),
+        }
+    });
+
+    VptrWriter {
+        function,
+        symbol,
+        suggested_name,
+        role,
+        class_name,
+        object,
+        stores,
+    }
+}
+
+/// `cocos2d::CCLayer` -> `CCLayer`, `Foo<int>` -> `Foo`
+pub fn get_constructor_name(class_name: &str) -> &str {
+    let unqualified_name = get_unqualified_name(class_name);
+    &unqualified_name[..unqualified_name.find('<').unwrap_or(unqualified_name.len())]
+}
+
+/// Keeps the writers of the given classes.
+pub fn filter_classes(writers: Vec<VptrWriter>, class_names: &[String]) -> Vec<VptrWriter> {
+    let class_names: HashSet<String> = class_names
+        .iter()
+        .map(|class_name| normalize_class_name(class_name))
+        .collect();
+    writers
+        .into_iter()
+        .filter(|writer| class_names.contains(&normalize_class_name(&writer.class_name)))
+        .collect()
+}
+
+pub fn get_display(writers: &[VptrWriter]) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut current_class: Option<&str> = None;
+
+    for writer in writers {
+        if current_class != Some(&writer.class_name) {
+            if current_class.is_some() {
+                lines.push(String::new());
+            }
+            lines.push(writer.class_name.clone());
+            current_class = Some(&writer.class_name);
+        }
+
+        let role = match writer.role {
+            Role::Constructor => "constructor",
+            Role::Destructor => "destructor",
+            Role::InlinedConstruction => "inlined",
+        };
+        let name = match &writer.suggested_name {
+            Some(suggested_name) => format!("{} (probably {})", writer.symbol, suggested_name),
+            None => demangle(&writer.symbol).unwrap_or(writer.symbol.clone()),
+        };
+        lines.push(format!(
+            "    {:<12} 0x{:x}  {}",
+            role,
+            writer.function & !1,
+            name
+        ));
+
+        for store in &writer.stores {
+            let object = match writer.object {
+                Object::This => "this".to_string(),
+                Object::Allocation(call) => format!("new@0x{:x}", call),
+                Object::Unknown => "?".to_string(),
+            };
+            let target = match store.object_offset {
+                Some(offset) if offset < 0 => format!("[{}-0x{:x}]", object, -offset),
+                Some(offset) => format!("[{}+0x{:x}]", object, offset),
+                None => format!("[{}]", object),
+            };
+            lines.push(format!(
+                "        0x{:x}  {} = vtable for {}+0x{:x} (offset-to-top {})",
+                store.address,
+                target,
+                store.class_name,
+                store.address_point - store.vtable_address,
+                store.offset_to_top
+            ));
+        }
+    }
+
+    lines.join("\n")
+}
@@ -0,0 +1,186 @@
+use elf::{abi, endian::LittleEndian};
+use std::path::Path;
+
+// the file mappings note of Linux core dumps, `NT_FILE` in linux/elf.h
+const NT_FILE: u32 = 0x46494c45;
+
+/// A file mapped into the crashed process, from the NT_FILE note.
+struct MappedFile {
+    start: u64,
+    /// offset of the mapping in the file
+    file_offset: u64,
+    path: String,
+}
+
+/// The memory of a process saved in an ELF core file, or a part of it.
+pub struct CoreDump {
+    data: Vec<u8>,
+    /// 4 for ELF32 (ARM), 8 for ELF64 (AArch64)
+    pub pointer_size: u32,
+    // (virtual address, file offset, size in file) of every PT_LOAD segment,
+    // segments the kernel did not dump have no bytes in the file
+    segments: Vec<(u64, u64, u64)>,
+    files: Vec<MappedFile>,
+}
+
+impl CoreDump {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data =
+            std::fs::read(path).map_err(|error| format!("failed to read {:?}: {}", path, error))?;
+        let elf = elf::ElfBytes::<LittleEndian>::minimal_parse(&data)
+            .map_err(|_| format!("{:?} is not an ELF file", path))?;
+        if elf.ehdr.e_type != abi::ET_CORE {
+            return Err(format!("{:?} is not a core dump", path));
+        }
+        let is_64 = elf.ehdr.class == elf::file::Class::ELF64;
+        let segments: Vec<elf::segment::ProgramHeader> = elf
+            .segments()
+            .map(|segments| segments.iter().collect())
+            .unwrap_or_default();
+
+        let mut files = Vec::new();
+        for segment in segments
+            .iter()
+            .filter(|segment| segment.p_type == abi::PT_NOTE)
+        {
+            let Some(notes) =
+                data.get(segment.p_offset as usize..(segment.p_offset + segment.p_filesz) as usize)
+            else {
+                continue;
+            };
+            files.extend(get_mapped_files(notes, is_64));
+        }
+
+        Ok(Self {
+            pointer_size: if is_64 { 8 } else { 4 },
+            segments: segments
+                .iter()
+                .filter(|segment| segment.p_type == abi::PT_LOAD)
+                .map(|segment| (segment.p_vaddr, segment.p_offset, segment.p_filesz))
+                .collect(),
+            files,
+            data,
+        })
+    }
+
+    /// Memory copied out of a debugger, `bytes` being at `addr`.
+    pub fn from_bytes(addr: u64, bytes: Vec<u8>, pointer_size: u32) -> Self {
+        Self {
+            pointer_size,
+            segments: vec![(addr, 0, bytes.len() as u64)],
+            files: Vec::new(),
+            data: bytes,
+        }
+    }
+
+    /// The bytes dumped at `addr..addr + size`, `None` if the range is not
+    /// entirely in one dumped segment.
+    pub fn read(&self, addr: u64, size: u64) -> Option<&[u8]> {
+        let (vaddr, offset, _) = self.segments.iter().find(|(vaddr, _, filesz)| {
+            *vaddr <= addr
+                && addr
+                    .checked_add(size)
+                    .is_some_and(|end| end <= vaddr + filesz)
+        })?;
+        let start = (offset + (addr - vaddr)) as usize;
+        self.data.get(start..start + size as usize)
+    }
+
+    /// The number of bytes saved.
+    pub fn get_size(&self) -> u64 {
+        self.segments.iter().map(|(_, _, size)| size).sum()
+    }
+
+    pub fn read_pointer(&self, addr: u64) -> Option<u64> {
+        let word = self.read(addr, self.pointer_size as u64)?;
+        let mut bytes = [0u8; 8];
+        bytes[..word.len()].copy_from_slice(word);
+        Some(u64::from_le_bytes(bytes))
+    }
+
+    /// (address, value) of every dumped pointer sized word of
+    /// `addr..addr + size`.
+    pub fn read_words(&self, addr: u64, size: u64) -> Vec<(u64, u64)> {
+        let pointer_size = self.pointer_size as u64;
+        (addr..addr.saturating_add(size - size % pointer_size))
+            .step_by(pointer_size as usize)
+            .filter_map(|word| Some((word, self.read_pointer(word)?)))
+            .collect()
+    }
+
+    /// The address a library was loaded at, from the mapping of the start of
+    /// the file whose name matches `library_path`'s.
+    pub fn get_load_address(&self, library_path: &Path) -> Option<u64> {
+        // a library mapped straight from an APK is named after the APK, its
+        // address has to be given instead
+        let path = library_path.to_string_lossy();
+        let name = path.rsplit(['/', '!']).next()?;
+
+        self.files
+            .iter()
+            .find(|file| file.file_offset == 0 && file.path.rsplit('/').next() == Some(name))
+            .map(|file| file.start)
+    }
+}
+
+/// Parses the NT_FILE notes: a count and a page size, `count` (start, end,
+/// page offset) triples, then the `count` paths.
+fn get_mapped_files(notes: &[u8], is_64: bool) -> Vec<MappedFile> {
+    let mut files = Vec::new();
+    let mut offset = 0;
+
+    while let (Some(name_size), Some(desc_size), Some(note_type)) = (
+        read_u32(notes, offset),
+        read_u32(notes, offset + 4),
+        read_u32(notes, offset + 8),
+    ) {
+        let desc_start = offset + 12 + (name_size as usize).next_multiple_of(4);
+        let desc_end = desc_start + desc_size as usize;
+        offset = desc_end.next_multiple_of(4);
+
+        if note_type != NT_FILE {
+            continue;
+        }
+        let Some(desc) = notes.get(desc_start..desc_end) else {
+            break;
+        };
+
+        let word_size = if is_64 { 8 } else { 4 };
+        let read_word = |index: usize| -> Option<u64> {
+            let word = desc.get(index * word_size..(index + 1) * word_size)?;
+            let mut bytes = [0u8; 8];
+            bytes[..word_size].copy_from_slice(word);
+            Some(u64::from_le_bytes(bytes))
+        };
+        let (Some(count), Some(page_size)) = (read_word(0), read_word(1)) else {
+            continue;
+        };
+        let mut paths = desc
+            .get((2 + 3 * count as usize) * word_size..)
+            .unwrap_or_default()
+            .split(|byte| *byte == 0);
+
+        for index in 0..count as usize {
+            let (Some(start), Some(page_offset), Some(path)) = (
+                read_word(2 + 3 * index),
+                read_word(2 + 3 * index + 2),
+                paths.next(),
+            ) else {
+                break;
+            };
+            files.push(MappedFile {
+                start,
+                file_offset: page_offset * page_size,
+                path: String::from_utf8_lossy(path).into_owned(),
+            });
+        }
+    }
+
+    files
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
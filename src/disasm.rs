@@ -0,0 +1,1446 @@
+//! A small decoder for the ARM, Thumb and AArch64 instructions the analyses
+//! care about: constant loads, calls, branches, returns and memory accesses
+//! relative to a register. Everything else decodes to `Operation::Other`.
+
+use crate::library::Library;
+
+pub const EM_AARCH64: u16 = 183;
+
+/// Register numbers follow the architecture (r0-r15, x0-x30), `SP` and `LR`
+/// are the ones of the instruction set being decoded.
+pub const ARM_SP: u8 = 13;
+pub const ARM_LR: u8 = 14;
+pub const ARM_PC: u8 = 15;
+/// sp and xzr share the encoding 31, which one is meant depends on the
+/// instruction.
+pub const ARM64_LR: u8 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InstructionSet {
+    Arm,
+    Thumb,
+    Arm64,
+}
+
+impl InstructionSet {
+    /// The instruction set of the code at `addr`, ARM32 code addresses have
+    /// the thumb bit set.
+    pub fn of(library: &Library, addr: u32) -> Self {
+        if library.machine == EM_AARCH64 {
+            InstructionSet::Arm64
+        } else if addr & 1 != 0 {
+            InstructionSet::Thumb
+        } else {
+            InstructionSet::Arm
+        }
+    }
+
+    /// The registers a call may overwrite (r0-r3, r12 and lr / x0-x18 and lr).
+    pub fn is_caller_saved(&self, register: u8) -> bool {
+        match self {
+            InstructionSet::Arm64 => register <= 18 || register == ARM64_LR,
+            _ => register <= 3 || register == 12 || register == ARM_LR,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operation {
+    /// `rd = imm` (mov, movs, movw, mvn, movz, movn, orr with the zero register)
+    MoveImmediate {
+        rd: u8,
+        imm: u64,
+    },
+    /// replaces 16 bits of `rd` (movt, movk)
+    MoveKeep {
+        rd: u8,
+        imm: u16,
+        shift: u8,
+    },
+    MoveRegister {
+        rd: u8,
+        rm: u8,
+    },
+    /// `rd = rn + imm`, reads of the pc are already folded into `imm`
+    AddImmediate {
+        rd: u8,
+        rn: u8,
+        imm: i64,
+    },
+    /// `rd = addr` (adr, adrp, add rd, pc, #imm)
+    Address {
+        rd: u8,
+        addr: u32,
+    },
+    /// `rt = [addr]` for pc relative loads
+    LoadLiteral {
+        rt: u8,
+        addr: u32,
+        size: u8,
+    },
+    /// `rt = [rn + offset]`
+    Load {
+        rt: u8,
+        rn: u8,
+        offset: i64,
+        size: u8,
+    },
+    /// `[rn + offset] = rt`
+    Store {
+        rt: u8,
+        rn: u8,
+        offset: i64,
+        size: u8,
+    },
+    /// `rt = [rn + rm]`
+    LoadRegister {
+        rt: u8,
+        rn: u8,
+        rm: u8,
+        size: u8,
+    },
+    /// a direct branch, `target` has the thumb bit set for thumb code
+    Branch {
+        target: u32,
+        link: bool,
+    },
+    ConditionalBranch {
+        target: u32,
+    },
+    /// `bx`, `blx`, `br`, `blr` through a register other than lr
+    BranchRegister {
+        rm: u8,
+        link: bool,
+    },
+    Return,
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub address: u32,
+    pub size: u32,
+    pub operation: Operation,
+    /// whether the instruction only executes under a condition (ARM
+    /// condition codes), which makes the value it writes uncertain
+    pub conditional: bool,
+}
+
+fn sign_extend(value: u64, bits: u32) -> i64 {
+    let shift = 64 - bits;
+    ((value << shift) as i64) >> shift
+}
+
+/// Decodes the instruction at `addr`, `None` if it is outside the file.
+pub fn decode(library: &Library, addr: u32, set: InstructionSet) -> Option<Instruction> {
+    let addr = addr & !1;
+    let offset = library.get_file_offset(addr)? as usize;
+    let bytes = library.data.get(offset..)?;
+
+    match set {
+        InstructionSet::Arm => {
+            let word = u32::from_le_bytes(bytes.get(..4)?.try_into().unwrap());
+            Some(decode_arm(word, addr))
+        }
+        InstructionSet::Arm64 => {
+            let word = u32::from_le_bytes(bytes.get(..4)?.try_into().unwrap());
+            Some(decode_arm64(word, addr))
+        }
+        InstructionSet::Thumb => {
+            let first = u16::from_le_bytes(bytes.get(..2)?.try_into().unwrap());
+            if first >> 11 >= 0b11101 {
+                let second = u16::from_le_bytes(bytes.get(2..4)?.try_into().unwrap());
+                Some(decode_thumb32(first, second, addr))
+            } else {
+                Some(decode_thumb16(first, addr))
+            }
+        }
+    }
+}
+
+/// `ARMExpandImm`: an 8-bit value rotated right by twice the 4-bit rotation.
+fn arm_expand_imm(imm12: u32) -> u32 {
+    (imm12 & 0xff).rotate_right(2 * (imm12 >> 8))
+}
+
+fn decode_arm(word: u32, addr: u32) -> Instruction {
+    let condition = word >> 28;
+    let pc = addr.wrapping_add(8);
+    let rd = ((word >> 12) & 0xf) as u8;
+    let rn = ((word >> 16) & 0xf) as u8;
+    let rm = (word & 0xf) as u8;
+
+    let operation = if condition == 0xf {
+        if word & 0x0e000000 == 0x0a000000 {
+            // blx imm24 switches to thumb
+            let offset = sign_extend(((word & 0xffffff) << 2 | (word >> 23) & 2) as u64, 26);
+            Operation::Branch {
+                target: (pc as i64 + offset) as u32 | 1,
+                link: true,
+            }
+        } else {
+            Operation::Other
+        }
+    } else if word & 0x0e000000 == 0x0a000000 {
+        let target = (pc as i64 + sign_extend(((word & 0xffffff) << 2) as u64, 26)) as u32;
+        let link = word & 0x01000000 != 0;
+        if condition != 0xe && !link {
+            Operation::ConditionalBranch { target }
+        } else {
+            Operation::Branch { target, link }
+        }
+    } else if word & 0x0ffffff0 == 0x012fff10 || word & 0x0ffffff0 == 0x012fff30 {
+        let link = word & 0x20 != 0;
+        if rm == ARM_LR && !link {
+            Operation::Return
+        } else {
+            Operation::BranchRegister { rm, link }
+        }
+    } else if word & 0x0ff00000 == 0x03000000 {
+        Operation::MoveImmediate {
+            rd,
+            imm: ((word >> 4) & 0xf000 | word & 0xfff) as u64,
+        }
+    } else if word & 0x0ff00000 == 0x03400000 {
+        Operation::MoveKeep {
+            rd,
+            imm: ((word >> 4) & 0xf000 | word & 0xfff) as u16,
+            shift: 16,
+        }
+    } else if word & 0x0fef0000 == 0x03a00000 {
+        Operation::MoveImmediate {
+            rd,
+            imm: arm_expand_imm(word & 0xfff) as u64,
+        }
+    } else if word & 0x0fef0000 == 0x03e00000 {
+        Operation::MoveImmediate {
+            rd,
+            imm: !arm_expand_imm(word & 0xfff) as u64,
+        }
+    } else if word & 0x0fef0ff0 == 0x01a00000 {
+        if rd == ARM_PC && rm == ARM_LR {
+            Operation::Return
+        } else {
+            Operation::MoveRegister { rd, rm }
+        }
+    } else if word & 0x0fe00000 == 0x02800000 || word & 0x0fe00000 == 0x02400000 {
+        let mut imm = arm_expand_imm(word & 0xfff) as i64;
+        if word & 0x0fe00000 == 0x02400000 {
+            imm = -imm;
+        }
+        if rn == ARM_PC {
+            Operation::Address {
+                rd,
+                addr: (pc as i64 + imm) as u32,
+            }
+        } else {
+            Operation::AddImmediate { rd, rn, imm }
+        }
+    } else if word & 0x0fe00ff0 == 0x00800000 && (rn == ARM_PC || rm == ARM_PC) {
+        // add rd, pc, rm (position independent address computation)
+        Operation::AddImmediate {
+            rd,
+            rn: if rn == ARM_PC { rm } else { rn },
+            imm: pc as i64,
+        }
+    } else if word & 0x0f000000 == 0x05000000 {
+        // ldr/str/ldrb/strb rt, [rn, #+/-imm12]{!}, the writeback of
+        // pre-indexed accesses is not tracked
+        let mut offset = (word & 0xfff) as i64;
+        if word & 0x00800000 == 0 {
+            offset = -offset;
+        }
+        let size = if word & 0x00400000 != 0 { 1 } else { 4 };
+        if word & 0x00100000 == 0 {
+            Operation::Store {
+                rt: rd,
+                rn,
+                offset,
+                size,
+            }
+        } else if rn == ARM_PC {
+            Operation::LoadLiteral {
+                rt: rd,
+                addr: (pc as i64 + offset) as u32,
+                size,
+            }
+        } else {
+            Operation::Load {
+                rt: rd,
+                rn,
+                offset,
+                size,
+            }
+        }
+    } else if word & 0x0ff00ff0 == 0x07900000 {
+        // ldr rt, [rn, rm]
+        if rn == ARM_PC {
+            Operation::Load {
+                rt: rd,
+                rn: rm,
+                offset: pc as i64,
+                size: 4,
+            }
+        } else {
+            Operation::LoadRegister {
+                rt: rd,
+                rn,
+                rm,
+                size: 4,
+            }
+        }
+    } else if word & 0x0f6000f0 == 0x014000b0 {
+        // ldrh/strh rt, [rn, #+/-imm8]
+        let mut offset = ((word >> 4) & 0xf0 | word & 0xf) as i64;
+        if word & 0x00800000 == 0 {
+            offset = -offset;
+        }
+        if word & 0x00100000 == 0 {
+            Operation::Store {
+                rt: rd,
+                rn,
+                offset,
+                size: 2,
+            }
+        } else {
+            Operation::Load {
+                rt: rd,
+                rn,
+                offset,
+                size: 2,
+            }
+        }
+    } else if word & 0x0fff8000 == 0x08bd8000 || word == 0xe49df004 {
+        // pop {..., pc} / ldr pc, [sp], #4
+        Operation::Return
+    } else {
+        Operation::Other
+    };
+
+    Instruction {
+        address: addr,
+        size: 4,
+        conditional: condition != 0xe && condition != 0xf,
+        operation,
+    }
+}
+
+fn decode_thumb16(half: u16, addr: u32) -> Instruction {
+    let pc = addr.wrapping_add(4);
+    let half = half as u32;
+    let low3 = (half & 7) as u8;
+    let mid3 = ((half >> 3) & 7) as u8;
+    let high3 = ((half >> 8) & 7) as u8;
+
+    let operation = match half >> 11 {
+        // movs rd, #imm8
+        0b00100 => Operation::MoveImmediate {
+            rd: high3,
+            imm: (half & 0xff) as u64,
+        },
+        // adds/subs rd, #imm8
+        0b00110 | 0b00111 => Operation::AddImmediate {
+            rd: high3,
+            rn: high3,
+            imm: if half >> 11 == 0b00110 {
+                (half & 0xff) as i64
+            } else {
+                -((half & 0xff) as i64)
+            },
+        },
+        0b00011 if half & 0x0400 != 0 => {
+            // adds/subs rd, rn, #imm3
+            let imm = ((half >> 6) & 7) as i64;
+            Operation::AddImmediate {
+                rd: low3,
+                rn: mid3,
+                imm: if half & 0x0200 != 0 { -imm } else { imm },
+            }
+        }
+        // ldr rt, [pc, #imm8]
+        0b01001 => Operation::LoadLiteral {
+            rt: high3,
+            addr: (pc & !3) + 4 * (half & 0xff),
+            size: 4,
+        },
+        0b01100..=0b10001 => {
+            let (size, is_load) = match half >> 11 {
+                0b01100 => (4, false),
+                0b01101 => (4, true),
+                0b01110 => (1, false),
+                0b01111 => (1, true),
+                0b10000 => (2, false),
+                _ => (2, true),
+            };
+            let offset = (((half >> 6) & 0x1f) * size as u32) as i64;
+            if is_load {
+                Operation::Load {
+                    rt: low3,
+                    rn: mid3,
+                    offset,
+                    size,
+                }
+            } else {
+                Operation::Store {
+                    rt: low3,
+                    rn: mid3,
+                    offset,
+                    size,
+                }
+            }
+        }
+        // str/ldr rt, [sp, #imm8]
+        0b10010 | 0b10011 => {
+            let offset = 4 * (half & 0xff) as i64;
+            if half >> 11 == 0b10011 {
+                Operation::Load {
+                    rt: high3,
+                    rn: ARM_SP,
+                    offset,
+                    size: 4,
+                }
+            } else {
+                Operation::Store {
+                    rt: high3,
+                    rn: ARM_SP,
+                    offset,
+                    size: 4,
+                }
+            }
+        }
+        // adr rd, label
+        0b10100 => Operation::Address {
+            rd: high3,
+            addr: (pc & !3) + 4 * (half & 0xff),
+        },
+        // add rd, sp, #imm8
+        0b10101 => Operation::AddImmediate {
+            rd: high3,
+            rn: ARM_SP,
+            imm: 4 * (half & 0xff) as i64,
+        },
+        // b label
+        0b11100 => Operation::Branch {
+            target: (pc as i64 + sign_extend(((half & 0x7ff) << 1) as u64, 12)) as u32 | 1,
+            link: false,
+        },
+        0b11010 | 0b11011 if (half >> 8) & 0xf < 0xe => Operation::ConditionalBranch {
+            target: (pc as i64 + sign_extend(((half & 0xff) << 1) as u64, 9)) as u32 | 1,
+        },
+        _ => decode_thumb16_misc(half, addr),
+    };
+
+    Instruction {
+        address: addr,
+        size: 2,
+        conditional: false,
+        operation,
+    }
+}
+
+fn decode_thumb16_misc(half: u32, addr: u32) -> Operation {
+    let pc = addr.wrapping_add(4);
+    let rdn = ((half & 7) | (half >> 4) & 8) as u8;
+    let rm = ((half >> 3) & 0xf) as u8;
+
+    if half & 0xff00 == 0x4400 {
+        // add rdn, rm
+        if rm == ARM_PC {
+            Operation::AddImmediate {
+                rd: rdn,
+                rn: rdn,
+                imm: pc as i64,
+            }
+        } else {
+            Operation::Other
+        }
+    } else if half & 0xff00 == 0x4600 {
+        if rdn == ARM_PC && rm == ARM_LR {
+            Operation::Return
+        } else {
+            Operation::MoveRegister { rd: rdn, rm }
+        }
+    } else if half & 0xff87 == 0x4700 {
+        if rm == ARM_LR {
+            Operation::Return
+        } else {
+            Operation::BranchRegister { rm, link: false }
+        }
+    } else if half & 0xff87 == 0x4780 {
+        Operation::BranchRegister { rm, link: true }
+    } else if half & 0xfe00 == 0xbc00 && half & 0x0100 != 0 {
+        // pop {..., pc}
+        Operation::Return
+    } else if half & 0xf500 == 0xb100 {
+        // cbz/cbnz rn, label
+        // i:imm5:'0'
+        let offset = ((half >> 2) & 0x3e) | ((half >> 3) & 0x40);
+        Operation::ConditionalBranch {
+            target: (pc + offset) | 1,
+        }
+    } else if half & 0xff00 == 0xb000 {
+        // add/sub sp, #imm7
+        let imm = 4 * (half & 0x7f) as i64;
+        Operation::AddImmediate {
+            rd: ARM_SP,
+            rn: ARM_SP,
+            imm: if half & 0x80 != 0 { -imm } else { imm },
+        }
+    } else if half & 0xf800 == 0x5800 {
+        // ldr rt, [rn, rm]
+        Operation::LoadRegister {
+            rt: (half & 7) as u8,
+            rn: ((half >> 3) & 7) as u8,
+            rm: ((half >> 6) & 7) as u8,
+            size: 4,
+        }
+    } else {
+        Operation::Other
+    }
+}
+
+/// `ThumbExpandImm` of the `i:imm3:imm8` field of modified immediates.
+fn thumb_expand_imm(imm12: u32) -> u32 {
+    let imm8 = imm12 & 0xff;
+    if imm12 >> 10 == 0 {
+        match (imm12 >> 8) & 3 {
+            0 => imm8,
+            1 => imm8 << 16 | imm8,
+            2 => imm8 << 24 | imm8 << 8,
+            _ => imm8 * 0x01010101,
+        }
+    } else {
+        (0x80 | imm12 & 0x7f).rotate_right(imm12 >> 7)
+    }
+}
+
+fn decode_thumb32(first: u16, second: u16, addr: u32) -> Instruction {
+    let pc = addr.wrapping_add(4);
+    let (first, second) = (first as u32, second as u32);
+    let rn = (first & 0xf) as u8;
+    let rd = ((second >> 8) & 0xf) as u8;
+    let rt = ((second >> 12) & 0xf) as u8;
+    let imm12 = (first & 0x0400) << 1 | (second >> 4) & 0x700 | second & 0xff;
+    let imm16 = (first & 0xf) << 12 | imm12;
+
+    let operation = if first & 0xf800 == 0xf000 && second & 0x8000 != 0 {
+        // b.w, bl, blx
+        let s = (first >> 10) & 1;
+        let j1 = (second >> 13) & 1;
+        let j2 = (second >> 11) & 1;
+        let i1 = !(j1 ^ s) & 1;
+        let i2 = !(j2 ^ s) & 1;
+
+        if second & 0x1000 != 0 || second & 0x4000 != 0 {
+            let offset = sign_extend(
+                (s << 24 | i1 << 23 | i2 << 22 | (first & 0x3ff) << 12 | (second & 0x7ff) << 1)
+                    as u64,
+                25,
+            );
+            let link = second & 0x4000 != 0;
+            if link && second & 0x1000 == 0 {
+                // blx switches to ARM, the target is word aligned
+                Operation::Branch {
+                    target: ((pc & !3) as i64 + offset) as u32 & !3,
+                    link: true,
+                }
+            } else {
+                Operation::Branch {
+                    target: (pc as i64 + offset) as u32 | 1,
+                    link,
+                }
+            }
+        } else if (first >> 6) & 0xf < 0xe {
+            // b<cond>.w
+            let offset = sign_extend(
+                (s << 20 | j2 << 19 | j1 << 18 | (first & 0x3f) << 12 | (second & 0x7ff) << 1)
+                    as u64,
+                21,
+            );
+            Operation::ConditionalBranch {
+                target: (pc as i64 + offset) as u32 | 1,
+            }
+        } else {
+            Operation::Other
+        }
+    } else if first & 0xfbf0 == 0xf240 && second & 0x8000 == 0 {
+        Operation::MoveImmediate {
+            rd,
+            imm: imm16 as u64,
+        }
+    } else if first & 0xfbf0 == 0xf2c0 && second & 0x8000 == 0 {
+        Operation::MoveKeep {
+            rd,
+            imm: imm16 as u16,
+            shift: 16,
+        }
+    } else if first & 0xfbef == 0xf04f && second & 0x8000 == 0 {
+        Operation::MoveImmediate {
+            rd,
+            imm: thumb_expand_imm(imm12) as u64,
+        }
+    } else if first & 0xfbef == 0xf06f && second & 0x8000 == 0 {
+        Operation::MoveImmediate {
+            rd,
+            imm: !thumb_expand_imm(imm12) as u64,
+        }
+    } else if (first & 0xfbe0 == 0xf100 || first & 0xfbe0 == 0xf1a0) && second & 0x8000 == 0 {
+        // add.w/sub.w rd, rn, #modified immediate
+        let imm = thumb_expand_imm(imm12) as i64;
+        Operation::AddImmediate {
+            rd,
+            rn,
+            imm: if first & 0x01e0 == 0x01a0 { -imm } else { imm },
+        }
+    } else if (first & 0xfbf0 == 0xf200 || first & 0xfbf0 == 0xf2a0) && second & 0x8000 == 0 {
+        // addw/subw rd, rn, #imm12
+        let imm = if first & 0x00f0 == 0x00a0 {
+            -(imm12 as i64)
+        } else {
+            imm12 as i64
+        };
+        if rn == ARM_PC {
+            Operation::Address {
+                rd,
+                addr: ((pc & !3) as i64 + imm) as u32,
+            }
+        } else {
+            Operation::AddImmediate { rd, rn, imm }
+        }
+    } else if first & 0xff7f == 0xf85f {
+        // ldr.w rt, [pc, #+/-imm12]
+        let offset = (second & 0xfff) as i64;
+        Operation::LoadLiteral {
+            rt,
+            addr: ((pc & !3) as i64 + if first & 0x80 != 0 { offset } else { -offset }) as u32,
+            size: 4,
+        }
+    } else if first & 0xff00 == 0xf800 && first & 0x0060 != 0x0060 {
+        decode_thumb32_memory(first, second)
+    } else if first & 0xffd0 == 0xe890 && first & 0xf == ARM_SP as u32 && second & 0x8000 != 0 {
+        // pop.w {..., pc}
+        Operation::Return
+    } else if first == 0xf85d && second == 0xfb04 {
+        // ldr.w pc, [sp], #4
+        Operation::Return
+    } else {
+        Operation::Other
+    };
+
+    Instruction {
+        address: addr,
+        size: 4,
+        conditional: false,
+        operation,
+    }
+}
+
+/// ldr/str/ldrb/strb/ldrh/strh (and their signed loads) with an immediate offset.
+fn decode_thumb32_memory(first: u32, second: u32) -> Operation {
+    let rn = (first & 0xf) as u8;
+    let rt = ((second >> 12) & 0xf) as u8;
+    let size = match (first >> 5) & 3 {
+        0 => 1,
+        1 => 2,
+        _ => 4,
+    };
+    let is_load = first & 0x0010 != 0;
+
+    let offset = if first & 0x0080 != 0 {
+        (second & 0xfff) as i64
+    } else if second & 0x0f00 == 0x0c00 {
+        // negative offset without writeback
+        -((second & 0xff) as i64)
+    } else if second & 0x0f00 == 0x0e00 {
+        // unprivileged, positive
+        (second & 0xff) as i64
+    } else {
+        return Operation::Other;
+    };
+
+    if is_load {
+        Operation::Load {
+            rt,
+            rn,
+            offset,
+            size,
+        }
+    } else {
+        Operation::Store {
+            rt,
+            rn,
+            offset,
+            size,
+        }
+    }
+}
+
+/// `DecodeBitMasks` for the logical immediates of AArch64.
+fn decode_bit_mask(n: u32, imms: u32, immr: u32, width: u32) -> Option<u64> {
+    let combined = (n << 6) | (!imms & 0x3f);
+    if combined < 2 {
+        return None;
+    }
+    let length = 31 - combined.leading_zeros();
+    let size = 1u32 << length;
+    let levels = size - 1;
+    let s = imms & levels;
+    let r = immr & levels;
+    if s == levels {
+        return None;
+    }
+
+    let element = if s + 1 == 64 {
+        u64::MAX
+    } else {
+        (1u64 << (s + 1)) - 1
+    };
+    let element = if r == 0 {
+        element
+    } else {
+        let mask = if size == 64 {
+            u64::MAX
+        } else {
+            (1u64 << size) - 1
+        };
+        ((element >> r) | (element << (size - r))) & mask
+    };
+
+    let mut result = 0u64;
+    let mut position = 0;
+    while position < width {
+        result |= element << position;
+        position += size;
+    }
+    Some(if width == 64 {
+        result
+    } else {
+        result & 0xffffffff
+    })
+}
+
+fn decode_arm64(word: u32, addr: u32) -> Instruction {
+    let rd = (word & 0x1f) as u8;
+    let rn = ((word >> 5) & 0x1f) as u8;
+    let is_64 = word & 0x80000000 != 0;
+
+    let operation = if word & 0x7c000000 == 0x14000000 {
+        // b / bl
+        Operation::Branch {
+            target: (addr as i64 + sign_extend(((word & 0x03ffffff) << 2) as u64, 28)) as u32,
+            link: word & 0x80000000 != 0,
+        }
+    } else if word & 0xff000010 == 0x54000000 {
+        Operation::ConditionalBranch {
+            target: (addr as i64 + sign_extend((((word >> 5) & 0x7ffff) << 2) as u64, 21)) as u32,
+        }
+    } else if word & 0x7e000000 == 0x34000000 {
+        // cbz/cbnz
+        Operation::ConditionalBranch {
+            target: (addr as i64 + sign_extend((((word >> 5) & 0x7ffff) << 2) as u64, 21)) as u32,
+        }
+    } else if word & 0x7e000000 == 0x36000000 {
+        // tbz/tbnz
+        Operation::ConditionalBranch {
+            target: (addr as i64 + sign_extend((((word >> 5) & 0x3fff) << 2) as u64, 16)) as u32,
+        }
+    } else if word & 0xfffffc1f == 0xd65f0000 {
+        Operation::Return
+    } else if word & 0xfffffc1f == 0xd61f0000 || word & 0xfffffc1f == 0xd63f0000 {
+        Operation::BranchRegister {
+            rm: rn,
+            link: word & 0x00200000 != 0,
+        }
+    } else if word & 0x1f000000 == 0x10000000 {
+        // adr / adrp
+        let imm = sign_extend(((word >> 3) & 0x1ffffc | (word >> 29) & 3) as u64, 21);
+        let addr = if word & 0x80000000 != 0 {
+            ((addr & !0xfff) as i64 + (imm << 12)) as u32
+        } else {
+            (addr as i64 + imm) as u32
+        };
+        Operation::Address { rd, addr }
+    } else if word & 0x7f800000 == 0x52800000 || word & 0x7f800000 == 0x12800000 {
+        // movz / movn
+        let shift = 16 * ((word >> 21) & 3);
+        let mut imm = (((word >> 5) & 0xffff) as u64) << shift;
+        if word & 0x40000000 == 0 {
+            imm = if is_64 { !imm } else { !imm & 0xffffffff };
+        }
+        Operation::MoveImmediate { rd, imm }
+    } else if word & 0x7f800000 == 0x72800000 {
+        Operation::MoveKeep {
+            rd,
+            imm: ((word >> 5) & 0xffff) as u16,
+            shift: (16 * ((word >> 21) & 3)) as u8,
+        }
+    } else if word & 0x7f8003e0 == 0x320003e0 {
+        // orr rd, zr, #bitmask (mov)
+        match decode_bit_mask(
+            (word >> 22) & 1,
+            (word >> 10) & 0x3f,
+            (word >> 16) & 0x3f,
+            if is_64 { 64 } else { 32 },
+        ) {
+            Some(imm) => Operation::MoveImmediate { rd, imm },
+            None => Operation::Other,
+        }
+    } else if word & 0x7fe0ffe0 == 0x2a0003e0 {
+        // orr rd, zr, rm (mov), rm is the zero register rather than sp
+        match ((word >> 16) & 0x1f) as u8 {
+            31 => Operation::MoveImmediate { rd, imm: 0 },
+            rm => Operation::MoveRegister { rd, rm },
+        }
+    } else if word & 0x1f800000 == 0x11000000 && (word & 0x20000000 == 0 || rd != 31) {
+        // add/sub(s) rd, rn, #imm12{, lsl #12}
+        let mut imm = ((word >> 10) & 0xfff) as i64;
+        if word & 0x00400000 != 0 {
+            imm <<= 12;
+        }
+        if word & 0x40000000 != 0 {
+            imm = -imm;
+        }
+        if imm == 0 && word & 0x20000000 == 0 {
+            Operation::MoveRegister { rd, rm: rn }
+        } else {
+            Operation::AddImmediate { rd, rn, imm }
+        }
+    } else if word & 0x3b000000 == 0x39000000 {
+        // ldr/str (unsigned offset)
+        let size_log2 = word >> 30;
+        let offset = (((word >> 10) & 0xfff) << size_log2) as i64;
+        decode_arm64_memory(word, rd, rn, offset)
+    } else if word & 0x3b200c00 == 0x38000000 {
+        // ldur/stur (unscaled offset)
+        let offset = sign_extend(((word >> 12) & 0x1ff) as u64, 9);
+        decode_arm64_memory(word, rd, rn, offset)
+    } else if word & 0xbf000000 == 0x18000000 {
+        // ldr rt, label
+        Operation::LoadLiteral {
+            rt: rd,
+            addr: (addr as i64 + sign_extend((((word >> 5) & 0x7ffff) << 2) as u64, 21)) as u32,
+            size: if word & 0x40000000 != 0 { 8 } else { 4 },
+        }
+    } else {
+        Operation::Other
+    };
+
+    Instruction {
+        address: addr,
+        size: 4,
+        conditional: false,
+        operation,
+    }
+}
+
+fn decode_arm64_memory(word: u32, rt: u8, rn: u8, offset: i64) -> Operation {
+    // SIMD&FP registers are not tracked
+    if word & 0x04000000 != 0 {
+        return Operation::Other;
+    }
+
+    let size = 1 << (word >> 30);
+    match (word >> 22) & 3 {
+        0 => Operation::Store {
+            rt,
+            rn,
+            offset,
+            size,
+        },
+        1 => Operation::Load {
+            rt,
+            rn,
+            offset,
+            size,
+        },
+        // sign extending loads; prfm shares the encoding of a 64-bit one
+        _ if size < 8 => Operation::Load {
+            rt,
+            rn,
+            offset,
+            size,
+        },
+        _ => Operation::Other,
+    }
+}
+
+/// Decodes a function from `addr` up to the end of its symbol (or of its
+/// unwind table entry), or, when the size is unknown, up to the first return
+/// or tail call that no earlier branch jumps over. At most `max_size` bytes
+/// are decoded.
+pub fn decode_function(library: &Library, addr: u32, max_size: u32) -> Vec<Instruction> {
+    let set = InstructionSet::of(library, addr);
+    let start = addr & !1;
+    // nothing can be decoded past the end of the address space
+    let Some(limit) = start.checked_add(max_size) else {
+        return Vec::new();
+    };
+    let end = library
+        .get_function_size(start)
+        .map(|size| start + size.min(max_size));
+
+    let mut instructions = Vec::new();
+    let mut furthest_branch = start;
+    let mut current = start;
+
+    while current < end.unwrap_or(limit) {
+        let Some(instruction) = decode(library, current, set) else {
+            break;
+        };
+        current = current.saturating_add(instruction.size);
+
+        if let Operation::ConditionalBranch { target } = instruction.operation {
+            furthest_branch = furthest_branch.max(target & !1);
+        }
+        let is_exit = matches!(
+            instruction.operation,
+            Operation::Return
+                | Operation::Branch { link: false, .. }
+                | Operation::BranchRegister { link: false, .. }
+        ) && !instruction.conditional;
+
+        instructions.push(instruction);
+
+        if end.is_none() && is_exit && furthest_branch < current {
+            break;
+        }
+    }
+
+    instructions
+}
+
+/// The constants known to be in each register while walking a function
+/// linearly. Control flow is ignored: the values are the ones of the
+/// straight-line path through the code.
+pub struct RegisterState {
+    set: InstructionSet,
+    values: [Option<u64>; 32],
+}
+
+impl RegisterState {
+    pub fn new(set: InstructionSet) -> Self {
+        Self {
+            set,
+            values: [None; 32],
+        }
+    }
+
+    pub fn get(&self, register: u8) -> Option<u64> {
+        self.values.get(register as usize).copied().flatten()
+    }
+
+    fn set(&mut self, register: u8, value: Option<u64>) {
+        // writes to xzr are discarded
+        if self.set == InstructionSet::Arm64 && register == 31 {
+            return;
+        }
+        if let Some(slot) = self.values.get_mut(register as usize) {
+            *slot = match (value, self.set) {
+                (Some(value), InstructionSet::Arm | InstructionSet::Thumb) => {
+                    Some(value & 0xffffffff)
+                }
+                _ => value,
+            };
+        }
+    }
+
+    /// Applies the effect of `instruction` on the known constants, reading
+    /// literal pools and memory through `library`.
+    pub fn step(&mut self, library: &Library, instruction: &Instruction) {
+        let certain = |value: Option<u64>| value.filter(|_| !instruction.conditional);
+
+        match instruction.operation {
+            Operation::MoveImmediate { rd, imm } => self.set(rd, certain(Some(imm))),
+            Operation::MoveKeep { rd, imm, shift } => {
+                let value = self
+                    .get(rd)
+                    .map(|value| value & !(0xffff << shift) | (imm as u64) << shift);
+                self.set(rd, certain(value));
+            }
+            Operation::MoveRegister { rd, rm } => self.set(rd, certain(self.get(rm))),
+            Operation::AddImmediate { rd, rn, imm } => {
+                let value = self
+                    .get(rn)
+                    .map(|value| (value as i64).wrapping_add(imm) as u64);
+                self.set(rd, certain(value));
+            }
+            Operation::Address { rd, addr } => self.set(rd, certain(Some(addr as u64))),
+            Operation::LoadLiteral { rt, addr, size } => {
+                let value = read_value(library, addr, size);
+                self.set(rt, certain(value));
+            }
+            Operation::Load {
+                rt,
+                rn,
+                offset,
+                size,
+            } => {
+                // only loads from the library itself (GOT entries, literal
+                // pools reached through a register) are known
+                let value = self
+                    .get(rn)
+                    .map(|base| (base as i64).wrapping_add(offset) as u32)
+                    .filter(|addr| library.get_file_offset(*addr).is_some())
+                    .and_then(|addr| read_value(library, addr, size));
+                self.set(rt, certain(value));
+            }
+            Operation::LoadRegister { rt, .. } => self.set(rt, None),
+            Operation::Branch { link: true, .. } | Operation::BranchRegister { link: true, .. } => {
+                for register in 0..32 {
+                    if self.set.is_caller_saved(register) {
+                        self.values[register as usize] = None;
+                    }
+                }
+            }
+            Operation::Other => {
+                // the destination of an unknown instruction is unknown, for
+                // ARM and Thumb it is usually the rd field; be conservative
+                // and forget every register that could have been written
+                if let Some(rd) = get_other_destination(library, instruction, self.set) {
+                    self.set(rd, None);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn read_value(library: &Library, addr: u32, size: u8) -> Option<u64> {
+    // GOT slots of defined symbols are only filled in by the dynamic linker
+    if let Some(symbol) = library.got_symbols.get(&addr)
+        && let Some(target) = library.sym_to_addr.get(symbol).filter(|addr| **addr != 0)
+    {
+        return Some(*target as u64);
+    }
+
+    let offset = library.get_file_offset(addr)? as usize;
+    let bytes = library.data.get(offset..offset + size as usize)?;
+    let mut buffer = [0u8; 8];
+    buffer[..bytes.len()].copy_from_slice(bytes);
+    Some(u64::from_le_bytes(buffer))
+}
+
+/// The register an instruction writes, calls aside (they clobber every
+/// caller saved register).
+pub fn get_written_register(
+    library: &Library,
+    instruction: &Instruction,
+    set: InstructionSet,
+) -> Option<u8> {
+    match instruction.operation {
+        Operation::MoveImmediate { rd, .. }
+        | Operation::MoveKeep { rd, .. }
+        | Operation::MoveRegister { rd, .. }
+        | Operation::AddImmediate { rd, .. }
+        | Operation::Address { rd, .. } => Some(rd),
+        Operation::LoadLiteral { rt, .. }
+        | Operation::Load { rt, .. }
+        | Operation::LoadRegister { rt, .. } => Some(rt),
+        Operation::Other => get_other_destination(library, instruction, set),
+        _ => None,
+    }
+}
+
+/// The encoding of an instruction, a 32-bit Thumb instruction as
+/// `first << 16 | second` like the ARM reference manual writes it.
+pub fn get_encoding(
+    library: &Library,
+    instruction: &Instruction,
+    set: InstructionSet,
+) -> Option<u32> {
+    let offset = library.get_file_offset(instruction.address)? as usize;
+    let bytes = library
+        .data
+        .get(offset..offset + instruction.size as usize)?;
+
+    match bytes.len() {
+        2 => Some(u16::from_le_bytes(bytes.try_into().ok()?) as u32),
+        4 if set == InstructionSet::Thumb => {
+            let first = u16::from_le_bytes(bytes[..2].try_into().ok()?) as u32;
+            let second = u16::from_le_bytes(bytes[2..].try_into().ok()?) as u32;
+            Some(first << 16 | second)
+        }
+        _ => Some(u32::from_le_bytes(bytes.try_into().ok()?)),
+    }
+}
+
+/// The register most likely written by an instruction the decoder does not
+/// model, `None` when it writes none (stores, compares, branches).
+fn get_other_destination(
+    library: &Library,
+    instruction: &Instruction,
+    set: InstructionSet,
+) -> Option<u8> {
+    let offset = library.get_file_offset(instruction.address)? as usize;
+    let bytes = library
+        .data
+        .get(offset..offset + instruction.size as usize)?;
+
+    match (set, instruction.size) {
+        (InstructionSet::Thumb, 2) => {
+            let half = u16::from_le_bytes(bytes.try_into().ok()?);
+            // shifts, data processing and register offset loads write the
+            // low 3 bits, except compares and tests
+            let is_compare = half & 0xf800 == 0x2800
+                || half & 0xffc0 == 0x4280
+                || half & 0xffc0 == 0x42c0
+                || half & 0xffc0 == 0x4200
+                || half & 0xff00 == 0x4500;
+            (half < 0x6000 && !is_compare).then_some((half & 7) as u8)
+        }
+        (InstructionSet::Thumb, _) => {
+            let first = u16::from_le_bytes(bytes[..2].try_into().ok()?);
+            let second = u16::from_le_bytes(bytes[2..].try_into().ok()?);
+            // data processing (modified immediate, plain binary immediate,
+            // shifted register) writes bits 8-11 of the second halfword
+            let is_data_processing = first & 0xf800 == 0xf000 && second & 0x8000 == 0
+                || first & 0xfe00 == 0xea00
+                || first & 0xff00 == 0xfb00
+                || first & 0xff00 == 0xfa00;
+            let is_load = first & 0xfe10 == 0xf810 || first & 0xfe50 == 0xe850;
+            if is_load {
+                Some(((second >> 12) & 0xf) as u8)
+            } else {
+                is_data_processing.then_some(((second >> 8) & 0xf) as u8)
+            }
+        }
+        (InstructionSet::Arm, _) => {
+            let word = u32::from_le_bytes(bytes.try_into().ok()?);
+            let is_data_processing = word & 0x0c000000 == 0 && (word >> 21) & 0xc != 0x8;
+            let is_load = word & 0x0c100000 == 0x04100000;
+            (is_data_processing || is_load).then_some(((word >> 12) & 0xf) as u8)
+        }
+        (InstructionSet::Arm64, _) => {
+            let word = u32::from_le_bytes(bytes.try_into().ok()?);
+            // data processing (immediate and register) writes rd, loads rt
+            let is_data_processing =
+                word & 0x1c000000 == 0x10000000 || word & 0x0e000000 == 0x0a000000;
+            let is_load = word & 0x0a000000 == 0x08000000 && word & 0x00400000 != 0;
+            (is_data_processing || is_load).then_some((word & 0x1f) as u8)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arm(word: u32) -> Operation {
+        decode_arm(word, 0x1000).operation
+    }
+
+    fn thumb16(half: u16) -> Operation {
+        decode_thumb16(half, 0x1002).operation
+    }
+
+    fn thumb32(first: u16, second: u16) -> Operation {
+        decode_thumb32(first, second, 0x1002).operation
+    }
+
+    fn arm64(word: u32) -> Operation {
+        decode_arm64(word, 0x1000).operation
+    }
+
+    #[test]
+    fn decode_arm_instructions() {
+        // mov r0, #1
+        assert_eq!(arm(0xe3a00001), Operation::MoveImmediate { rd: 0, imm: 1 });
+        // mvn r0, #0
+        assert_eq!(
+            arm(0xe3e00000),
+            Operation::MoveImmediate {
+                rd: 0,
+                imm: 0xffffffff
+            }
+        );
+        // movw r0, #0x1234 / movt r0, #0x5678
+        assert_eq!(
+            arm(0xe3010234),
+            Operation::MoveImmediate { rd: 0, imm: 0x1234 }
+        );
+        assert_eq!(
+            arm(0xe3450678),
+            Operation::MoveKeep {
+                rd: 0,
+                imm: 0x5678,
+                shift: 16
+            }
+        );
+        // mov r1, r0
+        assert_eq!(arm(0xe1a01000), Operation::MoveRegister { rd: 1, rm: 0 });
+        // ldr r0, [r0, #0x28] / str r1, [r0, #-8]
+        assert_eq!(
+            arm(0xe5900028),
+            Operation::Load {
+                rt: 0,
+                rn: 0,
+                offset: 0x28,
+                size: 4
+            }
+        );
+        assert_eq!(
+            arm(0xe5001008),
+            Operation::Store {
+                rt: 1,
+                rn: 0,
+                offset: -8,
+                size: 4
+            }
+        );
+        // ldr r0, [pc, #8] / adr r0, #8, the pc reads 8 bytes ahead
+        assert_eq!(
+            arm(0xe59f0008),
+            Operation::LoadLiteral {
+                rt: 0,
+                addr: 0x1010,
+                size: 4
+            }
+        );
+        assert_eq!(
+            arm(0xe28f0008),
+            Operation::Address {
+                rd: 0,
+                addr: 0x1010
+            }
+        );
+        // bl 0x2000
+        assert_eq!(
+            arm(0xeb0003fe),
+            Operation::Branch {
+                target: 0x2000,
+                link: true
+            }
+        );
+        // blx r3
+        assert_eq!(
+            arm(0xe12fff33),
+            Operation::BranchRegister { rm: 3, link: true }
+        );
+        // bx lr / pop {r4, pc}
+        assert_eq!(arm(0xe12fff1e), Operation::Return);
+        assert_eq!(arm(0xe8bd8010), Operation::Return);
+
+        // moveq r0, #0
+        let instruction = decode_arm(0x03a00000, 0x1000);
+        assert!(instruction.conditional);
+        assert_eq!(
+            instruction.operation,
+            Operation::MoveImmediate { rd: 0, imm: 0 }
+        );
+    }
+
+    #[test]
+    fn decode_thumb_instructions() {
+        // movs r0, #1 / adds r0, #0x51
+        assert_eq!(thumb16(0x2001), Operation::MoveImmediate { rd: 0, imm: 1 });
+        assert_eq!(
+            thumb16(0x3051),
+            Operation::AddImmediate {
+                rd: 0,
+                rn: 0,
+                imm: 0x51
+            }
+        );
+        // mov r4, r0
+        assert_eq!(thumb16(0x4604), Operation::MoveRegister { rd: 4, rm: 0 });
+        // ldr r0, [r0, #0x24] / str r1, [r0, #4]
+        assert_eq!(
+            thumb16(0x6a40),
+            Operation::Load {
+                rt: 0,
+                rn: 0,
+                offset: 0x24,
+                size: 4
+            }
+        );
+        assert_eq!(
+            thumb16(0x6041),
+            Operation::Store {
+                rt: 1,
+                rn: 0,
+                offset: 4,
+                size: 4
+            }
+        );
+        // ldr r0, [pc, #8], the pc is word aligned
+        assert_eq!(
+            thumb16(0x4802),
+            Operation::LoadLiteral {
+                rt: 0,
+                addr: 0x100c,
+                size: 4
+            }
+        );
+        // bx lr / pop {r4, pc}
+        assert_eq!(thumb16(0x4770), Operation::Return);
+        assert_eq!(thumb16(0xbd10), Operation::Return);
+
+        // bl 0x2000, thumb targets keep the thumb bit
+        assert_eq!(
+            thumb32(0xf000, 0xfffd),
+            Operation::Branch {
+                target: 0x2001,
+                link: true
+            }
+        );
+        // movw r0, #0x1034
+        assert_eq!(
+            thumb32(0xf241, 0x0034),
+            Operation::MoveImmediate { rd: 0, imm: 0x1034 }
+        );
+        // ldr.w r0, [r0, #0x140] / ldrb.w r0, [r0, #0x30] / str.w r1, [r0, #0x118]
+        assert_eq!(
+            thumb32(0xf8d0, 0x0140),
+            Operation::Load {
+                rt: 0,
+                rn: 0,
+                offset: 0x140,
+                size: 4
+            }
+        );
+        assert_eq!(
+            thumb32(0xf890, 0x0030),
+            Operation::Load {
+                rt: 0,
+                rn: 0,
+                offset: 0x30,
+                size: 1
+            }
+        );
+        assert_eq!(
+            thumb32(0xf8c0, 0x1118),
+            Operation::Store {
+                rt: 1,
+                rn: 0,
+                offset: 0x118,
+                size: 4
+            }
+        );
+    }
+
+    #[test]
+    fn decode_arm64_instructions() {
+        // mov w0, #1 / mov x0, #0x3f0 (movz) / orr x0, xzr, #0xff
+        assert_eq!(
+            arm64(0x52800020),
+            Operation::MoveImmediate { rd: 0, imm: 1 }
+        );
+        assert_eq!(
+            arm64(0xd2807e00),
+            Operation::MoveImmediate { rd: 0, imm: 0x3f0 }
+        );
+        assert_eq!(
+            arm64(0xb2401fe0),
+            Operation::MoveImmediate { rd: 0, imm: 0xff }
+        );
+        // movk x0, #0x1234, lsl #16
+        assert_eq!(
+            arm64(0xf2a24680),
+            Operation::MoveKeep {
+                rd: 0,
+                imm: 0x1234,
+                shift: 16
+            }
+        );
+        // add x0, x1, #0x10 / sub x0, x1, #0x10 / mov x0, sp
+        assert_eq!(
+            arm64(0x91004020),
+            Operation::AddImmediate {
+                rd: 0,
+                rn: 1,
+                imm: 0x10
+            }
+        );
+        assert_eq!(
+            arm64(0xd1004020),
+            Operation::AddImmediate {
+                rd: 0,
+                rn: 1,
+                imm: -0x10
+            }
+        );
+        assert_eq!(arm64(0x910003e0), Operation::MoveRegister { rd: 0, rm: 31 });
+        // adrp x8, 0x11000
+        assert_eq!(
+            arm64(0x90000088),
+            Operation::Address {
+                rd: 8,
+                addr: 0x11000
+            }
+        );
+        // ldr x0, [x0, #0x38] / ldur w1, [x0, #-4] / str x8, [x0]
+        assert_eq!(
+            arm64(0xf9401c00),
+            Operation::Load {
+                rt: 0,
+                rn: 0,
+                offset: 0x38,
+                size: 8
+            }
+        );
+        assert_eq!(
+            arm64(0xb85fc001),
+            Operation::Load {
+                rt: 1,
+                rn: 0,
+                offset: -4,
+                size: 4
+            }
+        );
+        assert_eq!(
+            arm64(0xf9000008),
+            Operation::Store {
+                rt: 8,
+                rn: 0,
+                offset: 0,
+                size: 8
+            }
+        );
+        // bl 0x1040 / cbz w0, 0x1008 / b.ne 0xff8
+        assert_eq!(
+            arm64(0x94000010),
+            Operation::Branch {
+                target: 0x1040,
+                link: true
+            }
+        );
+        assert_eq!(
+            arm64(0x34000040),
+            Operation::ConditionalBranch { target: 0x1008 }
+        );
+        assert_eq!(
+            arm64(0x54ffffc1),
+            Operation::ConditionalBranch { target: 0xff8 }
+        );
+        // br x16 / blr x8 / ret
+        assert_eq!(
+            arm64(0xd61f0200),
+            Operation::BranchRegister {
+                rm: 16,
+                link: false
+            }
+        );
+        assert_eq!(
+            arm64(0xd63f0100),
+            Operation::BranchRegister { rm: 8, link: true }
+        );
+        assert_eq!(arm64(0xd65f03c0), Operation::Return);
+    }
+
+    #[test]
+    fn decode_arm64_mov_from_zero_register() {
+        // mov w0, wzr
+        let instruction = decode_arm64(0x2a1f03e0, 0x1000);
+        assert_eq!(
+            instruction.operation,
+            Operation::MoveImmediate { rd: 0, imm: 0 }
+        );
+
+        // mov x0, x1
+        let instruction = decode_arm64(0xaa0103e0, 0x1000);
+        assert_eq!(
+            instruction.operation,
+            Operation::MoveRegister { rd: 0, rm: 1 }
+        );
+    }
+}
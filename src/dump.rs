@@ -0,0 +1,579 @@
+use crate::library;
+use elf::{abi, endian::LittleEndian, segment::ProgramHeader};
+use std::collections::HashMap;
+
+/// Rebuilds an ELF file from the memory image of a library dumped from a
+/// process, `base` being the address the dump starts at (the first mapping
+/// of the library). The sections, symbols and relocations come from the
+/// on-disk `companion` when given, otherwise from the dynamic segment of the
+/// dump. The dynamic linker's work is undone so that the image reads like the
+/// file: pointers into the image are rebased to link-time addresses and
+/// pointers to imported symbols are back to their addend.
+pub fn rebuild_elf(dump: &[u8], base: u64, companion: Option<&[u8]>) -> Result<Vec<u8>, String> {
+    let header = companion.unwrap_or(dump);
+    let elf = elf::ElfBytes::<LittleEndian>::minimal_parse(header).map_err(|_| {
+        "the memory dump does not start with an ELF header, give the on-disk library with --symbols-from"
+            .to_string()
+    })?;
+    let is_64 = elf.ehdr.class == elf::file::Class::ELF64;
+    let segments: Vec<ProgramHeader> = elf
+        .segments()
+        .map(|segments| segments.iter().collect())
+        .unwrap_or_default();
+    let loads: Vec<&ProgramHeader> = segments
+        .iter()
+        .filter(|segment| segment.p_type == abi::PT_LOAD)
+        .collect();
+    if loads.is_empty() {
+        return Err("the memory dump has no PT_LOAD program headers".to_string());
+    }
+
+    let image = Image {
+        first_vaddr: loads
+            .iter()
+            .map(|segment| segment.p_vaddr)
+            .min()
+            .unwrap_or(0)
+            & !0xfff,
+        end_vaddr: loads
+            .iter()
+            .map(|segment| segment.p_vaddr + segment.p_memsz)
+            .max()
+            .unwrap_or(0),
+        base,
+        pointer_size: if is_64 { 8 } else { 4 },
+    };
+
+    let mut data = match companion {
+        Some(companion) => {
+            // the companion's own headers are kept, those of the dump can be
+            // wiped or missing
+            let headers_end = (elf.ehdr.e_phoff
+                + elf.ehdr.e_phnum as u64 * elf.ehdr.e_phentsize as u64)
+                .max(elf.ehdr.e_ehsize as u64);
+            let mut data = companion.to_vec();
+            for segment in &loads {
+                let skip = headers_end
+                    .saturating_sub(segment.p_offset)
+                    .min(segment.p_filesz);
+                let offset = (segment.p_offset + skip) as usize;
+                let memory = image.get_offset(segment.p_vaddr + skip)? as usize;
+                // the dump ends before this segment
+                if memory >= dump.len() {
+                    continue;
+                }
+                let size = ((segment.p_filesz - skip) as usize)
+                    .min(dump.len().saturating_sub(memory))
+                    .min(data.len().saturating_sub(offset));
+                data[offset..offset + size].copy_from_slice(&dump[memory..memory + size]);
+            }
+            data
+        }
+        None => {
+            let mut data = dump.to_vec();
+            image.map_segments(&mut data, &elf.ehdr, &segments)?;
+            image.add_sections(&mut data, is_64, &segments)?;
+            data
+        }
+    };
+
+    image.unrelocate(&mut data, companion);
+    Ok(data)
+}
+
+struct Image {
+    // link-time addresses covered by the PT_LOAD segments, from the start of
+    // the page of the first one
+    first_vaddr: u64,
+    end_vaddr: u64,
+    // the address `first_vaddr` was loaded at
+    base: u64,
+    pointer_size: usize,
+}
+
+impl Image {
+    /// The offset of a link-time address in the dump.
+    fn get_offset(&self, vaddr: u64) -> Result<u64, String> {
+        vaddr.checked_sub(self.first_vaddr).ok_or_else(|| {
+            format!(
+                "0x{:x} is before the first segment of the memory dump (0x{:x})",
+                vaddr, self.first_vaddr
+            )
+        })
+    }
+
+    fn get_bias(&self) -> u64 {
+        self.base.wrapping_sub(self.first_vaddr)
+    }
+
+    /// Dynamic entries hold link-time addresses, but some dynamic linkers
+    /// relocate them in place.
+    fn get_vaddr(&self, addr: u64) -> u64 {
+        if (self.first_vaddr..self.end_vaddr).contains(&addr) {
+            addr
+        } else {
+            addr.wrapping_sub(self.get_bias())
+        }
+    }
+
+    fn is_runtime_address(&self, value: u64) -> bool {
+        let bias = self.get_bias();
+        (self.first_vaddr.wrapping_add(bias)..self.end_vaddr.wrapping_add(bias)).contains(&value)
+    }
+
+    fn read_word(&self, data: &[u8], offset: usize) -> Option<u64> {
+        let mut bytes = [0u8; 8];
+        bytes[..self.pointer_size].copy_from_slice(data.get(offset..offset + self.pointer_size)?);
+        Some(u64::from_le_bytes(bytes))
+    }
+
+    fn write_word(&self, data: &mut [u8], offset: usize, value: u64) {
+        if let Some(word) = data.get_mut(offset..offset + self.pointer_size) {
+            word.copy_from_slice(&value.to_le_bytes()[..self.pointer_size]);
+        }
+    }
+
+    /// Points the program headers of the dump at the dump itself, where every
+    /// segment lies at its address.
+    fn map_segments(
+        &self,
+        data: &mut [u8],
+        ehdr: &elf::file::FileHeader<LittleEndian>,
+        segments: &[ProgramHeader],
+    ) -> Result<(), String> {
+        let is_64 = ehdr.class == elf::file::Class::ELF64;
+        for (index, segment) in segments.iter().enumerate() {
+            if segment.p_vaddr < self.first_vaddr {
+                continue;
+            }
+            let offset = self.get_offset(segment.p_vaddr)?;
+            let size = segment
+                .p_memsz
+                .min((data.len() as u64).saturating_sub(offset));
+            let header = ehdr.e_phoff as usize + index * ehdr.e_phentsize as usize;
+            if is_64 {
+                write_u64(data, header + 8, offset)?;
+                write_u64(data, header + 32, size)?;
+            } else {
+                write_u32(data, header + 4, offset as u32)?;
+                write_u32(data, header + 16, size as u32)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Section headers for the dynamic symbols, relocations and .dynamic,
+    /// found through the dynamic segment, and a .data.rel.ro covering the
+    /// PT_GNU_RELRO segment.
+    fn add_sections(
+        &self,
+        data: &mut Vec<u8>,
+        is_64: bool,
+        segments: &[ProgramHeader],
+    ) -> Result<(), String> {
+        let dynamic_segment = segments
+            .iter()
+            .find(|segment| segment.p_type == abi::PT_DYNAMIC)
+            .ok_or("the memory dump has no dynamic segment, give the on-disk library with --symbols-from")?;
+
+        let mut dynamic: HashMap<i64, u64> = HashMap::new();
+        let entry_size = 2 * self.pointer_size;
+        let start = self.get_offset(dynamic_segment.p_vaddr)? as usize;
+        for entry in (start..start + dynamic_segment.p_memsz as usize).step_by(entry_size) {
+            let (Some(tag), Some(value)) = (
+                self.read_word(data, entry),
+                self.read_word(data, entry + self.pointer_size),
+            ) else {
+                break;
+            };
+            if tag == abi::DT_NULL as u64 {
+                break;
+            }
+            // sign extend the 32-bit tags
+            let tag = if is_64 {
+                tag as i64
+            } else {
+                tag as u32 as i32 as i64
+            };
+            dynamic.entry(tag).or_insert(value);
+        }
+        let get_address = |tag: i64| dynamic.get(&tag).map(|addr| self.get_vaddr(*addr));
+
+        let symbol_size = if is_64 { 24 } else { 16 };
+        let (rel_size, rela_size) = if is_64 { (16, 24) } else { (8, 12) };
+        let symbol_count = get_address(abi::DT_HASH)
+            .and_then(|hash| read_u32(data, self.get_offset(hash).ok()? as usize + 4))
+            .or_else(|| {
+                get_address(abi::DT_GNU_HASH)
+                    .and_then(|hash| self.get_gnu_hash_symbol_count(data, hash))
+            });
+
+        // (name, type, address, size, link to the section at this index, entry size)
+        let mut sections: Vec<(&str, u32, u64, u64, u32, u64)> = Vec::new();
+        if let (Some(symtab), Some(strtab), Some(count)) = (
+            get_address(abi::DT_SYMTAB),
+            get_address(abi::DT_STRTAB),
+            symbol_count,
+        ) {
+            // .dynsym and .dynstr are sections 1 and 2
+            sections.push((
+                ".dynsym",
+                abi::SHT_DYNSYM,
+                symtab,
+                count as u64 * symbol_size,
+                2,
+                symbol_size,
+            ));
+            sections.push((
+                ".dynstr",
+                abi::SHT_STRTAB,
+                strtab,
+                dynamic.get(&abi::DT_STRSZ).copied().unwrap_or(0),
+                0,
+                0,
+            ));
+        } else {
+            return Err("the dynamic segment of the memory dump has no symbol table".to_string());
+        }
+        if let Some(rel) = get_address(abi::DT_REL) {
+            sections.push((
+                ".rel.dyn",
+                abi::SHT_REL,
+                rel,
+                dynamic.get(&abi::DT_RELSZ).copied().unwrap_or(0),
+                1,
+                rel_size,
+            ));
+        }
+        if let Some(rela) = get_address(abi::DT_RELA) {
+            sections.push((
+                ".rela.dyn",
+                abi::SHT_RELA,
+                rela,
+                dynamic.get(&abi::DT_RELASZ).copied().unwrap_or(0),
+                1,
+                rela_size,
+            ));
+        }
+        if let Some(jmprel) = get_address(abi::DT_JMPREL) {
+            let size = dynamic.get(&abi::DT_PLTRELSZ).copied().unwrap_or(0);
+            if dynamic.get(&abi::DT_PLTREL) == Some(&(abi::DT_RELA as u64)) {
+                sections.push((".rela.plt", abi::SHT_RELA, jmprel, size, 1, rela_size));
+            } else {
+                sections.push((".rel.plt", abi::SHT_REL, jmprel, size, 1, rel_size));
+            }
+        }
+        sections.push((
+            ".dynamic",
+            abi::SHT_DYNAMIC,
+            dynamic_segment.p_vaddr,
+            dynamic_segment.p_memsz,
+            2,
+            entry_size as u64,
+        ));
+        if let Some(relro) = segments
+            .iter()
+            .find(|segment| segment.p_type == abi::PT_GNU_RELRO)
+        {
+            sections.push((
+                ".data.rel.ro",
+                abi::SHT_PROGBITS,
+                relro.p_vaddr,
+                relro.p_memsz,
+                0,
+                0,
+            ));
+        }
+
+        let mut names = vec![0u8];
+        let mut name_offsets = Vec::new();
+        for name in sections
+            .iter()
+            .map(|section| section.0)
+            .chain([".shstrtab"])
+        {
+            name_offsets.push(names.len() as u32);
+            names.extend(name.as_bytes());
+            names.push(0);
+        }
+        let names_offset = data.len() as u64;
+        data.extend(&names);
+        while !data.len().is_multiple_of(8) {
+            data.push(0);
+        }
+
+        let section_offset = data.len() as u64;
+        let header_size = if is_64 { 64 } else { 40 };
+        data.extend(std::iter::repeat_n(0u8, header_size));
+        for (index, (_, section_type, addr, size, link, entry_size)) in sections.iter().enumerate()
+        {
+            let offset = self.get_offset(*addr)?;
+            push_section_header(
+                data,
+                is_64,
+                name_offsets[index],
+                *section_type,
+                abi::SHF_ALLOC as u64,
+                *addr,
+                offset,
+                *size,
+                *link,
+                *entry_size,
+            );
+        }
+        push_section_header(
+            data,
+            is_64,
+            name_offsets[sections.len()],
+            abi::SHT_STRTAB,
+            0,
+            0,
+            names_offset,
+            names.len() as u64,
+            0,
+            0,
+        );
+
+        let section_count = sections.len() as u16 + 2;
+        if is_64 {
+            write_u64(data, 0x28, section_offset)?;
+            write_u16(data, 0x3a, header_size as u16)?;
+            write_u16(data, 0x3c, section_count)?;
+            write_u16(data, 0x3e, section_count - 1)?;
+        } else {
+            write_u32(data, 0x20, section_offset as u32)?;
+            write_u16(data, 0x2e, header_size as u16)?;
+            write_u16(data, 0x30, section_count)?;
+            write_u16(data, 0x32, section_count - 1)?;
+        }
+        Ok(())
+    }
+
+    /// DT_GNU_HASH only gives the number of symbols through the end of the
+    /// longest hash chain.
+    fn get_gnu_hash_symbol_count(&self, data: &[u8], hash: u64) -> Option<u32> {
+        let start = self.get_offset(hash).ok()? as usize;
+        let bucket_count = read_u32(data, start)? as usize;
+        let symbol_offset = read_u32(data, start + 4)?;
+        let bloom_size = read_u32(data, start + 8)? as usize;
+        let buckets = start + 16 + bloom_size * self.pointer_size;
+        let chains = buckets + 4 * bucket_count;
+
+        let last_bucket = (0..bucket_count)
+            .filter_map(|index| read_u32(data, buckets + 4 * index))
+            .max()
+            .filter(|symbol| *symbol >= symbol_offset)?;
+        let mut symbol = last_bucket;
+        // the last entry of a chain has the lowest bit set
+        while read_u32(data, chains + 4 * (symbol - symbol_offset) as usize)? & 1 == 0 {
+            symbol += 1;
+        }
+        Some(symbol + 1)
+    }
+
+    /// Rebases the pointers into the image found in the writable segments and
+    /// puts the addend back into the words relocated against imported
+    /// symbols. Relative relocations, packed or not, are undone by value.
+    fn unrelocate(&self, data: &mut [u8], companion: Option<&[u8]>) {
+        let segments: Vec<ProgramHeader> = elf::ElfBytes::<LittleEndian>::minimal_parse(data)
+            .ok()
+            .and_then(|elf| elf.segments().map(|segments| segments.iter().collect()))
+            .unwrap_or_default();
+        let symbols = library::get_dynamic_symbols(data);
+        let file_offsets: Vec<(u64, u64, u64)> = segments
+            .iter()
+            .filter(|segment| segment.p_type == abi::PT_LOAD)
+            .map(|segment| (segment.p_vaddr, segment.p_offset, segment.p_filesz))
+            .collect();
+        let get_file_offset = |vaddr: u64| {
+            file_offsets
+                .iter()
+                .find(|(start, _, size)| (*start..start + size).contains(&vaddr))
+                .map(|(start, offset, _)| (offset + vaddr - start) as usize)
+        };
+
+        // (file offset, symbol, addend) of the words relocated against imports
+        let mut imports: Vec<(usize, &str, Option<i64>)> = Vec::new();
+        for relocation in library::get_relocations(data) {
+            let Some((name, symbol)) = symbols.get(relocation.symbol as usize) else {
+                continue;
+            };
+            if relocation.symbol == 0 || !symbol.is_undefined() {
+                continue;
+            }
+            if let Some(offset) = get_file_offset(relocation.offset as u64) {
+                imports.push((offset, name, relocation.addend));
+            }
+        }
+
+        // without the file, REL addends are lost: the lowest value stored for
+        // a symbol is taken as the symbol itself, or as the address point for
+        // the vtables of the typeinfos
+        let mut lowest_values: HashMap<&str, u64> = HashMap::new();
+        for (offset, name, _) in &imports {
+            if let Some(value) = self.read_word(data, *offset) {
+                let lowest = lowest_values.entry(name).or_insert(value);
+                *lowest = (*lowest).min(value);
+            }
+        }
+        let writes: Vec<(usize, u64)> = imports
+            .iter()
+            .filter_map(|(offset, name, addend)| {
+                let value = match (addend, companion) {
+                    (Some(addend), _) => *addend as u64,
+                    (None, Some(companion)) => self.read_word(companion, *offset)?,
+                    (None, None) => {
+                        let address_point = if name.starts_with("_ZTV") {
+                            2 * self.pointer_size as u64
+                        } else {
+                            0
+                        };
+                        self.read_word(data, *offset)? - lowest_values[name] + address_point
+                    }
+                };
+                Some((*offset, value))
+            })
+            .collect();
+
+        let bias = self.get_bias();
+        for segment in segments
+            .iter()
+            .filter(|segment| segment.p_type == abi::PT_LOAD && segment.p_flags & abi::PF_W != 0)
+        {
+            let start = segment.p_offset as usize;
+            let end = start
+                .saturating_add(segment.p_filesz as usize)
+                .min(data.len());
+            for offset in (start..end).step_by(self.pointer_size) {
+                if let Some(value) = self.read_word(data, offset)
+                    && self.is_runtime_address(value)
+                {
+                    self.write_word(data, offset, value.wrapping_sub(bias));
+                }
+            }
+        }
+
+        for (offset, value) in writes {
+            self.write_word(data, offset, value);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn push_section_header(
+    data: &mut Vec<u8>,
+    is_64: bool,
+    name: u32,
+    section_type: u32,
+    flags: u64,
+    addr: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    entry_size: u64,
+) {
+    let alignment = if entry_size == 0 {
+        1
+    } else {
+        entry_size.min(8)
+    };
+    data.extend(name.to_le_bytes());
+    data.extend(section_type.to_le_bytes());
+    if is_64 {
+        for value in [flags, addr, offset, size] {
+            data.extend(value.to_le_bytes());
+        }
+        data.extend(link.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        data.extend(alignment.to_le_bytes());
+        data.extend(entry_size.to_le_bytes());
+    } else {
+        for value in [flags, addr, offset, size] {
+            data.extend((value as u32).to_le_bytes());
+        }
+        data.extend(link.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        data.extend((alignment as u32).to_le_bytes());
+        data.extend((entry_size as u32).to_le_bytes());
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn write_u16(data: &mut [u8], offset: usize, value: u16) -> Result<(), String> {
+    write_bytes(data, offset, &value.to_le_bytes())
+}
+
+fn write_u32(data: &mut [u8], offset: usize, value: u32) -> Result<(), String> {
+    write_bytes(data, offset, &value.to_le_bytes())
+}
+
+fn write_u64(data: &mut [u8], offset: usize, value: u64) -> Result<(), String> {
+    write_bytes(data, offset, &value.to_le_bytes())
+}
+
+/// Headers are patched in place, a dump cut inside them cannot be rebuilt.
+fn write_bytes(data: &mut [u8], offset: usize, bytes: &[u8]) -> Result<(), String> {
+    data.get_mut(offset..offset + bytes.len())
+        .ok_or_else(|| format!("the memory dump ends before its headers, at 0x{:x}", offset))?
+        .copy_from_slice(bytes);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_elf::TestElf;
+
+    const BASE: u64 = 0x7a00_0000_0000;
+
+    /// An AArch64 library with a pointer to its code in a writable segment,
+    /// and the address of that pointer.
+    fn build_library() -> (Vec<u8>, u32, u32) {
+        let mut elf = TestElf::arm64();
+        let function = elf.add_code(&[0xd65f03c0]);
+        elf.add_symbol("_ZN3Foo3runEv", function, 4);
+        let pointer = elf.add_section(".data", &(function as u64).to_le_bytes());
+        let mut image = elf.build();
+        // PF_R | PF_W | PF_X for the only PT_LOAD
+        image[0x44..0x48].copy_from_slice(&7u32.to_le_bytes());
+        (image, function, pointer)
+    }
+
+    #[test]
+    fn companion_headers_and_rebased_pointers() {
+        let (library, function, pointer) = build_library();
+        let mut dump = library.clone();
+        // the headers of the dump are wiped, the pointer relocated
+        dump[..0x40].fill(0);
+        let pointer = pointer as usize;
+        dump[pointer..pointer + 8].copy_from_slice(&(BASE + function as u64).to_le_bytes());
+
+        assert_eq!(
+            rebuild_elf(&dump, BASE, Some(&library)),
+            Ok(library.clone())
+        );
+        // a dump ending early only replaces what it holds
+        assert_eq!(
+            rebuild_elf(&dump[..0x80], BASE, Some(&library)),
+            Ok(library)
+        );
+    }
+
+    #[test]
+    fn dumps_that_cannot_be_rebuilt() {
+        let (library, ..) = build_library();
+
+        assert!(rebuild_elf(&[0; 4], BASE, None).is_err());
+        assert!(rebuild_elf(&library[4..], BASE, None).is_err());
+        // nothing but the segments, sections need the dynamic segment
+        assert_eq!(
+            rebuild_elf(&library, BASE, None),
+            Err("the memory dump has no dynamic segment, give the on-disk library with --symbols-from".to_string())
+        );
+    }
+}
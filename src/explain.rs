@@ -0,0 +1,367 @@
+use crate::class_index::demangle;
+use crate::library::Library;
+use crate::rtti::{BaseClassInfo, TypeInfoKind};
+
+// __vmi_class_type_info::__flags
+const NON_DIAMOND_REPEAT_MASK: u32 = 0x1;
+const DIAMOND_SHAPED_MASK: u32 = 0x2;
+
+/// A word of a vtable or typeinfo and what it is read as.
+struct Word {
+    address: u32,
+    size: u32,
+    value: Option<u64>,
+    meaning: String,
+}
+
+struct Record {
+    title: String,
+    words: Vec<Word>,
+}
+
+/// Dumps the words of the vtable groups at `vtable_addr` and of the typeinfo
+/// of the class and of all its bases, each with what reimu reads it as.
+pub fn explain_class(library: &Library, vtable_addr: u32) -> String {
+    let mut records = vec![explain_vtable(library, vtable_addr)];
+
+    let mut pending = vec![library.get_vtable_typeinfo(vtable_addr)];
+    let mut visited = Vec::new();
+    while let Some(typeinfo) = pending.pop() {
+        if visited.contains(&typeinfo) {
+            continue;
+        }
+        visited.push(typeinfo);
+
+        records.push(explain_typeinfo(library, typeinfo));
+        if let Some(typeinfo_info) = library.get_typeinfo(typeinfo) {
+            // depth first, in the order of the bases, the ones imported from a
+            // library that is not loaded are left at 0
+            pending.extend(
+                typeinfo_info
+                    .bases
+                    .iter()
+                    .rev()
+                    .map(|base| base.typeinfo)
+                    .filter(|base| *base != 0),
+            );
+        }
+    }
+
+    records
+        .iter()
+        .map(|record| record.get_display(library.pointer_size))
+        .collect::<Vec<String>>()
+        .join("\n\n")
+}
+
+fn explain_vtable(library: &Library, vtable_addr: u32) -> Record {
+    let pointer_size = library.pointer_size;
+    let symbol = library.addr_to_sym.get(&vtable_addr);
+    let mut words = Vec::new();
+    let mut addr = vtable_addr;
+
+    for (group, (group_address, offset_to_top, functions)) in library
+        .get_vtable_groups(vtable_addr)
+        .into_iter()
+        .enumerate()
+    {
+        // the vbase and vcall offsets of the virtual bases come before the
+        // group they belong to
+        while addr < group_address {
+            let offset = library.read_signed_pointer(addr).unwrap_or(0);
+            words.push(read_word(
+                library,
+                addr,
+                pointer_size,
+                format!("vcall or vbase offset {}", offset),
+            ));
+            addr += pointer_size;
+        }
+
+        words.push(read_word(
+            library,
+            addr,
+            pointer_size,
+            format!("group {}: offset-to-top {}", group, offset_to_top),
+        ));
+        addr += pointer_size;
+
+        let typeinfo = library.read_pointer(addr).unwrap_or(0);
+        words.push(read_word(
+            library,
+            addr,
+            pointer_size,
+            format!("typeinfo -> {}", get_typeinfo_name(library, typeinfo)),
+        ));
+        addr += pointer_size;
+
+        for (slot, function) in functions.iter().enumerate() {
+            words.push(read_word(
+                library,
+                addr,
+                pointer_size,
+                format!(
+                    "slot {} (vptr+0x{:x}): {}",
+                    slot,
+                    slot as u32 * pointer_size,
+                    get_function_name(library, *function)
+                ),
+            ));
+            addr += pointer_size;
+        }
+    }
+
+    // the word that ended the last group, to see why reimu stopped there
+    let next = library.read_pointer(addr).unwrap_or(0);
+    words.push(read_word(
+        library,
+        addr,
+        pointer_size,
+        format!(
+            "end of the vtable: {}",
+            if next == 0 {
+                "0".to_string()
+            } else if library.get_typeinfo_kind(addr) != TypeInfoKind::Unknown {
+                "points into a cxxabi typeinfo vtable, the typeinfo that follows".to_string()
+            } else {
+                format!("followed by {}", get_address_name(library, next))
+            }
+        ),
+    ));
+
+    let size = match library.get_symbol_size(vtable_addr) {
+        Some(size) if size != addr - vtable_addr => {
+            format!(", st_size 0x{:x} but 0x{:x} read", size, addr - vtable_addr)
+        }
+        Some(size) => format!(", st_size 0x{:x}", size),
+        None => String::new(),
+    };
+
+    Record {
+        title: format!(
+            "{} at 0x{:x}{}",
+            symbol.map_or("vtable".to_string(), |symbol| symbol.clone()),
+            vtable_addr,
+            size
+        ),
+        words,
+    }
+}
+
+fn explain_typeinfo(library: &Library, typeinfo: u32) -> Record {
+    let pointer_size = library.pointer_size;
+    let kind = library.get_typeinfo_kind(typeinfo);
+    let mut words = vec![read_word(
+        library,
+        typeinfo,
+        pointer_size,
+        match kind {
+            TypeInfoKind::Class => "vptr -> __cxxabiv1::__class_type_info".to_string(),
+            TypeInfoKind::SingleInheritance => {
+                "vptr -> __cxxabiv1::__si_class_type_info".to_string()
+            }
+            TypeInfoKind::VirtualMultipleInheritance => {
+                "vptr -> __cxxabiv1::__vmi_class_type_info".to_string()
+            }
+            TypeInfoKind::Unknown => "vptr, not into a cxxabi typeinfo vtable".to_string(),
+        },
+    )];
+
+    let name_addr = typeinfo + pointer_size;
+    let name = library
+        .read_pointer(name_addr)
+        .and_then(|name| library.read_cstr(name));
+    words.push(read_word(
+        library,
+        name_addr,
+        pointer_size,
+        match &name {
+            Some(name) => format!("name -> {:?}", name),
+            None => "name, unreadable".to_string(),
+        },
+    ));
+
+    let fields = typeinfo + 2 * pointer_size;
+    match kind {
+        TypeInfoKind::Class => {}
+        TypeInfoKind::SingleInheritance => {
+            let base = library.read_pointer(fields).unwrap_or(0);
+            words.push(read_word(
+                library,
+                fields,
+                pointer_size,
+                format!("base -> {}", get_reference_name(library, fields, base)),
+            ));
+        }
+        TypeInfoKind::VirtualMultipleInheritance => {
+            let flags = library.read_u32(fields).unwrap_or(0);
+            let mut flag_names = Vec::new();
+            if flags & NON_DIAMOND_REPEAT_MASK != 0 {
+                flag_names.push("non-diamond repeat");
+            }
+            if flags & DIAMOND_SHAPED_MASK != 0 {
+                flag_names.push("diamond shaped");
+            }
+            words.push(read_word(
+                library,
+                fields,
+                4,
+                if flag_names.is_empty() {
+                    format!("flags 0x{:x}", flags)
+                } else {
+                    format!("flags 0x{:x} ({})", flags, flag_names.join(", "))
+                },
+            ));
+
+            let base_count = library.read_u32(fields + 4).unwrap_or(0);
+            words.push(read_word(
+                library,
+                fields + 4,
+                4,
+                format!("base count {}", base_count),
+            ));
+
+            // a corrupt count would run through the rest of the section
+            let mut addr = fields + 8;
+            for index in 0..base_count.min(64) {
+                let (Some(base), Some(offset_flags)) = (
+                    library.read_pointer(addr),
+                    library.read_signed_pointer(addr + pointer_size),
+                ) else {
+                    break;
+                };
+                words.push(read_word(
+                    library,
+                    addr,
+                    pointer_size,
+                    format!(
+                        "base {} -> {}",
+                        index,
+                        get_reference_name(library, addr, base)
+                    ),
+                ));
+
+                let base_info = BaseClassInfo::new(base, offset_flags as i64);
+                words.push(read_word(
+                    library,
+                    addr + pointer_size,
+                    pointer_size,
+                    format!(
+                        "offset flags: {} {}{}",
+                        if base_info.is_virtual {
+                            "vbase offset at vptr"
+                        } else {
+                            "offset"
+                        },
+                        format_offset(base_info.offset),
+                        match (base_info.is_public, base_info.is_virtual) {
+                            (true, true) => ", public, virtual",
+                            (true, false) => ", public",
+                            (false, true) => ", virtual",
+                            (false, false) => "",
+                        }
+                    ),
+                ));
+                addr += 2 * pointer_size;
+            }
+        }
+        TypeInfoKind::Unknown => {
+            for index in 0..2 {
+                words.push(read_word(
+                    library,
+                    fields + index * pointer_size,
+                    pointer_size,
+                    "?".to_string(),
+                ));
+            }
+        }
+    }
+
+    Record {
+        title: format!(
+            "{} at 0x{:x}",
+            get_typeinfo_name(library, typeinfo),
+            typeinfo
+        ),
+        words,
+    }
+}
+
+fn read_word(library: &Library, address: u32, size: u32, meaning: String) -> Word {
+    let value = library.get_file_offset(address).and_then(|offset| {
+        let bytes = library
+            .data
+            .get(offset as usize..(offset + size) as usize)?;
+        let mut value = [0u8; 8];
+        value[..bytes.len()].copy_from_slice(bytes);
+        Some(u64::from_le_bytes(value))
+    });
+
+    Word {
+        address,
+        size,
+        value,
+        meaning,
+    }
+}
+
+fn get_typeinfo_name(library: &Library, typeinfo: u32) -> String {
+    match library.get_typeinfo(typeinfo) {
+        Some(typeinfo_info) => format!(
+            "typeinfo for {}",
+            demangle(&typeinfo_info.name).unwrap_or(typeinfo_info.name)
+        ),
+        None => get_address_name(library, typeinfo),
+    }
+}
+
+/// The typeinfo the word at `addr` points at, or the symbol it imports.
+fn get_reference_name(library: &Library, addr: u32, typeinfo: u32) -> String {
+    match library.unresolved_imports.get(&addr) {
+        Some(symbol) => format!("{}, imported from a library that is not loaded", symbol),
+        None => get_typeinfo_name(library, typeinfo),
+    }
+}
+
+fn get_function_name(library: &Library, function: u32) -> String {
+    let symbol = library.get_symbol_name(function);
+    demangle(&symbol).unwrap_or(symbol)
+}
+
+/// The symbol at or around `addr`, or the address itself.
+fn get_address_name(library: &Library, addr: u32) -> String {
+    match library.get_enclosing_symbol(addr) {
+        Some((symbol, 0)) => symbol.clone(),
+        Some((symbol, offset)) => format!("{}+0x{:x}", symbol, offset),
+        None => format!("0x{:x}", addr),
+    }
+}
+
+fn format_offset(offset: i32) -> String {
+    match offset {
+        offset if offset < 0 => format!("-0x{:x}", -(offset as i64)),
+        offset => format!("0x{:x}", offset),
+    }
+}
+
+impl Record {
+    fn get_display(&self, pointer_size: u32) -> String {
+        let mut lines = vec![self.title.clone()];
+
+        for word in &self.words {
+            let value = match word.value {
+                Some(value) => format!("{:0width$x}", value, width = 2 * word.size as usize),
+                None => "?".repeat(2 * word.size as usize),
+            };
+            lines.push(format!(
+                "    0x{:08x}  {:<width$}  {}",
+                word.address,
+                value,
+                word.meaning,
+                width = 2 * pointer_size as usize
+            ));
+        }
+
+        lines.join("\n")
+    }
+}
@@ -0,0 +1,349 @@
+use crate::class_index::{demangle, normalize_class_name};
+use crate::disasm::{self, Operation};
+use crate::library::Library;
+use crate::overrides::split_method;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+// a getter or setter is a single load or store and a return
+const MAX_ACCESSOR_SIZE: u32 = 0x10;
+
+#[derive(Serialize, Clone)]
+pub struct Field {
+    /// offset from `this` of the class the accessors belong to, or from the
+    /// start of the object for `ClassFields::get_object_fields`
+    pub offset: u32,
+    pub size: u8,
+    pub type_name: String,
+    pub name: String,
+    /// the getters and setters reading or writing the field
+    pub accessors: Vec<String>,
+}
+
+enum AccessorKind {
+    Getter,
+    Setter,
+}
+
+struct Accessor {
+    kind: AccessorKind,
+    offset: u32,
+    size: u8,
+}
+
+// (accessor, unqualified signature, demangled symbol) of the functions
+// accessing one offset
+type OffsetAccessors = Vec<(Accessor, String, String)>;
+
+/// Data members recovered from the `CC_SYNTHESIZE` style getters and setters
+/// of every class, indexed by normalized class name.
+pub struct ClassFields {
+    fields: HashMap<String, Vec<Field>>,
+}
+
+impl ClassFields {
+    pub fn new(library: &Library) -> Self {
+        // class name -> offset -> accessors
+        let mut accessors: HashMap<String, BTreeMap<u32, OffsetAccessors>> = HashMap::new();
+
+        for (symbol, addr) in &library.sym_to_addr {
+            if *addr == 0 || !symbol.starts_with("_ZN") {
+                continue;
+            }
+            if library
+                .get_symbol_size(*addr)
+                .is_none_or(|size| size > MAX_ACCESSOR_SIZE)
+            {
+                continue;
+            }
+            let Some(accessor) = get_accessor(library, *addr) else {
+                continue;
+            };
+            let Some(demangled) = demangle(symbol) else {
+                continue;
+            };
+            let Some((class_name, signature)) = split_method(&demangled) else {
+                continue;
+            };
+
+            accessors
+                .entry(class_name)
+                .or_default()
+                .entry(accessor.offset)
+                .or_default()
+                .push((accessor, signature, demangled));
+        }
+
+        let fields = accessors
+            .into_iter()
+            .map(|(class_name, offsets)| {
+                let mut fields: Vec<Field> = offsets
+                    .into_iter()
+                    .filter_map(|(offset, accessors)| get_field(offset, accessors))
+                    .collect();
+                disambiguate_names(&mut fields);
+                (normalize_class_name(&class_name), fields)
+            })
+            .collect();
+
+        Self { fields }
+    }
+
+    /// The fields accessed by the class's own member functions, sorted by offset.
+    pub fn get(&self, class_name: &str) -> &[Field] {
+        self.fields
+            .get(&normalize_class_name(class_name))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// The fields of the class described by `typeinfo` and of its non-virtual
+    /// bases, placed at their offset in the object and sorted by it.
+    pub fn get_object_fields(&self, library: &Library, typeinfo: u32) -> Vec<Field> {
+        let mut fields: BTreeMap<u32, Field> = BTreeMap::new();
+        self.add_subobject_fields(library, typeinfo, 0, &mut fields, &mut Vec::new());
+        fields.into_values().collect()
+    }
+
+    fn add_subobject_fields(
+        &self,
+        library: &Library,
+        typeinfo: u32,
+        offset: i32,
+        output: &mut BTreeMap<u32, Field>,
+        path: &mut Vec<u32>,
+    ) {
+        let Some(typeinfo_info) = library.get_typeinfo(typeinfo) else {
+            return;
+        };
+        if path.contains(&typeinfo) {
+            return;
+        }
+
+        let class_name = demangle(&typeinfo_info.name).unwrap_or(typeinfo_info.name.clone());
+        // the most derived class is visited first and keeps its own view of
+        // an offset its bases also access
+        for field in self.get(&class_name) {
+            let Ok(field_offset) = u32::try_from(offset + field.offset as i32) else {
+                continue;
+            };
+            output.entry(field_offset).or_insert_with(|| Field {
+                offset: field_offset,
+                ..field.clone()
+            });
+        }
+
+        path.push(typeinfo);
+        for base in typeinfo_info.bases.iter().filter(|base| !base.is_virtual) {
+            self.add_subobject_fields(library, base.typeinfo, offset + base.offset, output, path);
+        }
+        path.pop();
+    }
+}
+
+/// Matches `ldr r0, [r0, #offset]; bx lr` and `str r1, [r0, #offset]; bx lr`
+/// (or their AArch64 and narrower counterparts). A setter of a `bool` can
+/// mask the argument before storing it.
+fn get_accessor(library: &Library, addr: u32) -> Option<Accessor> {
+    let instructions = disasm::decode_function(library, addr, MAX_ACCESSOR_SIZE);
+    if instructions
+        .iter()
+        .any(|instruction| instruction.conditional)
+    {
+        return None;
+    }
+
+    let (kind, offset, size) = match instructions
+        .iter()
+        .map(|instruction| &instruction.operation)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [
+            Operation::Load {
+                rt: 0,
+                rn: 0,
+                offset,
+                size,
+            },
+            Operation::Return,
+        ] => (AccessorKind::Getter, *offset, *size),
+        [
+            Operation::Store {
+                rt: 1,
+                rn: 0,
+                offset,
+                size,
+            },
+            Operation::Return,
+        ] => (AccessorKind::Setter, *offset, *size),
+        [
+            Operation::Other,
+            Operation::Store {
+                rt,
+                rn: 0,
+                offset,
+                size: 1,
+            },
+            Operation::Return,
+        ] if *rt != 0 => (AccessorKind::Setter, *offset, 1),
+        _ => return None,
+    };
+
+    Some(Accessor {
+        kind,
+        offset: u32::try_from(offset).ok()?,
+        size,
+    })
+}
+
+/// Combines the accessors of one offset: the type comes from the parameter
+/// of a setter when there is one, from the width of the access otherwise.
+fn get_field(offset: u32, mut accessors: OffsetAccessors) -> Option<Field> {
+    accessors.sort_by(|a, b| a.2.cmp(&b.2));
+    let setter = accessors
+        .iter()
+        .filter(|(accessor, _, _)| matches!(accessor.kind, AccessorKind::Setter))
+        .find_map(|(accessor, signature, _)| {
+            get_setter_parameter(signature).map(|type_name| (accessor.size, type_name))
+        });
+
+    let (_, signature, _) = accessors.first()?;
+    let name = get_field_name(signature);
+    let (size, type_name) = setter.unwrap_or_else(|| {
+        let size = accessors
+            .iter()
+            .map(|(accessor, _, _)| accessor.size)
+            .max()
+            .unwrap_or(4);
+        (size, get_type_name(size, signature))
+    });
+
+    let mut accessors: Vec<String> = accessors
+        .into_iter()
+        .map(|(_, _, demangled)| demangled)
+        .collect();
+    accessors.dedup();
+
+    Some(Field {
+        offset,
+        size,
+        type_name,
+        name,
+        accessors,
+    })
+}
+
+/// `setTag(int)` -> `int`, `None` for references (the setter copies the
+/// referenced value) and for several parameters.
+fn get_setter_parameter(signature: &str) -> Option<String> {
+    let parameters = &signature[signature.find('(')? + 1..signature.rfind(')')?];
+    if parameters.is_empty() || parameters.contains(',') || parameters.contains('&') {
+        return None;
+    }
+    Some(parameters.to_string())
+}
+
+/// `getTag()` / `setTag(int)` -> `m_tag`, `isVisible() const` -> `m_visible`
+fn get_field_name(signature: &str) -> String {
+    let method_name = &signature[..signature.find('(').unwrap_or(signature.len())];
+    let stem = ["get", "set", "is"]
+        .iter()
+        .find_map(|prefix| {
+            method_name
+                .strip_prefix(prefix)
+                .filter(|stem| stem.starts_with(|c: char| c.is_ascii_uppercase() || c == '_'))
+        })
+        .unwrap_or(method_name)
+        .trim_start_matches('_');
+
+    let mut chars = stem.chars();
+    match chars.next() {
+        Some(first) => format!("m_{}{}", first.to_ascii_lowercase(), chars.as_str()),
+        None => format!("m_{}", method_name),
+    }
+}
+
+/// A type of the access width, getters do not mangle their return type.
+fn get_type_name(size: u8, signature: &str) -> String {
+    match size {
+        1 if signature.starts_with("is") => "bool",
+        1 => "char",
+        2 => "short",
+        8 => "long long",
+        _ => "int",
+    }
+    .to_string()
+}
+
+/// Two offsets can end up with the same name (e.g. `getPosition` of a base
+/// and of a member object), suffix the later ones with their offset.
+fn disambiguate_names(fields: &mut [Field]) {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for field in fields.iter_mut() {
+        let count = seen.entry(field.name.clone()).or_default();
+        if *count > 0 {
+            field.name = format!("{}_{:x}", field.name, field.offset);
+        }
+        *count += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_elf::TestElf;
+
+    const RET: u32 = 0xd65f03c0;
+
+    #[test]
+    fn fields_come_from_getters_and_setters() {
+        let mut elf = TestElf::arm64();
+        // ldr w0, [x0, #0x10]
+        let addr = elf.add_code(&[0xb9401000, RET]);
+        elf.add_symbol("_ZN3Foo6getTagEv", addr, 8);
+        // str w1, [x0, #0x10]
+        let addr = elf.add_code(&[0xb9001001, RET]);
+        elf.add_symbol("_ZN3Foo6setTagEi", addr, 8);
+        // ldrb w0, [x0, #0x14]
+        let addr = elf.add_code(&[0x39405000, RET]);
+        elf.add_symbol("_ZNK3Foo9isVisibleEv", addr, 8);
+        // ldr x0, [x0, #0x18]; add w0, w0, #1
+        let addr = elf.add_code(&[0xf9400c00, 0x11000400, RET]);
+        elf.add_symbol("_ZN3Foo8getCountEv", addr, 12);
+
+        let fields = ClassFields::new(&elf.load());
+        let fields: Vec<_> = fields
+            .get("Foo")
+            .iter()
+            .map(|field| {
+                (
+                    field.offset,
+                    field.size,
+                    field.type_name.as_str(),
+                    field.name.as_str(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            fields,
+            vec![(0x10, 4, "int", "m_tag"), (0x14, 1, "bool", "m_visible")]
+        );
+    }
+
+    #[test]
+    fn field_names() {
+        assert_eq!(get_field_name("getTag()"), "m_tag");
+        assert_eq!(get_field_name("isVisible() const"), "m_visible");
+        assert_eq!(get_field_name("set_Name(char const*)"), "m_name");
+        assert_eq!(get_field_name("position()"), "m_position");
+    }
+
+    #[test]
+    fn setter_parameters() {
+        assert_eq!(get_setter_parameter("setTag(int)").as_deref(), Some("int"));
+        assert_eq!(get_setter_parameter("setName(std::string const&)"), None);
+        assert_eq!(get_setter_parameter("setPosition(float, float)"), None);
+    }
+}
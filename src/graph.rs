@@ -0,0 +1,178 @@
+use crate::classes::is_in_namespace;
+use crate::hierarchy::Hierarchy;
+use crate::rtti::BaseClassInfo;
+use std::collections::{BTreeSet, HashMap};
+
+pub struct Graph<'a> {
+    hierarchy: &'a Hierarchy,
+    nodes: BTreeSet<u32>,
+    // (derived typeinfo, base)
+    edges: Vec<(u32, &'a BaseClassInfo)>,
+}
+
+impl<'a> Graph<'a> {
+    /// Every class of the library, optionally restricted to a namespace.
+    pub fn new_library(hierarchy: &'a Hierarchy, namespace: Option<&str>) -> Self {
+        let nodes = hierarchy.typeinfos.keys().copied().collect();
+        Self::new(hierarchy, nodes, namespace, None)
+    }
+
+    /// The bases and derived classes of `typeinfo`, up to `max_depth` links
+    /// away from it.
+    pub fn new_neighborhood(
+        hierarchy: &'a Hierarchy,
+        typeinfo: u32,
+        max_depth: Option<usize>,
+        namespace: Option<&str>,
+    ) -> Self {
+        let mut nodes = BTreeSet::from([typeinfo]);
+
+        nodes.extend(
+            hierarchy
+                .get_derived_flat(typeinfo, max_depth)
+                .into_iter()
+                .map(|(_, derived)| derived),
+        );
+
+        let mut frontier = vec![typeinfo];
+        let mut depth = 0;
+        while !frontier.is_empty() && max_depth.is_none_or(|max_depth| depth < max_depth) {
+            frontier = frontier
+                .iter()
+                .filter_map(|addr| hierarchy.typeinfos.get(addr))
+                .flat_map(|typeinfo| typeinfo.bases.iter().map(|base| base.typeinfo))
+                .filter(|base| nodes.insert(*base))
+                .collect();
+            depth += 1;
+        }
+
+        Self::new(hierarchy, nodes, namespace, Some(typeinfo))
+    }
+
+    fn new(
+        hierarchy: &'a Hierarchy,
+        mut nodes: BTreeSet<u32>,
+        namespace: Option<&str>,
+        root: Option<u32>,
+    ) -> Self {
+        // bases that live in another library have no typeinfo here
+        nodes.retain(|addr| hierarchy.typeinfos.contains_key(addr));
+
+        if let Some(namespace) = namespace {
+            nodes.retain(|addr| {
+                Some(*addr) == root || is_in_namespace(&hierarchy.get_class_name(*addr), namespace)
+            });
+        }
+
+        let mut edges = Vec::new();
+        for addr in &nodes {
+            for base in &hierarchy.typeinfos[addr].bases {
+                if nodes.contains(&base.typeinfo) {
+                    edges.push((*addr, base));
+                }
+            }
+        }
+
+        Self {
+            hierarchy,
+            nodes,
+            edges,
+        }
+    }
+
+    pub fn get_dot(&self) -> String {
+        let mut lines = vec![
+            "digraph inheritance {".to_string(),
+            "    rankdir=BT;".to_string(),
+            "    node [shape=box, fontname=monospace];".to_string(),
+            "    edge [arrowhead=empty, fontname=monospace];".to_string(),
+        ];
+
+        for addr in &self.nodes {
+            lines.push(format!(
+                "    \"{}\";",
+                escape_dot(&self.hierarchy.get_class_name(*addr))
+            ));
+        }
+
+        for (derived, base) in &self.edges {
+            lines.push(format!(
+                "    \"{}\" -> \"{}\" [label=\"{}\"{}];",
+                escape_dot(&self.hierarchy.get_class_name(*derived)),
+                escape_dot(&self.hierarchy.get_class_name(base.typeinfo)),
+                get_edge_label(base),
+                if base.is_virtual {
+                    ", style=dashed"
+                } else {
+                    ""
+                }
+            ));
+        }
+
+        lines.push("}".to_string());
+        lines.join("\n")
+    }
+
+    pub fn get_mermaid(&self) -> String {
+        // mermaid identifiers cannot contain `::` or template arguments, so
+        // every class gets a numbered node with the name as its label.
+        let ids: HashMap<u32, usize> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(id, addr)| (*addr, id))
+            .collect();
+        let get_id = |addr: u32| ids[&addr];
+
+        let mut lines = vec!["flowchart BT".to_string()];
+
+        for addr in &self.nodes {
+            lines.push(format!(
+                "    n{}[\"{}\"]",
+                get_id(*addr),
+                escape_mermaid(&self.hierarchy.get_class_name(*addr))
+            ));
+        }
+
+        for (derived, base) in &self.edges {
+            lines.push(format!(
+                "    n{} {}|\"{}\"| n{}",
+                get_id(*derived),
+                if base.is_virtual { "-.->" } else { "-->" },
+                get_edge_label(base),
+                get_id(base.typeinfo)
+            ));
+        }
+
+        lines.join("\n")
+    }
+}
+
+// `public +0x100`, `virtual public vbase -0xc`
+fn get_edge_label(base: &BaseClassInfo) -> String {
+    let access = if base.is_public { "public" } else { "private" };
+
+    if base.is_virtual {
+        format!("virtual {} vbase {}", access, format_offset(base.offset))
+    } else {
+        format!("{} +0x{:x}", access, base.offset)
+    }
+}
+
+fn format_offset(offset: i32) -> String {
+    if offset < 0 {
+        format!("-0x{:x}", -(offset as i64))
+    } else {
+        format!("0x{:x}", offset)
+    }
+}
+
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_mermaid(text: &str) -> String {
+    text.replace('"', "#quot;")
+        .replace('<', "#lt;")
+        .replace('>', "#gt;")
+}
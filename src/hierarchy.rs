@@ -0,0 +1,189 @@
+use crate::class_index::{demangle, get_class_name_from_symbol, normalize_class_name};
+use crate::library::Library;
+use crate::rtti::TypeInfo;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// The base links of every typeinfo in a library, indexed in both directions.
+pub struct Hierarchy {
+    pub typeinfos: HashMap<u32, TypeInfo>,
+    // base typeinfo -> typeinfos deriving from it directly, sorted by name
+    derived: HashMap<u32, Vec<u32>>,
+    // normalized demangled class name -> typeinfo
+    names: HashMap<String, u32>,
+    // typeinfo -> vtable
+    vtables: HashMap<u32, u32>,
+}
+
+impl Hierarchy {
+    pub fn new(library: &Library) -> Self {
+        let typeinfos: HashMap<u32, TypeInfo> = library
+            .get_typeinfos()
+            .into_iter()
+            .filter_map(|addr| library.get_typeinfo(addr).map(|typeinfo| (addr, typeinfo)))
+            .collect();
+
+        let mut hierarchy = Self {
+            typeinfos,
+            derived: HashMap::new(),
+            names: HashMap::new(),
+            vtables: HashMap::new(),
+        };
+
+        // the `_ZTV` of a class with virtual bases starts with vbase offsets
+        for (vtable, _) in library.get_vtables() {
+            let primary_group = library.get_primary_group(vtable);
+            if let Some(typeinfo) = library.read_pointer(primary_group + library.pointer_size) {
+                hierarchy.vtables.insert(typeinfo, vtable);
+            }
+        }
+
+        for (addr, typeinfo) in &hierarchy.typeinfos {
+            hierarchy.names.insert(
+                normalize_class_name(&hierarchy.get_class_name(*addr)),
+                *addr,
+            );
+
+            for base in &typeinfo.bases {
+                hierarchy
+                    .derived
+                    .entry(base.typeinfo)
+                    .or_default()
+                    .push(*addr);
+            }
+        }
+
+        let mut derived = std::mem::take(&mut hierarchy.derived);
+        for classes in derived.values_mut() {
+            classes.sort_by_cached_key(|addr| hierarchy.get_class_name(*addr));
+            classes.dedup();
+        }
+        hierarchy.derived = derived;
+
+        hierarchy
+    }
+
+    /// The demangled name of the class described by the typeinfo at `addr`.
+    pub fn get_class_name(&self, addr: u32) -> String {
+        match self.typeinfos.get(&addr) {
+            Some(typeinfo) => demangle(&typeinfo.name).unwrap_or(typeinfo.name.clone()),
+            None => format!("typeinfo_{:x}", addr),
+        }
+    }
+
+    /// Resolves a class name, a `_ZTV`/`_ZTI`/`_ZTS` symbol, or the address of
+    /// a typeinfo or vtable to the address of the class's typeinfo. Unlike
+    /// `ClassIndex::resolve`, classes without a vtable (interfaces that are
+    /// only ever used as bases) are found too.
+    pub fn find_typeinfo(&self, library: &Library, selector: &str) -> Option<u32> {
+        if let Some(addr) = self.names.get(&normalize_class_name(selector)) {
+            return Some(*addr);
+        }
+
+        if selector.starts_with("_ZT")
+            && let Some(addr) = get_class_name_from_symbol(selector)
+                .and_then(|class_name| self.names.get(&normalize_class_name(&class_name)))
+        {
+            return Some(*addr);
+        }
+
+        if let Some(addr) = selector
+            .strip_prefix("0x")
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            && self.typeinfos.contains_key(&addr)
+        {
+            return Some(addr);
+        }
+
+        let (_, vtable_symbol) = library
+            .class_index
+            .resolve(selector, &library.addr_to_sym)?;
+        Some(library.get_vtable_typeinfo(library.sym_to_addr[vtable_symbol]))
+    }
+
+    pub fn get_vtable(&self, addr: u32) -> Option<u32> {
+        self.vtables.get(&addr).copied()
+    }
+
+    /// The offset of the `base` subobject inside `derived`, following
+    /// non-virtual bases only (the offset of a virtual base depends on the
+    /// most derived class).
+    pub fn get_base_offset(&self, derived: u32, base: u32) -> Option<i32> {
+        if derived == base {
+            return Some(0);
+        }
+
+        self.typeinfos
+            .get(&derived)?
+            .bases
+            .iter()
+            .filter(|base_info| !base_info.is_virtual)
+            .find_map(|base_info| {
+                self.get_base_offset(base_info.typeinfo, base)
+                    .map(|offset| base_info.offset + offset)
+            })
+    }
+
+    pub fn get_derived(&self, addr: u32) -> &[u32] {
+        self.derived
+            .get(&addr)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Every class deriving from `addr` as (depth, typeinfo) in depth-first
+    /// order, a class deriving through several paths appears once per path.
+    pub fn get_derived_tree(&self, addr: u32, max_depth: Option<usize>) -> Vec<(usize, u32)> {
+        let mut result = Vec::new();
+        let mut path = vec![addr];
+        self._get_derived_tree(addr, 1, max_depth, &mut path, &mut result);
+        result
+    }
+
+    fn _get_derived_tree(
+        &self,
+        addr: u32,
+        depth: usize,
+        max_depth: Option<usize>,
+        path: &mut Vec<u32>,
+        result: &mut Vec<(usize, u32)>,
+    ) {
+        if max_depth.is_some_and(|max_depth| depth > max_depth) {
+            return;
+        }
+
+        for derived in self.get_derived(addr) {
+            // a malformed typeinfo could list one of its own subclasses as a base
+            if path.contains(derived) {
+                continue;
+            }
+
+            result.push((depth, *derived));
+            path.push(*derived);
+            self._get_derived_tree(*derived, depth + 1, max_depth, path, result);
+            path.pop();
+        }
+    }
+
+    /// Every class deriving from `addr` as (depth, typeinfo) in breadth-first
+    /// order, each class appears once at its shortest distance.
+    pub fn get_derived_flat(&self, addr: u32, max_depth: Option<usize>) -> Vec<(usize, u32)> {
+        let mut result = Vec::new();
+        let mut visited = HashSet::from([addr]);
+        let mut queue = VecDeque::from([(0, addr)]);
+
+        while let Some((depth, current)) = queue.pop_front() {
+            if max_depth.is_some_and(|max_depth| depth >= max_depth) {
+                continue;
+            }
+
+            for derived in self.get_derived(current) {
+                if visited.insert(*derived) {
+                    result.push((depth + 1, *derived));
+                    queue.push_back((depth + 1, *derived));
+                }
+            }
+        }
+
+        result
+    }
+}
@@ -0,0 +1,287 @@
+use crate::hierarchy::Hierarchy;
+use crate::library::Library;
+use serde::Serialize;
+use std::collections::HashSet;
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LayoutEntryKind {
+    Class,
+    PrimaryBase,
+    Base,
+    VirtualBase,
+    VtablePointer,
+}
+
+#[derive(Serialize)]
+pub struct LayoutEntry {
+    /// offset from the start of the most derived object, `None` for virtual
+    /// bases whose vbase offset could not be read
+    pub offset: Option<i32>,
+    pub depth: usize,
+    pub kind: LayoutEntryKind,
+    pub class_name: String,
+}
+
+#[derive(Serialize)]
+pub struct ClassLayout {
+    pub class_name: String,
+    /// the size of the class when it is known
+    pub size: Option<u32>,
+    /// the end of the last vptr, the class is at least this large
+    pub min_size: u32,
+    pub entries: Vec<LayoutEntry>,
+}
+
+struct LayoutBuilder<'a> {
+    library: &'a Library,
+    hierarchy: &'a Hierarchy,
+    // (address point, offset-to-top) of the groups of the most derived
+    // class's vtable
+    root_groups: Vec<(u32, i32)>,
+    // offsets of the subobjects that have a vtable group in the most derived
+    // class's vtable
+    vptr_offsets: HashSet<i32>,
+    entries: Vec<LayoutEntry>,
+}
+
+/// Places every base subobject and vptr of the class described by
+/// `typeinfo`, expanding nested bases the way clang's
+/// `-fdump-record-layouts` does.
+pub fn get_class_layout(
+    library: &Library,
+    hierarchy: &Hierarchy,
+    typeinfo: u32,
+    size: Option<u32>,
+) -> ClassLayout {
+    let root_groups: Vec<(u32, i32)> = hierarchy
+        .get_vtable(typeinfo)
+        .map(|vtable| {
+            library
+                .get_vtable_groups(vtable)
+                .into_iter()
+                .map(|(group_address, offset_to_top, _)| {
+                    (group_address + 2 * library.pointer_size, offset_to_top)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let vptr_offsets = root_groups
+        .iter()
+        .map(|(_, offset_to_top)| -offset_to_top)
+        .collect();
+
+    let mut builder = LayoutBuilder {
+        library,
+        hierarchy,
+        root_groups,
+        vptr_offsets,
+        entries: Vec::new(),
+    };
+    builder.add_subobject(typeinfo, Some(0), 0, LayoutEntryKind::Class, &mut vec![]);
+
+    let min_size = builder
+        .entries
+        .iter()
+        .filter(|entry| entry.kind == LayoutEntryKind::VtablePointer)
+        .filter_map(|entry| entry.offset)
+        .map(|offset| offset as u32 + library.pointer_size)
+        .max()
+        .unwrap_or(0);
+
+    ClassLayout {
+        class_name: hierarchy.get_class_name(typeinfo),
+        size,
+        min_size,
+        entries: builder.entries,
+    }
+}
+
+impl LayoutBuilder<'_> {
+    fn add_subobject(
+        &mut self,
+        typeinfo: u32,
+        offset: Option<i32>,
+        depth: usize,
+        kind: LayoutEntryKind,
+        path: &mut Vec<u32>,
+    ) {
+        self.entries.push(LayoutEntry {
+            offset,
+            depth,
+            kind,
+            class_name: self.hierarchy.get_class_name(typeinfo),
+        });
+
+        // a malformed typeinfo could list one of its own subclasses as a base
+        if path.contains(&typeinfo) {
+            return;
+        }
+        path.push(typeinfo);
+
+        let bases = self
+            .hierarchy
+            .typeinfos
+            .get(&typeinfo)
+            .map(|typeinfo| typeinfo.bases.as_slice())
+            .unwrap_or_default();
+
+        let primary_base = bases
+            .iter()
+            .find(|base| !base.is_virtual && base.offset == 0)
+            .filter(|base| {
+                offset.is_some_and(|offset| self.is_dynamic(base.typeinfo, offset, path))
+            });
+
+        if primary_base.is_none()
+            && offset.is_some_and(|offset| self.is_dynamic(typeinfo, offset, path))
+        {
+            self.entries.push(LayoutEntry {
+                offset,
+                depth: depth + 1,
+                kind: LayoutEntryKind::VtablePointer,
+                class_name: self.hierarchy.get_class_name(typeinfo),
+            });
+        }
+
+        for base in bases.iter().filter(|base| !base.is_virtual) {
+            let kind = if primary_base.is_some_and(|primary| std::ptr::eq(primary, base)) {
+                LayoutEntryKind::PrimaryBase
+            } else {
+                LayoutEntryKind::Base
+            };
+            let base_offset = offset.map(|offset| offset + base.offset);
+            self.add_subobject(base.typeinfo, base_offset, depth + 1, kind, path);
+        }
+
+        // a virtual base is shared by the whole object, the direct and
+        // indirect ones are placed once after the non-virtual bases
+        if depth == 0 {
+            let mut virtual_bases = Vec::new();
+            self.collect_virtual_bases(typeinfo, offset, &mut virtual_bases, path);
+            for (base_typeinfo, base_offset) in virtual_bases {
+                self.add_subobject(
+                    base_typeinfo,
+                    base_offset,
+                    depth + 1,
+                    LayoutEntryKind::VirtualBase,
+                    path,
+                );
+            }
+        }
+
+        path.pop();
+    }
+
+    /// The virtual bases of every subobject, in the order clang places them:
+    /// a virtual base comes before the virtual bases of its own bases. The
+    /// offset of a virtual base is only known through the vbase offset in
+    /// the group of the most derived vtable for the subobject declaring it.
+    fn collect_virtual_bases(
+        &self,
+        typeinfo: u32,
+        offset: Option<i32>,
+        virtual_bases: &mut Vec<(u32, Option<i32>)>,
+        path: &mut Vec<u32>,
+    ) {
+        let Some(typeinfo_info) = self.hierarchy.typeinfos.get(&typeinfo) else {
+            return;
+        };
+
+        for base in &typeinfo_info.bases {
+            if path.contains(&base.typeinfo) {
+                continue;
+            }
+
+            let base_offset = if base.is_virtual {
+                if virtual_bases
+                    .iter()
+                    .any(|(virtual_base, _)| *virtual_base == base.typeinfo)
+                {
+                    continue;
+                }
+                let base_offset = offset.and_then(|offset| {
+                    let (address_point, _) = self
+                        .root_groups
+                        .iter()
+                        .find(|(_, offset_to_top)| *offset_to_top == -offset)?;
+                    let vbase_offset = self
+                        .library
+                        .read_signed_pointer((*address_point as i64 + base.offset as i64) as u32)?;
+                    Some(offset + vbase_offset)
+                });
+                virtual_bases.push((base.typeinfo, base_offset));
+                base_offset
+            } else {
+                offset.map(|offset| offset + base.offset)
+            };
+
+            path.push(base.typeinfo);
+            self.collect_virtual_bases(base.typeinfo, base_offset, virtual_bases, path);
+            path.pop();
+        }
+    }
+
+    /// Whether the subobject has a vptr: it has a vtable of its own, one of
+    /// its bases does, or the most derived class has a vtable group for it.
+    fn is_dynamic(&self, typeinfo: u32, offset: i32, path: &[u32]) -> bool {
+        if self.hierarchy.get_vtable(typeinfo).is_some() || self.vptr_offsets.contains(&offset) {
+            return true;
+        }
+
+        self.hierarchy
+            .typeinfos
+            .get(&typeinfo)
+            .is_some_and(|typeinfo_info| {
+                typeinfo_info.bases.iter().any(|base| {
+                    !path.contains(&base.typeinfo)
+                        && (base.is_virtual
+                            || self.is_dynamic(base.typeinfo, offset + base.offset, path))
+                })
+            })
+    }
+}
+
+impl ClassLayout {
+    pub fn get_display(&self) -> String {
+        let mut lines = Vec::new();
+
+        for entry in &self.entries {
+            let offset = match entry.offset {
+                Some(offset) if offset < 0 => format!("-0x{:x}", -(offset as i64)),
+                Some(offset) => format!("0x{:x}", offset),
+                None => "?".to_string(),
+            };
+            let indent = "  ".repeat(entry.depth);
+
+            lines.push(match entry.kind {
+                LayoutEntryKind::Class => format!("{:>10} | {}", offset, entry.class_name),
+                LayoutEntryKind::PrimaryBase => format!(
+                    "{:>10} | {}{} (primary base)",
+                    offset, indent, entry.class_name
+                ),
+                LayoutEntryKind::Base => {
+                    format!("{:>10} | {}{} (base)", offset, indent, entry.class_name)
+                }
+                LayoutEntryKind::VirtualBase => format!(
+                    "{:>10} | {}{} (virtual base)",
+                    offset, indent, entry.class_name
+                ),
+                LayoutEntryKind::VtablePointer => format!(
+                    "{:>10} | {}({} vtable pointer)",
+                    offset, indent, entry.class_name
+                ),
+            });
+        }
+
+        lines.push(match self.size {
+            Some(size) => format!("{:>10} | [sizeof=0x{:x}]", "", size),
+            None => format!(
+                "{:>10} | [sizeof=unknown, at least 0x{:x}]",
+                "", self.min_size
+            ),
+        });
+
+        lines.join("\n")
+    }
+}
@@ -0,0 +1,1086 @@
+use crate::apk;
+use crate::binreader::BinReader;
+use crate::class_index::ClassIndex;
+use crate::disasm::{self, InstructionSet, Operation, RegisterState};
+use crate::dump;
+use crate::rtti::{self, BaseClassInfo, Class, TypeInfo, TypeInfoKind};
+use crate::unwind;
+use elf::{endian::LittleEndian, symbol::SymbolTable};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+const SHT_DYNSYM: u32 = 0xb;
+const SHT_STRTAB: u32 = 0x3;
+// the libraries loaded after the first one start on this boundary
+const MODULE_ALIGNMENT: u32 = 0x1000000;
+// the cxxabi vtable the vptr of every kind of typeinfo points into
+const CXXABI_VTABLES: [(&str, TypeInfoKind); 3] = [
+    ("_ZTVN10__cxxabiv117__class_type_infoE", TypeInfoKind::Class),
+    (
+        "_ZTVN10__cxxabiv120__si_class_type_infoE",
+        TypeInfoKind::SingleInheritance,
+    ),
+    (
+        "_ZTVN10__cxxabiv121__vmi_class_type_infoE",
+        TypeInfoKind::VirtualMultipleInheritance,
+    ),
+];
+
+/// A library read and indexed once, shared by every query made against it.
+/// The libraries it depends on can be loaded with it, they are mapped after
+/// it and linked to it like the dynamic linker would.
+pub struct Library {
+    /// every loaded file, one after the other, with the relocations applied
+    pub data: Vec<u8>,
+    /// the library being analyzed first, then its dependencies in load order
+    pub modules: Vec<Module>,
+    pub sym_to_addr: HashMap<String, u32>,
+    pub addr_to_sym: HashMap<u32, String>,
+    pub class_index: ClassIndex,
+    pub cxxabi_offsets: Vec<u32>,
+    // file offset of .data.rel.ro for `handle_typename`, which only reads a
+    // lone 32-bit file with its .rodata and .data.rel.ro section headers
+    start_data_rel_ro: Option<u32>,
+    /// 4 for ELF32 (ARM), 8 for ELF64 (AArch64)
+    pub pointer_size: u32,
+    /// `e_machine` of the ELF header
+    pub machine: u16,
+    // (virtual address, file offset, size in file) of every PT_LOAD segment
+    segments: Vec<(u32, u32, u32)>,
+    // (virtual address, size in memory) of the executable PT_LOAD segments
+    executable_ranges: Vec<(u32, u32)>,
+    /// GOT slot -> imported symbol, from the JUMP_SLOT and GLOB_DAT relocations
+    pub got_symbols: HashMap<u32, String>,
+    /// PLT stub -> imported symbol it jumps to
+    pub plt_symbols: HashMap<u32, String>,
+    /// relocated word -> imported symbol that no loaded module defines, the
+    /// word only holds the addend of the relocation
+    pub unresolved_imports: HashMap<u32, String>,
+    // imported symbol -> library providing it, when it can be told
+    import_libraries: HashMap<String, String>,
+    // (start address, size, name) of every defined symbol with a size,
+    // sorted by start address. The thumb bit is cleared.
+    symbol_ranges: Vec<(u32, u32, String)>,
+    // (start address, size) of every function in the unwind tables, sorted
+    // by start address. ARM32 thumb functions have the thumb bit set.
+    function_ranges: Vec<(u32, u32)>,
+}
+
+/// A file mapped into the address space of a `Library`.
+pub struct Module {
+    pub path: PathBuf,
+    /// the address the file is loaded at, 0 for the library being analyzed
+    pub base: u32,
+    // offset and size of the file in `Library::data`
+    data_offset: u32,
+    size: u32,
+    // (virtual address, size) of .data.rel.ro
+    data_rel_ro: Option<(u32, u32)>,
+}
+
+impl Library {
+    /// Loads the library at `paths[0]` and maps the other ones after it,
+    /// resolving the symbols each of them imports in load order.
+    pub fn load(paths: &[PathBuf]) -> Self {
+        Self::link(
+            paths
+                .iter()
+                .map(|path| (path.clone(), read_library(path)))
+                .collect(),
+        )
+    }
+
+    /// Loads the raw memory image of a library at `paths[0]`, dumped from a
+    /// process where it started at `base`, and maps the other ones after it.
+    /// Symbols and relocations come from the on-disk library `symbols_from`,
+    /// or from the dynamic segment of the dump.
+    pub fn load_memory_dump(paths: &[PathBuf], base: u64, symbols_from: Option<&Path>) -> Self {
+        let dump = std::fs::read(&paths[0]).unwrap_or_else(|error| {
+            eprintln!("failed to read {:?}: {}", paths[0], error);
+            std::process::exit(1);
+        });
+        let companion = symbols_from.map(read_library);
+        let image = dump::rebuild_elf(&dump, base, companion.as_deref()).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        });
+
+        let mut files = vec![(paths[0].clone(), image)];
+        files.extend(
+            paths[1..]
+                .iter()
+                .map(|path| (path.clone(), read_library(path))),
+        );
+        Self::link(files)
+    }
+
+    /// A library made of a single file already in memory.
+    #[cfg(test)]
+    pub fn from_image(image: Vec<u8>) -> Self {
+        Self::link(vec![(PathBuf::from("test.so"), image)])
+    }
+
+    fn link(files: Vec<(PathBuf, Vec<u8>)>) -> Self {
+        let mut data = Vec::new();
+        let mut modules = Vec::new();
+        let mut pointer_size = 0;
+        let mut machine = 0;
+        let mut base = 0;
+
+        for (path, file) in &files {
+            let elf = elf::ElfBytes::<LittleEndian>::minimal_parse(file)
+                .unwrap_or_else(|_| panic!("not an ELF file: {:?}", path));
+            let file_pointer_size = match elf.ehdr.class {
+                elf::file::Class::ELF32 => 4,
+                elf::file::Class::ELF64 => 8,
+            };
+
+            if modules.is_empty() {
+                pointer_size = file_pointer_size;
+                machine = elf.ehdr.e_machine;
+            } else if (file_pointer_size, elf.ehdr.e_machine) != (pointer_size, machine) {
+                eprintln!(
+                    "{:?} is not built for the architecture of {:?}",
+                    path, files[0].0
+                );
+                std::process::exit(1);
+            }
+
+            let image_end = get_load_segments(file)
+                .iter()
+                .map(|(vaddr, _, _, memsz)| vaddr + memsz)
+                .max()
+                .unwrap_or(0);
+            modules.push(Module {
+                path: path.clone(),
+                base,
+                data_offset: data.len() as u32,
+                size: file.len() as u32,
+                data_rel_ro: get_section_by_name(file, ".data.rel.ro")
+                    .map(|section| (base + section.sh_addr as u32, section.sh_size as u32)),
+            });
+            base = (base + image_end).next_multiple_of(MODULE_ALIGNMENT);
+            data.extend(file);
+        }
+
+        let mut sym_to_addr = HashMap::new();
+        let mut addr_to_sym = HashMap::new();
+        let mut sym_to_size = HashMap::new();
+        let mut segments = Vec::new();
+        let mut executable_ranges = Vec::new();
+        let mut got_symbols = HashMap::new();
+        let mut import_libraries = HashMap::new();
+
+        for module in &modules {
+            let module_data = get_module_data(&data, module);
+
+            for (name, symbol) in get_dynamic_symbols(module_data) {
+                let addr = match symbol.st_value as u32 {
+                    0 => 0,
+                    value => module.base + value,
+                };
+                if addr != 0 || module.base == 0 {
+                    addr_to_sym.insert(addr, name.clone());
+                }
+                // the first definition in load order wins, like a symbol
+                // lookup in the global scope
+                if sym_to_addr.get(&name).is_none_or(|defined| *defined == 0) {
+                    sym_to_addr.insert(name.clone(), addr);
+                    sym_to_size.insert(name, symbol.st_size as u32);
+                }
+            }
+
+            segments.extend(get_load_segments(module_data).into_iter().map(
+                |(vaddr, offset, size, _)| (module.base + vaddr, module.data_offset + offset, size),
+            ));
+            executable_ranges.extend(
+                get_executable_ranges(module_data)
+                    .into_iter()
+                    .map(|(vaddr, size)| (module.base + vaddr, size)),
+            );
+            got_symbols.extend(
+                get_got_symbols(module_data)
+                    .into_iter()
+                    .map(|(slot, symbol)| (module.base + slot, symbol)),
+            );
+            for (symbol, library) in get_import_libraries(
+                module_data,
+                &module.path,
+                &get_needed_libraries(module_data),
+            ) {
+                import_libraries.entry(symbol).or_insert(library);
+            }
+        }
+
+        let class_index = ClassIndex::new(&sym_to_addr, &sym_to_size);
+        // typeinfos point past the offset-to-top and typeinfo of the cxxabi
+        // vtables, a library only imports the ones its classes need. The
+        // imports that no loaded module defines are left at 0.
+        let cxxabi_offsets = CXXABI_VTABLES
+            .iter()
+            .filter_map(|(symbol, _)| sym_to_addr.get(*symbol))
+            .filter(|addr| **addr != 0)
+            .map(|addr| addr + 2 * pointer_size)
+            .collect();
+        let has_cxxabi_imports = CXXABI_VTABLES
+            .iter()
+            .any(|(symbol, _)| sym_to_addr.get(*symbol) == Some(&0));
+        let start_data_rel_ro = get_section_range(&data, ".data.rel.ro")
+            .filter(|_| {
+                pointer_size == 4
+                    && modules.len() == 1
+                    && !has_cxxabi_imports
+                    && get_section_by_name(&data, ".rodata").is_some()
+            })
+            .map(|(start, _)| start as u32);
+
+        let mut symbol_ranges: Vec<(u32, u32, String)> = sym_to_size
+            .into_iter()
+            .filter(|(symbol, size)| *size > 0 && sym_to_addr[symbol] != 0)
+            .map(|(symbol, size)| (sym_to_addr[&symbol] & !1, size, symbol))
+            .collect();
+        symbol_ranges.sort();
+
+        let mut library = Self {
+            data,
+            modules,
+            sym_to_addr,
+            addr_to_sym,
+            class_index,
+            cxxabi_offsets,
+            start_data_rel_ro,
+            pointer_size,
+            machine,
+            segments,
+            executable_ranges,
+            symbol_ranges,
+            got_symbols,
+            plt_symbols: HashMap::new(),
+            unresolved_imports: HashMap::new(),
+            import_libraries,
+            function_ranges: Vec::new(),
+        };
+        library.apply_relocations();
+        library.plt_symbols = library.find_plt_stubs();
+        library.function_ranges = unwind::get_function_ranges(&library);
+        library
+    }
+
+    /// The module whose address range holds `addr`.
+    pub fn get_module(&self, addr: u32) -> Option<&Module> {
+        self.modules.iter().rev().find(|module| module.base <= addr)
+    }
+
+    /// The bytes of the file loaded as `module`.
+    pub fn get_module_data(&self, module: &Module) -> &[u8] {
+        get_module_data(&self.data, module)
+    }
+
+    /// Writes what the dynamic linker would into the relocated words: the
+    /// load address plus the addend for relative relocations, the first
+    /// definition in load order for symbol ones. Words referring to symbols
+    /// no module defines keep the addend.
+    fn apply_relocations(&mut self) {
+        let mut writes = Vec::new();
+        let mut unresolved_imports = HashMap::new();
+
+        for module in &self.modules {
+            let module_data = self.get_module_data(module);
+            let symbols = get_dynamic_symbols(module_data);
+
+            for relocation in get_relocations(module_data) {
+                let addr = module.base + relocation.offset;
+                let Some(in_place) = self.read_pointer(addr) else {
+                    continue;
+                };
+                // REL relocations keep their addend in the relocated word
+                let addend = relocation.addend.unwrap_or(in_place as i64);
+                let name = symbols
+                    .get(relocation.symbol as usize)
+                    .map(|(name, _)| name)
+                    .filter(|_| relocation.symbol != 0);
+                let target = name
+                    .and_then(|name| self.sym_to_addr.get(name))
+                    .filter(|addr| **addr != 0)
+                    .map(|addr| *addr as i64);
+                if let (Some(name), None) = (name, target) {
+                    unresolved_imports.insert(addr, name.clone());
+                }
+
+                let value = match (relocation.r_type, target) {
+                    (elf::abi::R_ARM_RELATIVE | elf::abi::R_AARCH64_RELATIVE, _) => {
+                        module.base as i64 + addend
+                    }
+                    (_, None) if relocation.addend.is_none() => continue,
+                    (elf::abi::R_ARM_GLOB_DAT | elf::abi::R_ARM_JUMP_SLOT, Some(target)) => target,
+                    (
+                        elf::abi::R_ARM_ABS32
+                        | elf::abi::R_AARCH64_ABS64
+                        | elf::abi::R_AARCH64_GLOB_DAT
+                        | elf::abi::R_AARCH64_JUMP_SLOT,
+                        target,
+                    ) => target.unwrap_or(0) + addend,
+                    _ => continue,
+                };
+                writes.push((addr, value as u32));
+            }
+        }
+
+        for (addr, value) in writes {
+            let Some(offset) = self.get_file_offset(addr) else {
+                continue;
+            };
+            let offset = offset as usize;
+            let bytes = (value as u64).to_le_bytes();
+            let size = self.pointer_size as usize;
+            self.data[offset..offset + size].copy_from_slice(&bytes[..size]);
+        }
+        self.unresolved_imports = unresolved_imports;
+    }
+
+    /// Walks the .plt of every module and pairs every stub with the GOT slot
+    /// it jumps through.
+    fn find_plt_stubs(&self) -> HashMap<u32, String> {
+        let mut stubs = HashMap::new();
+        for module in &self.modules {
+            if let Some(section) = get_section_by_name(self.get_module_data(module), ".plt") {
+                let start = module.base + section.sh_addr as u32;
+                self.find_plt_section_stubs(start, section.sh_size as u32, &mut stubs);
+            }
+        }
+        stubs
+    }
+
+    fn find_plt_section_stubs(&self, start: u32, size: u32, stubs: &mut HashMap<u32, String>) {
+        // ARM32 stubs are ARM code, possibly behind a `bx pc; nop` thumb prefix
+        let set = if self.machine == disasm::EM_AARCH64 {
+            InstructionSet::Arm64
+        } else {
+            InstructionSet::Arm
+        };
+
+        let mut state = RegisterState::new(set);
+        let mut entry_start = None;
+        let mut addr = start;
+        let end = start + size;
+
+        while addr < end {
+            let Some(instruction) = disasm::decode(self, addr, set) else {
+                break;
+            };
+            addr += instruction.size;
+
+            // the literal words of the PLT header decode as nothing useful
+            if entry_start.is_none() && instruction.operation != Operation::Other {
+                entry_start = Some(instruction.address);
+            }
+
+            let slot = match instruction.operation {
+                Operation::Load { rn, offset, .. } => state
+                    .get(rn)
+                    .map(|base| (base as i64).wrapping_add(offset) as u32),
+                Operation::LoadLiteral { addr, .. } => Some(addr),
+                _ => None,
+            };
+            if let (Some(slot), Some(start)) = (slot, entry_start)
+                && let Some(symbol) = self.got_symbols.get(&slot)
+            {
+                stubs.insert(start, symbol.clone());
+                let thumb_prefix = start
+                    .checked_sub(4)
+                    .and_then(|prefix| self.read_u32(prefix));
+                if thumb_prefix == Some(0x46c04778) {
+                    stubs.insert(start - 4, symbol.clone());
+                }
+            }
+
+            state.step(self, &instruction);
+
+            let is_jump = match instruction.operation {
+                Operation::Load { rt, .. } => set != InstructionSet::Arm64 && rt == disasm::ARM_PC,
+                Operation::BranchRegister { .. } => true,
+                _ => false,
+            };
+            if is_jump {
+                entry_start = None;
+                state = RegisterState::new(set);
+            }
+        }
+    }
+
+    /// Every address a call to `symbol` can target: its definition and the
+    /// PLT stubs importing it.
+    pub fn get_function_addresses(&self, symbol: &str) -> Vec<u32> {
+        let mut addresses: Vec<u32> = self
+            .plt_symbols
+            .iter()
+            .filter(|(_, name)| *name == symbol)
+            .map(|(addr, _)| *addr)
+            .collect();
+        if let Some(addr) = self.sym_to_addr.get(symbol).filter(|addr| **addr != 0) {
+            addresses.push(addr & !1);
+        }
+        addresses
+    }
+
+    /// The symbol whose `st_size` range covers `addr`, and the offset of
+    /// `addr` inside it.
+    pub fn get_enclosing_symbol(&self, addr: u32) -> Option<(&String, u32)> {
+        let addr = addr & !1;
+        let index = self
+            .symbol_ranges
+            .partition_point(|(start, _, _)| *start <= addr);
+
+        // symbols can nest (a vtable inside a larger object), so look back
+        // for the closest one that covers the address
+        self.symbol_ranges[..index]
+            .iter()
+            .rev()
+            .take(16)
+            .find(|(start, size, _)| addr < start + size)
+            .map(|(start, _, symbol)| (symbol, addr - start))
+    }
+
+    /// The `st_size` of the symbol starting at `addr`.
+    pub fn get_symbol_size(&self, addr: u32) -> Option<u32> {
+        let addr = addr & !1;
+        let index = self
+            .symbol_ranges
+            .partition_point(|(start, _, _)| *start < addr);
+        self.symbol_ranges
+            .get(index)
+            .filter(|(start, _, _)| *start == addr)
+            .map(|(_, size, _)| *size)
+    }
+
+    /// The `st_size` of the symbol starting at `addr`, or the size the unwind
+    /// tables give the function starting there.
+    pub fn get_function_size(&self, addr: u32) -> Option<u32> {
+        self.get_symbol_size(addr).or_else(|| {
+            let addr = addr & !1;
+            let index = self
+                .function_ranges
+                .partition_point(|(start, _)| start & !1 < addr);
+            self.function_ranges
+                .get(index)
+                .filter(|(start, _)| start & !1 == addr)
+                .map(|(_, size)| *size)
+        })
+    }
+
+    /// The function from the unwind tables covering `addr` (with the thumb
+    /// bit set for thumb code), and the offset of `addr` inside it.
+    pub fn get_enclosing_function(&self, addr: u32) -> Option<(u32, u32)> {
+        let addr = addr & !1;
+        let index = self
+            .function_ranges
+            .partition_point(|(start, _)| start & !1 <= addr);
+        let (start, size) = self.function_ranges[..index].last()?;
+        (addr < (start & !1) + size).then_some((*start, addr - (start & !1)))
+    }
+
+    /// The start of every function in the unwind tables, including the ones
+    /// without a symbol.
+    pub fn get_unwind_functions(&self) -> impl Iterator<Item = u32> + '_ {
+        self.function_ranges.iter().map(|(start, _)| *start)
+    }
+
+    /// Maps a virtual address to its file offset through the PT_LOAD segments.
+    pub fn get_file_offset(&self, addr: u32) -> Option<u32> {
+        self.segments
+            .iter()
+            .find(|(vaddr, _, size)| (*vaddr..vaddr + size).contains(&addr))
+            .map(|(vaddr, offset, _)| offset + (addr - vaddr))
+    }
+
+    /// Whether `addr` is inside an executable PT_LOAD segment.
+    pub fn is_executable_address(&self, addr: u32) -> bool {
+        self.get_executable_range(addr).is_some()
+    }
+
+    /// The (virtual address, size in memory) of the executable PT_LOAD segment
+    /// holding `addr`.
+    pub fn get_executable_range(&self, addr: u32) -> Option<(u32, u32)> {
+        let addr = addr & !1;
+        self.executable_ranges
+            .iter()
+            .find(|(start, size)| *start <= addr && addr < start + size)
+            .copied()
+    }
+
+    pub fn get_data_rel_ro_offset(&self, addr: u32) -> u32 {
+        get_file_offset_for_address_under_section(&self.data, ".data.rel.ro", addr.into()) as u32
+    }
+
+    pub fn is_data_rel_ro_address(&self, addr: u32) -> bool {
+        self.modules
+            .iter()
+            .filter_map(|module| module.data_rel_ro)
+            .any(|(start, size)| (start..start + size).contains(&addr))
+    }
+
+    pub fn read_u32(&self, addr: u32) -> Option<u32> {
+        let mut reader = BinReader::new(&self.data);
+        reader.set_position(self.get_file_offset(addr)?);
+        reader.read_u32()
+    }
+
+    /// Reads a pointer sized word, see `BinReader::read_word`.
+    pub fn read_pointer(&self, addr: u32) -> Option<u32> {
+        let mut reader = BinReader::new(&self.data);
+        reader.set_position(self.get_file_offset(addr)?);
+        reader.read_word(self.pointer_size)
+    }
+
+    pub fn read_signed_pointer(&self, addr: u32) -> Option<i32> {
+        let mut reader = BinReader::new(&self.data);
+        reader.set_position(self.get_file_offset(addr)?);
+        reader.read_signed_word(self.pointer_size)
+    }
+
+    pub fn read_cstr(&self, addr: u32) -> Option<String> {
+        let offset = self.get_file_offset(addr)? as usize;
+        let length = self.data[offset..].iter().position(|byte| *byte == 0)?;
+        String::from_utf8(self.data[offset..offset + length].to_vec()).ok()
+    }
+
+    pub fn get_vtable_typeinfo(&self, vtable_addr: u32) -> u32 {
+        self.read_pointer(self.get_primary_group(vtable_addr) + self.pointer_size)
+            .expect("failed to read vtable's typeinfo")
+    }
+
+    /// The address of the primary group of the vtable at `vtable_addr`. The
+    /// `_ZTV` symbol of a class with virtual bases starts with their vbase
+    /// and vcall offsets, the primary group is the first offset-to-top of 0
+    /// followed by a typeinfo.
+    pub fn get_primary_group(&self, vtable_addr: u32) -> u32 {
+        let Some(size) = self.get_symbol_size(vtable_addr) else {
+            return vtable_addr;
+        };
+
+        (vtable_addr..vtable_addr.saturating_add(size))
+            .step_by(self.pointer_size as usize)
+            .find(|addr| {
+                self.read_pointer(*addr) == Some(0)
+                    && self.is_typeinfo_pointer(addr + self.pointer_size)
+            })
+            .unwrap_or(vtable_addr)
+    }
+
+    /// Whether the word at `addr` points at a typeinfo, or imports one from a
+    /// library that is not loaded.
+    fn is_typeinfo_pointer(&self, addr: u32) -> bool {
+        if let Some(import) = self.unresolved_imports.get(&addr) {
+            return import.starts_with("_ZTI");
+        }
+        self.read_pointer(addr)
+            .is_some_and(|typeinfo| self.get_typeinfo_kind(typeinfo) != TypeInfoKind::Unknown)
+    }
+
+    pub fn get_typeinfo_kind(&self, typeinfo_addr: u32) -> TypeInfoKind {
+        // the vptr of a typeinfo whose cxxabi vtable is imported from a
+        // library that is not loaded only holds an addend, its relocation
+        // still names the vtable
+        let cxxabi_vtable = match self.unresolved_imports.get(&typeinfo_addr) {
+            Some(import) => CXXABI_VTABLES.iter().find(|(symbol, _)| symbol == import),
+            None => {
+                let vptr = self.read_pointer(typeinfo_addr);
+                CXXABI_VTABLES.iter().find(|(symbol, _)| {
+                    self.sym_to_addr
+                        .get(*symbol)
+                        .filter(|addr| **addr != 0)
+                        .is_some_and(|addr| vptr == Some(addr + 2 * self.pointer_size))
+                })
+            }
+        };
+
+        cxxabi_vtable.map_or(TypeInfoKind::Unknown, |(_, kind)| *kind)
+    }
+
+    /// Parses the typeinfo at `typeinfo_addr` according to its cxxabi class.
+    pub fn get_typeinfo(&self, typeinfo_addr: u32) -> Option<TypeInfo> {
+        let kind = self.get_typeinfo_kind(typeinfo_addr);
+        let pointer_size = self.pointer_size;
+        let name = self.read_cstr(self.read_pointer(typeinfo_addr + pointer_size)?)?;
+
+        let bases = match kind {
+            TypeInfoKind::Unknown => return None,
+            TypeInfoKind::Class => Vec::new(),
+            TypeInfoKind::SingleInheritance => vec![BaseClassInfo {
+                typeinfo: self.read_pointer(typeinfo_addr + 2 * pointer_size)?,
+                offset: 0,
+                is_virtual: false,
+                is_public: true,
+            }],
+            TypeInfoKind::VirtualMultipleInheritance => {
+                // unsigned int __flags, __base_count follow the name
+                let base_count = self.read_u32(typeinfo_addr + 2 * pointer_size + 4)?;
+                (0..base_count)
+                    .map(|i| {
+                        let base_addr = typeinfo_addr + 2 * pointer_size + 8 + 2 * pointer_size * i;
+                        Some(BaseClassInfo::new(
+                            self.read_pointer(base_addr)?,
+                            self.read_signed_pointer(base_addr + pointer_size)? as i64,
+                        ))
+                    })
+                    .collect::<Option<Vec<BaseClassInfo>>>()?
+            }
+        };
+
+        Some(TypeInfo {
+            address: typeinfo_addr,
+            name,
+            kind,
+            bases,
+        })
+    }
+
+    /// Every typeinfo in .data.rel.ro, found by its pointer into one of the
+    /// cxxabi vtables, or by the relocation importing it.
+    pub fn get_typeinfos(&self) -> Vec<u32> {
+        self.get_data_rel_ro_words()
+            .into_iter()
+            .filter(|(addr, word)| {
+                u32::try_from(*word).is_ok_and(|word| self.cxxabi_offsets.contains(&word))
+                    || self.unresolved_imports.get(addr).is_some_and(|import| {
+                        CXXABI_VTABLES.iter().any(|(symbol, _)| symbol == import)
+                    })
+            })
+            .map(|(addr, _)| addr)
+            .collect()
+    }
+
+    pub fn get_inheritance(&self, vtable_addr: u32) -> Class {
+        let typeinfo = self.get_vtable_typeinfo(vtable_addr);
+        // a vtable built without RTTI has no typeinfo to read in .data.rel.ro
+        let Some(start_data_rel_ro) = self
+            .start_data_rel_ro
+            .filter(|_| self.is_data_rel_ro_address(typeinfo))
+        else {
+            return self.get_typeinfo_inheritance(typeinfo);
+        };
+
+        let mut reader = BinReader::new(&self.data);
+        let mut inherit_info = Class::default();
+
+        let typeinfo_offset = self.get_data_rel_ro_offset(typeinfo);
+
+        rtti::handle_typename(
+            &mut reader,
+            &mut inherit_info,
+            typeinfo_offset,
+            start_data_rel_ro,
+            &self.cxxabi_offsets,
+        );
+
+        inherit_info
+    }
+
+    /// The bases of the class described by `typeinfo`, recursively, read
+    /// according to the kind of every typeinfo.
+    pub fn get_typeinfo_inheritance(&self, typeinfo: u32) -> Class {
+        let mut inherit_info = Class::default();
+        self.get_inheritance_from_typeinfo(typeinfo, &mut inherit_info, &mut Vec::new());
+        inherit_info
+    }
+
+    // `handle_typename` reads 32-bit words of a single file, 64-bit, linked
+    // and rebuilt libraries go through the kind-aware parser instead
+    fn get_inheritance_from_typeinfo(
+        &self,
+        typeinfo: u32,
+        output: &mut Class,
+        path: &mut Vec<u32>,
+    ) {
+        let Some(typeinfo_info) = self.get_typeinfo(typeinfo) else {
+            return;
+        };
+        output.name = typeinfo_info.name;
+
+        if path.contains(&typeinfo) {
+            return;
+        }
+        path.push(typeinfo);
+        for (index, base) in typeinfo_info.bases.iter().enumerate() {
+            let base_output = output.push_base();
+            // a base imported from a library that is not loaded is only
+            // known by the `_ZTI` symbol of its relocation
+            let base_word = match typeinfo_info.kind {
+                TypeInfoKind::SingleInheritance => typeinfo + 2 * self.pointer_size,
+                _ => typeinfo + 2 * self.pointer_size + 8 + 2 * self.pointer_size * index as u32,
+            };
+            if let Some(name) = self
+                .unresolved_imports
+                .get(&base_word)
+                .and_then(|symbol| symbol.strip_prefix("_ZTI"))
+            {
+                base_output.name = name.to_string();
+                continue;
+            }
+            self.get_inheritance_from_typeinfo(base.typeinfo, base_output, path);
+        }
+        path.pop();
+    }
+
+    pub fn get_class_vtable(&self, vtable_addr: u32) -> Vec<(i32, Vec<u32>)> {
+        self.get_vtable_groups(vtable_addr)
+            .into_iter()
+            .map(|(_, offset_to_top, functions)| (offset_to_top, functions))
+            .collect()
+    }
+
+    /// The groups of the vtable at `vtable_addr` as (address of the
+    /// offset-to-top, offset-to-top, slots).
+    pub fn get_vtable_groups(&self, vtable_addr: u32) -> Vec<(u32, i32, Vec<u32>)> {
+        let mut reader = BinReader::new(&self.data);
+        let primary_group = self.get_primary_group(vtable_addr);
+        let vtable_offset = self
+            .get_file_offset(primary_group)
+            .expect("vtable is not in a PT_LOAD segment");
+        let get_address = |offset: u32| primary_group + (offset - vtable_offset);
+
+        rtti::get_class_vtable(
+            &mut reader,
+            vtable_offset,
+            &|offset| self.get_typeinfo_kind(get_address(offset)) != TypeInfoKind::Unknown,
+            // the file header is usually mapped with the code, a 0 is not a
+            // function unless the slot imports one
+            &|offset, word| {
+                (word != 0 && self.is_executable_address(word))
+                    || self.unresolved_imports.contains_key(&get_address(offset))
+            },
+            self.pointer_size,
+        )
+        .into_iter()
+        .map(|(offset, offset_to_top, functions)| (get_address(offset), offset_to_top, functions))
+        .collect()
+    }
+
+    /// Every vtable of the library as (address, `_ZTV` symbol), including the
+    /// ones found by `discover_vtables`.
+    pub fn get_vtables(&self) -> Vec<(u32, Option<String>)> {
+        let mut vtables: Vec<(u32, Option<String>)> = self
+            .class_index
+            .get_classes()
+            .into_iter()
+            .map(|(_, symbol)| (self.sym_to_addr[symbol], Some(symbol.to_owned())))
+            .collect();
+        vtables.extend(self.discover_vtables().into_iter().map(|addr| (addr, None)));
+        vtables
+    }
+
+    /// Finds the vtables that have no `_ZTV` symbol (hidden visibility) by
+    /// looking for an offset-to-top of 0 followed by a pointer to a typeinfo.
+    pub fn discover_vtables(&self) -> Vec<u32> {
+        let words = self.get_data_rel_ro_words();
+        let typeinfos: HashSet<u32> = self.get_typeinfos().into_iter().collect();
+
+        words
+            .windows(2)
+            .filter(|pair| {
+                pair[0].1 == 0
+                    && u32::try_from(pair[1].1).is_ok_and(|word| typeinfos.contains(&word))
+            })
+            .map(|pair| pair[0].0)
+            .filter(|addr| !self.class_index.is_in_vtable_symbol(*addr))
+            .collect()
+    }
+
+    /// (address, value) of every pointer sized word in the .data.rel.ro of
+    /// every module
+    fn get_data_rel_ro_words(&self) -> Vec<(u32, u64)> {
+        let mut words = Vec::new();
+
+        for (start, size) in self.modules.iter().filter_map(|module| module.data_rel_ro) {
+            let Some(offset) = self.get_file_offset(start) else {
+                continue;
+            };
+            let offset = offset as usize;
+            words.extend(
+                self.data[offset..offset + size as usize]
+                    .chunks_exact(self.pointer_size as usize)
+                    .enumerate()
+                    .map(|(index, word)| {
+                        let mut bytes = [0u8; 8];
+                        bytes[..word.len()].copy_from_slice(word);
+                        (
+                            start + self.pointer_size * index as u32,
+                            u64::from_le_bytes(bytes),
+                        )
+                    }),
+            );
+        }
+
+        words
+    }
+
+    /// The symbol at `addr`, `symbol@plt` for the PLT stub of an imported
+    /// function, or an IDA style `sub_` name if there is none.
+    pub fn get_symbol_name(&self, addr: u32) -> String {
+        if let Some(symbol) = self.addr_to_sym.get(&addr) {
+            return symbol.to_owned();
+        }
+        match self.plt_symbols.get(&(addr & !1)) {
+            Some(symbol) => format!("{}@plt", symbol),
+            None => format!("sub_{:x}", addr & !1),
+        }
+    }
+
+    /// The DT_NEEDED library an imported symbol comes from.
+    pub fn get_import_library(&self, symbol: &str) -> Option<&str> {
+        self.import_libraries
+            .get(symbol.strip_suffix("@plt").unwrap_or(symbol))
+            .map(String::as_str)
+    }
+}
+
+fn read_library(path: &Path) -> Vec<u8> {
+    apk::read_library(path).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    })
+}
+
+fn get_module_data<'a>(data: &'a [u8], module: &Module) -> &'a [u8] {
+    &data[module.data_offset as usize..(module.data_offset + module.size) as usize]
+}
+
+/// (virtual address, file offset, size in file, size in memory) of every
+/// PT_LOAD segment.
+fn get_load_segments(data: &[u8]) -> Vec<(u32, u32, u32, u32)> {
+    let elf = elf::ElfBytes::<elf::endian::LittleEndian>::minimal_parse(data).unwrap();
+
+    elf.segments()
+        .map(|segments| {
+            segments
+                .iter()
+                .filter(|segment| segment.p_type == elf::abi::PT_LOAD)
+                .map(|segment| {
+                    (
+                        segment.p_vaddr as u32,
+                        segment.p_offset as u32,
+                        segment.p_filesz as u32,
+                        segment.p_memsz as u32,
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn get_executable_ranges(data: &[u8]) -> Vec<(u32, u32)> {
+    let elf = elf::ElfBytes::<elf::endian::LittleEndian>::minimal_parse(data).unwrap();
+
+    elf.segments()
+        .map(|segments| {
+            segments
+                .iter()
+                .filter(|segment| {
+                    segment.p_type == elf::abi::PT_LOAD && segment.p_flags & elf::abi::PF_X != 0
+                })
+                .map(|segment| (segment.p_vaddr as u32, segment.p_memsz as u32))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn get_section_by_name<S: AsRef<str>>(
+    data: &[u8],
+    section_name: S,
+) -> Option<elf::section::SectionHeader> {
+    let elf = elf::ElfBytes::<elf::endian::LittleEndian>::minimal_parse(data).unwrap();
+    let (shdrs_r, strtab_r) = elf.section_headers_with_strtab().unwrap();
+    let (shdrs, strtab) = (shdrs_r.unwrap(), strtab_r.unwrap());
+
+    shdrs
+        .into_iter()
+        .find(|header| match strtab.get(header.sh_name as usize) {
+            Ok(header_name) => header_name == section_name.as_ref(),
+            Err(_) => false,
+        })
+}
+
+pub fn get_section_range(data: &[u8], section_name: &str) -> Option<(u64, u64)> {
+    get_section_by_name(data, section_name)
+        .map(|header| (header.sh_offset, header.sh_offset + header.sh_size))
+}
+
+pub fn get_file_offset_for_address_under_section<S: AsRef<str>>(
+    data: &[u8],
+    section_name: S,
+    addr: u64,
+) -> u64 {
+    let section = get_section_by_name(data, section_name).unwrap();
+    let relative_address = addr - section.sh_addr;
+    section.sh_offset + relative_address
+}
+
+pub fn get_dynamic_symbols(data: &[u8]) -> Vec<(String, elf::symbol::Symbol)> {
+    let elf = elf::ElfBytes::<elf::endian::LittleEndian>::minimal_parse(data).unwrap();
+    let shdrs = elf.section_headers().unwrap();
+
+    let dynsym_section = shdrs
+        .iter()
+        .find(|hdr| hdr.sh_type == SHT_DYNSYM)
+        .expect("no SHT_DYNSYM");
+    let string_table_section = shdrs
+        .get(dynsym_section.sh_link as usize)
+        .ok()
+        .filter(|hdr| hdr.sh_type == SHT_STRTAB)
+        .or_else(|| shdrs.iter().find(|hdr| hdr.sh_type == SHT_STRTAB))
+        .unwrap();
+    let string_table = elf.section_data_as_strtab(&string_table_section).unwrap();
+
+    SymbolTable::new(
+        LittleEndian,
+        elf.ehdr.class,
+        &data[dynsym_section.sh_offset as usize
+            ..dynsym_section.sh_offset as usize + dynsym_section.sh_size as usize],
+    )
+    .iter()
+    .map(|sym| {
+        (
+            string_table.get(sym.st_name as usize).unwrap().to_string(),
+            sym,
+        )
+    })
+    .collect()
+}
+
+pub struct Relocation {
+    pub offset: u32,
+    pub symbol: u32,
+    pub r_type: u32,
+    /// only RELA relocations have an explicit addend
+    pub addend: Option<i64>,
+}
+
+/// Every dynamic relocation of the file, REL and RELA.
+pub fn get_relocations(data: &[u8]) -> Vec<Relocation> {
+    let elf = elf::ElfBytes::<LittleEndian>::minimal_parse(data).unwrap();
+    let Some(shdrs) = elf.section_headers() else {
+        return Vec::new();
+    };
+
+    let mut relocations = Vec::new();
+    for shdr in shdrs.iter() {
+        if let Ok(rels) = elf.section_data_as_rels(&shdr) {
+            relocations.extend(rels.map(|rel| Relocation {
+                offset: rel.r_offset as u32,
+                symbol: rel.r_sym,
+                r_type: rel.r_type,
+                addend: None,
+            }));
+        } else if let Ok(relas) = elf.section_data_as_relas(&shdr) {
+            relocations.extend(relas.map(|rela| Relocation {
+                offset: rela.r_offset as u32,
+                symbol: rela.r_sym,
+                r_type: rela.r_type,
+                addend: Some(rela.r_addend),
+            }));
+        }
+    }
+    relocations
+}
+
+/// GOT slot -> symbol of the JUMP_SLOT and GLOB_DAT relocations.
+fn get_got_symbols(data: &[u8]) -> HashMap<u32, String> {
+    let symbols = get_dynamic_symbols(data);
+    let is_import = |r_type: u32| {
+        matches!(
+            r_type,
+            elf::abi::R_ARM_JUMP_SLOT
+                | elf::abi::R_ARM_GLOB_DAT
+                | elf::abi::R_AARCH64_JUMP_SLOT
+                | elf::abi::R_AARCH64_GLOB_DAT
+        )
+    };
+
+    get_relocations(data)
+        .into_iter()
+        .filter(|relocation| relocation.symbol != 0 && is_import(relocation.r_type))
+        .filter_map(|relocation| {
+            symbols
+                .get(relocation.symbol as usize)
+                .map(|(name, _)| (relocation.offset, name.clone()))
+        })
+        .collect()
+}
+
+fn get_needed_libraries(data: &[u8]) -> Vec<String> {
+    let elf = elf::ElfBytes::<LittleEndian>::minimal_parse(data).unwrap();
+    let (Ok(Some(dynamic)), Ok(Some((_, strtab)))) = (elf.dynamic(), elf.dynamic_symbol_table())
+    else {
+        return Vec::new();
+    };
+
+    dynamic
+        .iter()
+        .filter(|entry| entry.d_tag == elf::abi::DT_NEEDED)
+        .filter_map(|entry| strtab.get(entry.d_val() as usize).ok())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Imported symbol -> providing library. Symbol versions name the library
+/// (libc.so, libm.so, ...); other symbols are looked up in the DT_NEEDED
+/// libraries found next to the library (in the same APK directory for a
+/// library read from an APK), in load order.
+fn get_import_libraries(
+    data: &[u8],
+    path: &Path,
+    needed_libraries: &[String],
+) -> HashMap<String, String> {
+    let elf = elf::ElfBytes::<LittleEndian>::minimal_parse(data).unwrap();
+    let versions = elf.symbol_version_table().ok().flatten();
+    let imports: Vec<(usize, String)> = get_dynamic_symbols(data)
+        .into_iter()
+        .enumerate()
+        .filter(|(_, (name, symbol))| !name.is_empty() && symbol.is_undefined())
+        .map(|(index, (name, _))| (index, name))
+        .collect();
+
+    let mut import_libraries = HashMap::new();
+    for (index, name) in &imports {
+        if let Some(requirement) = versions
+            .as_ref()
+            .and_then(|versions| versions.get_requirement(*index).ok().flatten())
+        {
+            import_libraries.insert(name.clone(), requirement.file.to_string());
+        }
+    }
+
+    for needed in needed_libraries {
+        let Ok(needed_data) = apk::read_library(&path.with_file_name(needed)) else {
+            continue;
+        };
+        if elf::ElfBytes::<LittleEndian>::minimal_parse(&needed_data).is_err() {
+            continue;
+        }
+        let exports: HashSet<String> = get_dynamic_symbols(&needed_data)
+            .into_iter()
+            .filter(|(_, symbol)| !symbol.is_undefined())
+            .map(|(name, _)| name)
+            .collect();
+
+        for (_, name) in &imports {
+            if exports.contains(name) && !import_libraries.contains_key(name) {
+                import_libraries.insert(name.clone(), needed.clone());
+            }
+        }
+    }
+
+    import_libraries
+}
+
+pub fn dump_symbols(data: &[u8]) -> (HashMap<String, u32>, HashMap<u32, String>) {
+    let mut sym_addr_map: HashMap<String, u32> = HashMap::default();
+    let mut addr_sym_map: HashMap<u32, String> = HashMap::default();
+
+    for (name, sym) in get_dynamic_symbols(data) {
+        sym_addr_map.insert(name.clone(), sym.st_value as u32);
+        addr_sym_map.insert(sym.st_value as u32, name);
+    }
+
+    (sym_addr_map, addr_sym_map)
+}
@@ -1,346 +1,201 @@
+mod address_info;
+mod apk;
 mod binreader;
-
-use binreader::BinReader;
-use elf::{endian::LittleEndian, symbol::SymbolTable};
-use serde::Serialize;
-use std::collections::HashMap;
-
-const SHT_DYNSYM: u32 = 0xb;
-const SHT_STRTAB: u32 = 0x3;
-
-#[derive(Debug, Default, Serialize)]
-struct Class {
-    pub name: String,
-    pub base: Vec<Class>,
-}
-
-impl Class {
-    pub fn push_base(&mut self) -> &mut Class {
-        self.base.push(Class::default());
-        self.base.last_mut().unwrap()
-    }
-
-    pub fn get_display(&self) -> String {
-        let mut lines: Vec<String> = Vec::new();
-        self._get_display(&mut lines, 0);
-        lines.join("\n")
-    }
-
-    fn _get_display(&self, buf: &mut Vec<String>, mut level: u32) {
-        let demangled = cpp_demangle::Symbol::new(&self.name)
-            .expect("failed to parse symbol")
-            .demangle()
-            .expect("failed to demangle symbol");
-        let line = format!(
-            "{}{}",
-            std::iter::repeat(" ")
-                .take(4 * level as usize)
-                .collect::<String>(),
-            demangled
-        );
-        buf.push(line);
-
-        level += 1;
-
-        for base in &self.base {
-            base._get_display(buf, level);
-        }
-    }
-}
-
-fn get_section_by_name<S: AsRef<str>>(
-    data: &Vec<u8>,
-    section_name: S,
-) -> Option<elf::section::SectionHeader> {
-    let elf = elf::ElfBytes::<elf::endian::LittleEndian>::minimal_parse(data).unwrap();
-    let (shdrs_r, strtab_r) = elf.section_headers_with_strtab().unwrap();
-    let (shdrs, strtab) = (shdrs_r.unwrap(), strtab_r.unwrap());
-
-    if let Some(header) = shdrs
-        .into_iter()
-        .filter(|header| match strtab.get(header.sh_name as usize) {
-            Ok(header_name) => header_name == section_name.as_ref(),
-            Err(_) => false,
-        })
-        .next()
-    {
-        Some(header)
-    } else {
-        None
-    }
-}
-
-fn get_section_range(data: &Vec<u8>, section_name: &String) -> Option<(u64, u64)> {
-    if let Some(header) = get_section_by_name(data, section_name) {
-        Some((header.sh_offset, header.sh_offset + header.sh_size))
-    } else {
-        None
-    }
-}
-
-fn get_file_offset_for_address_under_section<S: AsRef<str>>(
-    data: &Vec<u8>,
-    section_name: S,
-    addr: u64,
-) -> u64 {
-    let section = get_section_by_name(data, section_name).unwrap();
-    let relative_address = addr - section.sh_addr;
-    section.sh_offset + relative_address
-}
-
-fn dump_symbols(data: &Vec<u8>) -> (HashMap<String, u32>, HashMap<u32, String>) {
-    let elf = elf::ElfBytes::<elf::endian::LittleEndian>::minimal_parse(data).unwrap();
-    let shdrs = elf.section_headers().unwrap();
-
-    let dynsym_section = shdrs
-        .iter()
-        .filter(|hdr| hdr.sh_type == SHT_DYNSYM)
-        .next()
-        .expect("no SHT_DYNSYM");
-    let string_table_section = shdrs
-        .iter()
-        .filter(|hdr| hdr.sh_type == SHT_STRTAB)
-        .next()
-        .unwrap();
-    let string_table = elf.section_data_as_strtab(&string_table_section).unwrap();
-
-    let mut sym_addr_map: HashMap<String, u32> = HashMap::default();
-    let mut addr_sym_map: HashMap<u32, String> = HashMap::default();
-
-    SymbolTable::new(
-        LittleEndian,
-        elf::file::Class::ELF32,
-        &data[dynsym_section.sh_offset as usize
-            ..dynsym_section.sh_offset as usize + dynsym_section.sh_size as usize],
-    )
-    .iter()
-    .for_each(|sym| {
-        sym_addr_map.insert(
-            string_table.get(sym.st_name as usize).unwrap().to_string(),
-            sym.st_value as u32,
-        );
-        addr_sym_map.insert(
-            sym.st_value as u32,
-            string_table.get(sym.st_name as usize).unwrap().to_string(),
-        );
-    });
-
-    return (sym_addr_map, addr_sym_map);
-}
-
-fn handle_typename(
-    reader: &mut BinReader,
-    output: &mut Class,
-    offset: u32,
-    start_data_rel_ro: u32,
-    rtti_class_offsets: &Vec<u32>,
-) {
-    reader.set_position(offset + 4);
-
-    let reader_data = reader.get_data();
-    let name = reader
-        .read_cstr(Some(|address: u32| {
-            return get_file_offset_for_address_under_section(reader_data, ".rodata", address.into())
-                as u32;
-        }))
-        .expect("failed to read type name")
-        .to_string();
-    let second_field = reader
-        .read_u32()
-        .expect("failed to read dword after type name");
-
-    output.name = name;
-
-    if rtti_class_offsets
-        .iter()
-        .find(|offset| **offset == second_field)
-        .is_some()
-    {
-        // some typeinfos will only provide a reference to the type class
-        // and the type name (see _ZTIN7cocos2d15CCTouchDelegateE).
-        // we cannot just check if second_field is above the start address
-        // of .data.rel.ro because another typeinfo is declared right after.
-        return;
-    }
-
-    if second_field > start_data_rel_ro {
-        // ; reference to rtti's type class
-        // ; type name
-        // ; parent typename < second_field
-        // reference: _ZTIN7cocos2d10CCMenuItemE (1.3)
-        handle_typename(
-            reader,
-            output.push_base(),
-            get_file_offset_for_address_under_section(
-                reader.get_data(),
-                ".data.rel.ro",
-                second_field.into(),
-            )
-            .try_into()
-            .unwrap(),
-            start_data_rel_ro,
-            rtti_class_offsets,
-        );
-    } else {
-        let third_field = reader
-            .read_u32()
-            .expect("failed to read dword after second field");
-
-        if third_field > start_data_rel_ro {
-            // ; reference to rtti's type class
-            // ; type name
-            // -- new vtable --
-            // vtable's offset to this = 0x00000000 < second_field
-            // class's typeinfo < third_field
-            //
-            // we are already over the typeinfo we wanted to read.
-            // reference: _ZTI17TextInputDelegate (1.3)
-            return;
-        } else {
-            // ; reference to rtti's type class
-            // ; type name
-            // ; attribute < second_field
-            // ; count of base classes < third_field
-            // .. [base classes]
-            //
-            // base class:
-            //     ; base class type info
-            //     ; base class attributes
-            // reference: _ZTIN7cocos2d7CCLayerE (1.3)
-
-            let _attribute = second_field;
-            let base_class_count = third_field;
-
-            for _ in 0..base_class_count {
-                let type_descriptor_address = reader
-                    .read_u32()
-                    .expect("failed to read address to base class typeinfo");
-                let type_descriptor_offset = get_file_offset_for_address_under_section(
-                    reader.get_data(),
-                    ".data.rel.ro",
-                    type_descriptor_address.into(),
-                );
-                let _base_attribute = reader
-                    .read_u32()
-                    .expect("failed to read attribute of base class");
-                let return_offset = reader.get_position();
-                handle_typename(
-                    reader,
-                    output.push_base(),
-                    type_descriptor_offset.try_into().unwrap(),
-                    start_data_rel_ro,
-                    rtti_class_offsets,
-                );
-                reader.set_position(return_offset.try_into().unwrap());
-            }
-        }
-    }
-}
-
-fn get_vtable_mangled_name(class_name: &String) -> String {
-    if !class_name.find("::").is_some() {
-        return format!("_ZTV{}{}", class_name.len(), class_name);
-    }
-    let mut mangled = "_ZTVN".to_string();
-
-    for name in class_name.split("::") {
-        mangled.push_str(format!("{}{}", name.len(), name).as_str());
-    }
-
-    mangled.push_str("E");
-    mangled.to_string()
-}
-
-fn handle_vtable(
-    reader: &mut BinReader,
-    class_typeinfo: u32,
-    cxxabi_offsets: &Vec<u32>,
-) -> (i32, Vec<u32>) {
-    let offset_to_this = reader.read_i32().unwrap();
-    reader.set_position_relative(4); // skip reference to typeinfo
-
-    let mut function_pointers = Vec::new();
-
-    while let Some(addr) = reader.read_u32() {
-        let next_u32 = reader.read_u32().expect("failed to read ahead");
-
-        let in_typeinfo = cxxabi_offsets
-            .iter()
-            .find(|offset| **offset == addr)
-            .is_some();
-        let in_offset_to_this = next_u32 == class_typeinfo;
-
-        if in_typeinfo || in_offset_to_this || addr == 0 {
-            reader.set_position_relative(-8);
-            break;
-        }
-        reader.set_position_relative(-4);
-
-        function_pointers.push(addr);
-    }
-
-    (offset_to_this, function_pointers)
-}
-
-fn get_class_vtable(
-    reader: &mut BinReader,
-    vtable_addr: u32,
-    cxxabi_offsets: &Vec<u32>,
-) -> Vec<(i32, Vec<u32>)> {
-    let mut result: Vec<(i32, Vec<u32>)> = Vec::new();
-
-    reader.set_position(vtable_addr + 4);
-    let class_typeinfo = reader.read_u32().unwrap();
-    reader.set_position(vtable_addr);
-
-    let mut table_offset = vtable_addr;
-
-    loop {
-        reader.set_position(table_offset + 4);
-        let typeinfo_addr = reader.read_u32().unwrap();
-        reader.set_position(table_offset);
-
-        if typeinfo_addr != class_typeinfo {
-            break;
-        }
-
-        let table = handle_vtable(reader, class_typeinfo, &cxxabi_offsets);
-        result.push(table);
-        table_offset = reader.get_position() as u32;
-    }
-
-    result
-}
+mod class_index;
+mod class_info;
+mod classes;
+mod constructors;
+mod core;
+mod disasm;
+mod dump;
+mod explain;
+mod fields;
+mod graph;
+mod hierarchy;
+mod layout;
+mod library;
+mod objects;
+mod overrides;
+mod returns;
+mod rtti;
+mod sizes;
+#[cfg(test)]
+mod test_elf;
+mod trivial;
+mod unwind;
+mod validate;
+mod vcall;
+
+use library::Library;
 
 fn main() {
     let cmd = clap::Command::new("reimu")
+        .arg(
+            clap::arg!(--"load-base" <ADDRESS> "Read the first -L library as a raw memory dump of the library, ADDRESS being where the dump starts in the process (e.g. 0x7a3c200000)")
+                .global(true),
+        )
+        .arg(
+            clap::arg!(--"symbols-from" <PATH> "The on-disk library of a memory dump, for its symbols and relocations instead of the dynamic segment of the dump")
+                .value_parser(clap::value_parser!(std::path::PathBuf))
+                .requires("load-base")
+                .global(true),
+        )
         .subcommand(
             clap::command!("class-info")
                 .group(
                     clap::ArgGroup::new("actions")
-                        .args(["create-vtable-ida", "create-vtable-cpp", "dump-vtable-json", "inheritance"])
+                        .args(["create-vtable-ida", "create-vtable-cpp", "dump-vtable-json", "inheritance", "layout", "explain"])
                         .required(true),
                 )
                 .arg(clap::arg!(--"create-vtable-ida"))
                 .arg(clap::arg!(--"create-vtable-cpp"))
                 .arg(clap::arg!(--"inheritance"))
-                .arg(clap::arg!(--"dump-vtable-json"))
+                .arg(clap::arg!(--"dump-vtable-json" "Dump the slots of the primary vtable as a JSON array (the class size is in `classes --format json`)"))
+                .arg(clap::arg!(--"layout" "Show the offset of every base subobject and vptr"))
+                .arg(clap::arg!(--"explain" "Dump the words of the vtable and of the typeinfos of the class and its bases, with what each one is read as"))
+                .arg(library_path_arg())
                 .arg(
-                    clap::arg!(-L --"library-path" <PATH>)
-                        .value_parser(clap::value_parser!(std::path::PathBuf))
-                        .required(true),
+                    clap::arg!([CLASS] ... "The class names (case sensitive) (e.g. FLAlertLayer, cocos2d::CCNode), their _ZTV/_ZTI/_ZTS symbols, the addresses of their vtables or typeinfos (e.g. 0x5f0a28) or glob patterns (e.g. cocos2d::CC*Layer)")
+                        .required_unless_present("all"),
                 )
+                .arg(clap::arg!(--"all" "Select every class with a vtable").conflicts_with("CLASS"))
+                .arg(clap::arg!(--"regex" "Match every CLASS as a regular expression against the whole class name"))
                 .arg(
-                    clap::arg!(<CLASS> "The class name (case sensitive) (e.g. FLAlertLayer, cocos2d::CCNode)")
-                        .required(true),
+                    clap::arg!(-o --"output-dir" <DIR> "Write one file per class into DIR instead of printing a combined document")
+                        .value_parser(clap::value_parser!(std::path::PathBuf)),
                 ),
         )
         .subcommand(
             clap::command!("symbols")
-                .arg(clap::arg!(-L --"library-path" <PATH>)
+                .arg(clap::arg!(-L --"library-path" <PATH> "The library, or app.apk!/lib/<ABI>/<library> for one inside an APK")
                     .value_parser(clap::value_parser!(std::path::PathBuf))
                     .required(true)
                 )
+        )
+        .subcommand(
+            clap::command!("classes")
+                .about("List every class with a vtable")
+                .arg(library_path_arg())
+                .arg(
+                    clap::arg!(--"format" <FORMAT>)
+                        .value_parser(["text", "json", "csv"])
+                        .default_value("text"),
+                )
+                .arg(clap::arg!(--"namespace" <NAMESPACE> "Only list classes declared in NAMESPACE or in namespaces nested in it (e.g. cocos2d, :: for the global namespace)")),
+        )
+        .subcommand(
+            clap::command!("derived")
+                .about("List the classes deriving from a class")
+                .arg(library_path_arg())
+                .arg(clap::arg!(--"direct" "Only list the classes deriving directly from CLASS"))
+                .arg(clap::arg!(--"flat" "Print a flat list with the depth of every class instead of a tree"))
+                .arg(clap::arg!(<CLASS> "The base class name, _ZTV/_ZTI/_ZTS symbol, or vtable/typeinfo address").required(true)),
+        )
+        .subcommand(
+            clap::command!("graph")
+                .about("Export the inheritance graph as Graphviz DOT or Mermaid")
+                .arg(library_path_arg())
+                .arg(
+                    clap::arg!(--"format" <FORMAT>)
+                        .value_parser(["dot", "mermaid"])
+                        .default_value("dot"),
+                )
+                .arg(
+                    clap::arg!(--"depth" <DEPTH> "Only follow DEPTH links away from CLASS")
+                        .value_parser(clap::value_parser!(usize))
+                        .requires("CLASS"),
+                )
+                .arg(clap::arg!(--"namespace" <NAMESPACE> "Only include classes declared in NAMESPACE or in namespaces nested in it"))
+                .arg(clap::arg!([CLASS] "Only export the bases and derived classes of CLASS instead of the whole library")),
+        )
+        .subcommand(
+            clap::command!("overrides")
+                .about("Find every class overriding a virtual function")
+                .arg(library_path_arg())
+                .arg(
+                    clap::arg!(--"format" <FORMAT>)
+                        .value_parser(["text", "json"])
+                        .default_value("text"),
+                )
+                .arg(clap::arg!(--"inherited" "Also list the derived classes that keep the base implementation"))
+                .arg(clap::arg!(<METHOD> "The qualified method with its arguments (e.g. \"cocos2d::CCLayer::ccTouchBegan(cocos2d::CCTouch*, cocos2d::CCEvent*)\")").required(true)),
+        )
+        .subcommand(
+            clap::command!("address")
+                .about("Find the vtable slots pointing at a function")
+                .arg(library_path_arg())
+                .arg(
+                    clap::arg!(--"format" <FORMAT>)
+                        .value_parser(["text", "json"])
+                        .default_value("text"),
+                )
+                .arg(clap::arg!(<ADDRESS> "The address of the function (e.g. 0x2a4c31)").required(true)),
+        )
+        .subcommand(
+            clap::command!("vcall")
+                .about("Resolve a virtual call through a vtable offset for a class and its subclasses")
+                .arg(library_path_arg())
+                .arg(
+                    clap::arg!(--"format" <FORMAT>)
+                        .value_parser(["text", "json"])
+                        .default_value("text"),
+                )
+                .arg(
+                    clap::arg!(--"subobject" <OFFSET> "The offset of the subobject whose vptr is loaded, selecting a secondary vtable group (e.g. 0x100)")
+                        .default_value("0"),
+                )
+                .arg(clap::arg!(<CLASS> "The static type of the object").required(true))
+                .arg(clap::arg!(<OFFSET> "The byte offset loaded from the vptr (e.g. 0x84)").required(true)),
+        )
+        .subcommand(
+            clap::command!("constructors")
+                .about("Find the constructors and destructors of classes through their vtable pointer stores")
+                .arg(library_path_arg())
+                .arg(
+                    clap::arg!(--"format" <FORMAT>)
+                        .value_parser(["text", "json"])
+                        .default_value("text"),
+                )
+                .arg(clap::arg!([CLASS] ... "Only list the functions constructing or destroying these classes")),
+        )
+        .subcommand(
+            clap::command!("validate")
+                .about("Check every vtable and typeinfo against the C++ ABI, exiting with 1 when anything is found")
+                .arg(library_path_arg())
+                .arg(
+                    clap::arg!(--"format" <FORMAT>)
+                        .value_parser(["text", "json"])
+                        .default_value("text"),
+                ),
+        )
+        .subcommand(
+            clap::command!("object")
+                .about("Identify the dynamic class of an object in an ELF core dump through its vptr")
+                .arg(library_path_arg())
+                .arg(
+                    clap::arg!(--"format" <FORMAT>)
+                        .value_parser(["text", "json"])
+                        .default_value("text"),
+                )
+                .group(
+                    clap::ArgGroup::new("memory")
+                        .args(["core", "bytes"])
+                        .required(true),
+                )
+                .arg(
+                    clap::arg!(--"core" <PATH> "The core dump of the process")
+                        .value_parser(clap::value_parser!(std::path::PathBuf)),
+                )
+                .arg(
+                    clap::arg!(--"bytes" <HEX> "The bytes of the object in memory order, as copied from a debugger (e.g. \"d8 15 00 40 00 00 00 00\"), - to read them from stdin. Every vptr among them is identified")
+                        .requires("library-base"),
+                )
+                .arg(clap::arg!(--"library-base" <ADDRESS> "The address the first -L library is loaded at, when the file mappings of the core dump do not name it (e.g. 0x7a3c200000)"))
+                .arg(clap::arg!(--"scan" <SIZE> "Identify every object with a vptr in the SIZE bytes from ADDRESS (e.g. 0x1000)"))
+                .arg(
+                    clap::arg!([ADDRESS] "The address of the object or of one of its base subobjects (e.g. 0x7a41e3c0a0), or the address the --bytes were copied from")
+                        .required_unless_present("bytes"),
+                ),
         );
 
     match cmd.get_matches().subcommand() {
@@ -348,266 +203,574 @@ fn main() {
             let game_bin_path = matches
                 .get_one::<std::path::PathBuf>("library-path")
                 .unwrap();
-            let game_bin = std::fs::read(game_bin_path)
-                .expect(format!("failed to read given path: {:?}", game_bin_path).as_str());
+            let game_bin = apk::read_library(game_bin_path).unwrap_or_else(|error| {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            });
             println!(
                 "{}",
-                serde_json::to_string_pretty(&dump_symbols(&game_bin)).unwrap()
+                serde_json::to_string_pretty(&library::dump_symbols(&game_bin)).unwrap()
             );
         }
-        Some(("class-info", matches)) => {
-            let game_bin_path = matches
-                .get_one::<std::path::PathBuf>("library-path")
-                .unwrap();
-            let game_bin = std::fs::read(game_bin_path)
-                .expect(format!("failed to read given path: {:?}", game_bin_path).as_str());
-            let mut reader = BinReader::new(&game_bin);
+        Some(("classes", matches)) => {
+            let library = load_library(matches);
+            let sizes = sizes::ClassSizes::new(&library);
+            let mut summaries = classes::get_class_summaries(&library, &sizes);
 
-            let (sym_to_addr, addr_to_sym) = dump_symbols(&game_bin);
-            let action = matches.get_one::<clap::Id>("actions").unwrap().as_str();
-            let class_name = matches.get_one::<String>("CLASS").unwrap();
-
-            let cxxabi_offsets = vec![
-                sym_to_addr["_ZTVN10__cxxabiv120__si_class_type_infoE"] + 8,
-                sym_to_addr["_ZTVN10__cxxabiv117__class_type_infoE"] + 8,
-                sym_to_addr["_ZTVN10__cxxabiv121__vmi_class_type_infoE"] + 8,
-            ];
-
-            match action {
-                "inheritance" => {
-                    let mut inherit_info = Class::default();
-                    let vtable_symbol = get_vtable_mangled_name(class_name);
-                    let vtable_addr = sym_to_addr
-                        .get(&vtable_symbol)
-                        .expect(format!("unknown symbol for vtable: {:?}", vtable_symbol).as_str());
-                    let vtable_offset = get_file_offset_for_address_under_section(
-                        &game_bin,
-                        ".data.rel.ro",
-                        (*vtable_addr).into(),
-                    ) as u32;
-
-                    reader.set_position(vtable_offset + 4);
-                    let typeinfo_offset = get_file_offset_for_address_under_section(
-                        &game_bin,
-                        ".data.rel.ro",
-                        reader.read_u32().unwrap() as u64,
-                    );
-                    reader.set_position(vtable_offset);
-
-                    handle_typename(
-                        &mut reader,
-                        &mut inherit_info,
-                        typeinfo_offset as u32,
-                        get_section_range(&game_bin, &".data.rel.ro".to_string())
-                            .unwrap()
-                            .0 as u32,
-                        &cxxabi_offsets,
+            if let Some(namespace) = matches.get_one::<String>("namespace") {
+                summaries.retain(|summary| classes::is_in_namespace(&summary.name, namespace));
+            }
+
+            match matches.get_one::<String>("format").unwrap().as_str() {
+                "json" => println!("{}", serde_json::to_string_pretty(&summaries).unwrap()),
+                "csv" => println!("{}", classes::get_csv(&summaries)),
+                _ => println!("{}", classes::get_text(&summaries)),
+            }
+        }
+        Some(("derived", matches)) => {
+            let library = load_library(matches);
+            let hierarchy = hierarchy::Hierarchy::new(&library);
+            let selector = matches.get_one::<String>("CLASS").unwrap();
+            let typeinfo = hierarchy
+                .find_typeinfo(&library, selector)
+                .unwrap_or_else(|| {
+                    eprintln!(
+                        "{}",
+                        library.class_index.get_unknown_class_message(selector)
                     );
+                    std::process::exit(1);
+                });
+            let max_depth = matches.get_flag("direct").then_some(1);
 
-                    println!("{}", inherit_info.get_display());
+            if matches.get_flag("flat") {
+                for (depth, derived) in hierarchy.get_derived_flat(typeinfo, max_depth) {
+                    println!("{}\t{}", depth, hierarchy.get_class_name(derived));
                 }
-                "create-vtable-ida" | "create-vtable-cpp" | "dump-vtable-json" => {
-                    #[derive(Serialize)]
-                    struct DumpVtableJSONOutput {
-                        name: String,
-                        address: u32,
-                        offset: u32,
-                    }
-
-                    let vtable_symbol = get_vtable_mangled_name(class_name);
-                    let vtable_offset = get_file_offset_for_address_under_section(
-                        reader.get_data(),
-                        ".data.rel.ro",
-                        (*sym_to_addr.get(&vtable_symbol).expect(
-                            format!("unknown symbol for vtable: {:?}", vtable_symbol).as_str(),
-                        ))
-                        .into(),
-                    ) as u32;
-                    let dump = get_class_vtable(
-                        &mut reader,
-                        vtable_offset.try_into().unwrap(),
-                        &cxxabi_offsets,
+            } else {
+                println!("{}", hierarchy.get_class_name(typeinfo));
+                for (depth, derived) in hierarchy.get_derived_tree(typeinfo, max_depth) {
+                    println!(
+                        "{}{}",
+                        " ".repeat(4 * depth),
+                        hierarchy.get_class_name(derived)
                     );
-
-                    if action == "dump-vtable-json" {
-                        let mut entry: Vec<DumpVtableJSONOutput> = Vec::new();
-                        let mut i = 0;
-                        dump[0].1.iter().for_each(|addr| {
-                            entry.push(DumpVtableJSONOutput {
-                                name: (&addr_to_sym[addr]).clone(),
-                                address: *addr,
-                                offset: 8 + (4 * i),
+                }
+            }
+        }
+        Some(("graph", matches)) => {
+            let library = load_library(matches);
+            let hierarchy = hierarchy::Hierarchy::new(&library);
+            let namespace = matches.get_one::<String>("namespace").map(String::as_str);
+
+            let graph = match matches.get_one::<String>("CLASS") {
+                Some(selector) => {
+                    let typeinfo =
+                        hierarchy
+                            .find_typeinfo(&library, selector)
+                            .unwrap_or_else(|| {
+                                eprintln!(
+                                    "{}",
+                                    library.class_index.get_unknown_class_message(selector)
+                                );
+                                std::process::exit(1);
                             });
-                            i += 1;
-                        });
-                        println!("{}", serde_json::to_string_pretty(&entry).unwrap());
-                    } else if action == "create-vtable-cpp" {
-                        let mut inherit_info = Class::default();
-
-                        reader.set_position(vtable_offset + 4);
-                        let typeinfo_addr = get_file_offset_for_address_under_section(
-                            reader.get_data(),
-                            ".data.rel.ro",
-                            reader.read_u32().unwrap().into(),
-                        ) as u32;
-                        reader.set_position(vtable_offset);
-
-                        handle_typename(
-                            &mut reader,
-                            &mut inherit_info,
-                            typeinfo_addr,
-                            get_section_range(&game_bin, &".data.rel.ro".to_string())
-                                .unwrap()
-                                .0 as u32,
-                            &cxxabi_offsets,
-                        );
+                    graph::Graph::new_neighborhood(
+                        &hierarchy,
+                        typeinfo,
+                        matches.get_one::<usize>("depth").copied(),
+                        namespace,
+                    )
+                }
+                None => graph::Graph::new_library(&hierarchy, namespace),
+            };
 
-                        println!(
-                            "NOTE: The return type for these functions cannot be determined and are defined as `void`"
-                        );
+            match matches.get_one::<String>("format").unwrap().as_str() {
+                "mermaid" => println!("{}", graph.get_mermaid()),
+                _ => println!("{}", graph.get_dot()),
+            }
+        }
+        Some(("overrides", matches)) => {
+            let library = load_library(matches);
+            let hierarchy = hierarchy::Hierarchy::new(&library);
+            let report = overrides::find_overrides(
+                &library,
+                &hierarchy,
+                matches.get_one::<String>("METHOD").unwrap(),
+            )
+            .unwrap_or_else(|message| {
+                eprintln!("{}", message);
+                std::process::exit(1);
+            });
+
+            match matches.get_one::<String>("format").unwrap().as_str() {
+                "json" => println!("{}", serde_json::to_string_pretty(&report).unwrap()),
+                _ => println!("{}", report.get_display(matches.get_flag("inherited"))),
+            }
+        }
+        Some(("address", matches)) => {
+            let library = load_library(matches);
+            let hierarchy = hierarchy::Hierarchy::new(&library);
+            let address = matches.get_one::<String>("ADDRESS").unwrap();
+            let Some(address) = address
+                .strip_prefix("0x")
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            else {
+                eprintln!(
+                    "expected a hexadecimal address (e.g. 0x2a4c31), got {:?}",
+                    address
+                );
+                std::process::exit(1);
+            };
+            let info = address_info::get_address_info(&library, &hierarchy, address);
 
-                        if inherit_info.base.len() > 0 {
-                            let base = inherit_info
-                                .base
-                                .iter()
-                                .map(|class| {
-                                    let demangled = cpp_demangle::Symbol::new(&class.name)
-                                        .expect("failed to parse symbol")
-                                        .demangle()
-                                        .expect("failed to demangle symbol");
-                                    format!("public {}", demangled)
-                                })
-                                .collect::<Vec<String>>()
-                                .join(", ");
-                            println!("class {} : {} {{", class_name, base);
-                        } else {
-                            println!("class {} {{", class_name);
-                        }
-
-                        println!("public:");
-
-                        let mut declarations: Vec<String> = Vec::new();
-                        let mut declare_virtual_dtor = false;
-
-                        for addr in &dump[0].1 {
-                            let symbol = addr_to_sym[&addr].to_owned();
-                            let demangled = cpp_demangle::Symbol::new(&symbol)
-                                .expect("failed to parse symbol")
-                                .demangle()
-                                .expect("failed to demangle symbol");
-
-                            if symbol.ends_with("D0Ev") || symbol.ends_with("D1Ev") {
-                                declare_virtual_dtor = true;
-                            } else if demangled.starts_with(class_name) {
-                                declarations
-                                    .push(format!("void {}", &demangled[class_name.len() + 2..]));
-                            }
-                        }
-
-                        if declare_virtual_dtor {
-                            if let Some(ns_end) = class_name.rfind("::") {
-                                println!("    virtual ~{}();", &class_name[ns_end + 2..]);
-                            } else {
-                                println!("    virtual ~{}();", &class_name);
-                            }
-                        }
-
-                        for declaration in declarations {
-                            println!("    virtual {};", declaration);
-                        }
-
-                        println!("}};");
-                    } else if action == "create-vtable-ida" {
-                        let mut main_class_fields: Vec<String> = Vec::new();
-                        let mut last_offset_to_this = 0;
-                        let mut filler_counter = 0;
-
-                        for table in dump {
-                            let offset_to_this = table.0.abs();
-                            let vft_struct_name = format!("{}_{}_vft", class_name, offset_to_this);
-                            let filler_size = (offset_to_this - last_offset_to_this) - 4;
-
-                            if filler_size > 0 {
-                                main_class_fields
-                                    .push(format!("char fill_{}[{}]", filler_counter, filler_size));
-                                filler_counter += 1;
-                            }
-                            main_class_fields
-                                .push(format!("{}* __vtable_{}", vft_struct_name, offset_to_this));
-
-                            last_offset_to_this = offset_to_this;
-
-                            let mut function_name_counter: HashMap<String, u32> = HashMap::new();
-
-                            println!("struct {} {{", vft_struct_name);
-
-                            table.1.iter().for_each(|addr| {
-                                let symbol = addr_to_sym[addr].to_owned();
-
-                                if symbol.ends_with("D1Ev") {
-                                    println!("    void (*__dtor)({}*);", class_name);
-                                } else if symbol.ends_with("D0Ev") {
-                                    println!("    void (*__delete)({}*);", class_name);
-                                } else {
-                                    let mut demangled = cpp_demangle::Symbol::new(&symbol)
-                                        .expect("failed to parse symbol")
-                                        .demangle()
-                                        .expect("failed to demangle symbol");
-
-                                    if demangled.starts_with("{virtual override thunk") {
-                                        demangled =
-                                            demangled.split_once(",").unwrap().1[1..].to_string();
-                                        demangled = demangled[0..demangled.len() - 2].to_string();
-                                    }
-
-                                    let start_of_args = demangled.find("(").unwrap();
-
-                                    let mut name = demangled[0..start_of_args]
-                                        .split("::")
-                                        .last()
-                                        .unwrap()
-                                        .to_string();
-
-                                    if function_name_counter.contains_key(&name) {
-                                        function_name_counter
-                                            .insert(name.clone(), function_name_counter[&name] + 1);
-                                        name = format!("{}_{}", name, function_name_counter[&name]);
-                                    } else {
-                                        function_name_counter.insert(name.to_owned(), 1);
-                                    }
-
-                                    let mut sig = (&demangled[start_of_args..]).to_string();
-
-                                    if sig.starts_with("()") {
-                                        sig = format!("({}*){}", class_name, &sig[2..]);
-                                    } else {
-                                        sig = format!("({}*, {}", class_name, &sig[1..]);
-                                    }
-
-                                    if sig.ends_with("const") {
-                                        sig = sig[0..sig.len() - 5].to_string();
-                                        sig = sig.trim_end().to_string();
-                                    }
-
-                                    println!("    void (*{}){};", name, sig);
-                                }
-                            });
+            match matches.get_one::<String>("format").unwrap().as_str() {
+                "json" => println!("{}", serde_json::to_string_pretty(&info).unwrap()),
+                _ => println!("{}", info.get_display()),
+            }
+        }
+        Some(("vcall", matches)) => {
+            let library = load_library(matches);
+            let hierarchy = hierarchy::Hierarchy::new(&library);
+            let subobject_offset = parse_number(matches.get_one::<String>("subobject").unwrap());
+            let offset = parse_number(matches.get_one::<String>("OFFSET").unwrap());
+            let call = vcall::resolve_virtual_call(
+                &library,
+                &hierarchy,
+                matches.get_one::<String>("CLASS").unwrap(),
+                subobject_offset as i32,
+                offset,
+            )
+            .unwrap_or_else(|message| {
+                eprintln!("{}", message);
+                std::process::exit(1);
+            });
+
+            match matches.get_one::<String>("format").unwrap().as_str() {
+                "json" => println!("{}", serde_json::to_string_pretty(&call).unwrap()),
+                _ => println!("{}", call.get_display()),
+            }
+        }
+        Some(("constructors", matches)) => {
+            let library = load_library(matches);
+            let hierarchy = hierarchy::Hierarchy::new(&library);
+            let mut writers = constructors::find_vptr_writers(&library, &hierarchy);
+
+            if let Some(selectors) = matches.get_many::<String>("CLASS") {
+                let class_names: Vec<String> = selectors
+                    .map(|selector| {
+                        let typeinfo =
+                            hierarchy
+                                .find_typeinfo(&library, selector)
+                                .unwrap_or_else(|| {
+                                    eprintln!(
+                                        "{}",
+                                        library.class_index.get_unknown_class_message(selector)
+                                    );
+                                    std::process::exit(1);
+                                });
+                        hierarchy.get_class_name(typeinfo)
+                    })
+                    .collect();
+                writers = constructors::filter_classes(writers, &class_names);
+            }
 
-                            println!("}};");
-                        }
+            match matches.get_one::<String>("format").unwrap().as_str() {
+                "json" => println!("{}", serde_json::to_string_pretty(&writers).unwrap()),
+                _ => println!("{}", constructors::get_display(&writers)),
+            }
+        }
+        Some(("validate", matches)) => {
+            let library = load_library(matches);
+            let hierarchy = hierarchy::Hierarchy::new(&library);
+            let findings = validate::validate(&library, &hierarchy);
+
+            match matches.get_one::<String>("format").unwrap().as_str() {
+                "json" => println!("{}", serde_json::to_string_pretty(&findings).unwrap()),
+                _ => println!("{}", validate::get_display(&findings)),
+            }
+            if !findings.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Some(("object", matches)) => {
+            let library = load_library(matches);
+            let hierarchy = hierarchy::Hierarchy::new(&library);
+            let address = matches
+                .get_one::<String>("ADDRESS")
+                .map(|address| parse_address(address))
+                .unwrap_or(0);
+
+            let memory = match matches.get_one::<String>("bytes") {
+                Some(bytes) => core::CoreDump::from_bytes(
+                    address,
+                    parse_hex_bytes(bytes),
+                    library.pointer_size,
+                ),
+                None => {
+                    let core_path = matches.get_one::<std::path::PathBuf>("core").unwrap();
+                    let core = core::CoreDump::load(core_path).unwrap_or_else(|error| {
+                        eprintln!("{}", error);
+                        std::process::exit(1);
+                    });
+                    if core.pointer_size != library.pointer_size {
+                        eprintln!(
+                            "{:?} is not built for the architecture of {:?}",
+                            library.modules[0].path, core_path
+                        );
+                        std::process::exit(1);
+                    }
+                    core
+                }
+            };
+
+            let library_base = matches
+                .get_one::<String>("library-base")
+                .map(|base| parse_address(base))
+                .or_else(|| memory.get_load_address(&library.modules[0].path));
+            if library_base.is_none() {
+                eprintln!(
+                    "{:?} is not in the file mappings of the core dump, give its load address with --library-base",
+                    library.modules[0].path
+                );
+                std::process::exit(1);
+            }
+            let load_addresses =
+                objects::LoadAddresses::new(&library, |module| match module.base {
+                    0 => library_base,
+                    _ => memory.get_load_address(&module.path),
+                });
+
+            // the copied bytes are scanned whole
+            let size = match (
+                matches.get_one::<String>("scan"),
+                matches.get_one::<String>("bytes"),
+            ) {
+                (Some(size), _) => parse_number(size) as u64,
+                (None, Some(_)) => memory.get_size(),
+                (None, None) => library.pointer_size as u64,
+            };
+            let Some(vptr) = memory.read_pointer(address) else {
+                eprintln!("0x{:x} is not in the dumped memory", address);
+                std::process::exit(1);
+            };
+
+            let fields = fields::ClassFields::new(&library);
+            let objects = objects::find_objects(
+                &library,
+                &hierarchy,
+                &fields,
+                &load_addresses,
+                &memory,
+                address,
+                size,
+            );
 
-                        println!("struct {} {{", class_name);
-                        for field in main_class_fields {
-                            println!("    {};", field);
-                        }
-                        println!("}};");
+            if objects.is_empty() && !matches.contains_id("scan") && !matches.contains_id("bytes") {
+                eprintln!(
+                    "0x{:x} holds 0x{:x}, which is not the address point of a vtable",
+                    address, vptr
+                );
+                std::process::exit(1);
+            }
+
+            match matches.get_one::<String>("format").unwrap().as_str() {
+                "json" => println!("{}", serde_json::to_string_pretty(&objects).unwrap()),
+                _ => println!("{}", objects::get_display(&objects)),
+            }
+        }
+        Some(("class-info", matches)) => {
+            let library = load_library(matches);
+            let action = matches.get_one::<clap::Id>("actions").unwrap().as_str();
+            let selectors: Vec<String> = matches
+                .get_many::<String>("CLASS")
+                .unwrap_or_default()
+                .cloned()
+                .collect();
+            let classes = select_classes(
+                &library,
+                &selectors,
+                matches.get_flag("all"),
+                matches.get_flag("regex"),
+            );
+            let output_dir = matches.get_one::<std::path::PathBuf>("output-dir");
+            let hierarchy = (action == "layout").then(|| hierarchy::Hierarchy::new(&library));
+            let sizes = matches!(
+                action,
+                "inheritance" | "layout" | "create-vtable-cpp" | "create-vtable-ida"
+            )
+            .then(|| sizes::ClassSizes::new(&library));
+            let get_size =
+                |class_name: &str| sizes.as_ref().and_then(|sizes| sizes.get_size(class_name));
+            let fields = matches!(action, "create-vtable-cpp" | "create-vtable-ida")
+                .then(|| fields::ClassFields::new(&library));
+            let return_types = returns::ReturnTypes::new(&library);
+
+            let mut documents: Vec<(&String, String)> = Vec::new();
+            let mut json_documents = serde_json::Map::new();
+
+            for (class_name, vtable_symbol) in &classes {
+                match action {
+                    "inheritance" => documents.push((
+                        class_name,
+                        class_info::get_inheritance(&library, vtable_symbol, get_size(class_name)),
+                    )),
+                    "dump-vtable-json" => {
+                        let entry =
+                            class_info::dump_vtable_json(&library, vtable_symbol, &return_types);
+                        documents.push((class_name, serde_json::to_string_pretty(&entry).unwrap()));
+                        json_documents
+                            .insert(class_name.to_string(), serde_json::to_value(entry).unwrap());
+                    }
+                    "create-vtable-cpp" => documents.push((
+                        class_name,
+                        class_info::create_vtable_cpp(
+                            &library,
+                            class_name,
+                            vtable_symbol,
+                            get_size(class_name),
+                            fields.as_ref().unwrap().get(class_name),
+                            &return_types,
+                        ),
+                    )),
+                    "create-vtable-ida" => documents.push((
+                        class_name,
+                        class_info::create_vtable_ida(
+                            &library,
+                            class_name,
+                            vtable_symbol,
+                            get_size(class_name),
+                            &fields.as_ref().unwrap().get_object_fields(
+                                &library,
+                                library.get_vtable_typeinfo(library.sym_to_addr[*vtable_symbol]),
+                            ),
+                            &return_types,
+                        ),
+                    )),
+                    "explain" => documents.push((
+                        class_name,
+                        explain::explain_class(&library, library.sym_to_addr[*vtable_symbol]),
+                    )),
+                    "layout" => {
+                        let typeinfo =
+                            library.get_vtable_typeinfo(library.sym_to_addr[*vtable_symbol]);
+                        let layout = layout::get_class_layout(
+                            &library,
+                            hierarchy.as_ref().unwrap(),
+                            typeinfo,
+                            get_size(class_name),
+                        );
+                        documents.push((class_name, layout.get_display()))
+                    }
+                    _ => {
+                        panic!("unknown action: {:?}", action)
                     }
                 }
-                _ => {
-                    panic!("unknown action: {:?}", action)
+            }
+
+            if let Some(output_dir) = output_dir {
+                let extension = match action {
+                    "inheritance" | "layout" | "explain" => "txt",
+                    "dump-vtable-json" => "json",
+                    _ => "h",
+                };
+
+                std::fs::create_dir_all(output_dir)
+                    .unwrap_or_else(|_| panic!("failed to create directory: {:?}", output_dir));
+
+                // `Foo<int*>` and `Foo<int&>` both become `Foo_int__`, the
+                // vtable address tells them apart
+                let names: Vec<String> = classes
+                    .iter()
+                    .map(|(class_name, _)| get_output_file_name(class_name))
+                    .collect();
+                for ((name, (_, vtable_symbol)), (_, document)) in
+                    names.iter().zip(&classes).zip(documents)
+                {
+                    let name = if names.iter().filter(|other| *other == name).count() > 1 {
+                        format!("{}_{:x}", name, library.sym_to_addr[*vtable_symbol])
+                    } else {
+                        name.clone()
+                    };
+                    let path = output_dir.join(format!("{}.{}", name, extension));
+                    std::fs::write(&path, document + "\n")
+                        .unwrap_or_else(|_| panic!("failed to write file: {:?}", path));
                 }
+                return;
+            }
+
+            if action == "create-vtable-cpp" {
+                println!(
+                    "NOTE: The return types of these functions are guessed from their machine code, check the confidence in the comments"
+                );
+            }
+
+            if action == "dump-vtable-json" && classes.len() != 1 {
+                // one array per class, keyed by the class name
+                println!("{}", serde_json::to_string_pretty(&json_documents).unwrap());
+            } else {
+                println!(
+                    "{}",
+                    documents
+                        .into_iter()
+                        .map(|(_, document)| document)
+                        .collect::<Vec<String>>()
+                        .join("\n\n")
+                );
             }
         }
         _ => {}
     }
 }
+
+/// The `-L` argument of the subcommands analyzing a library.
+fn library_path_arg() -> clap::Arg {
+    clap::arg!(-L --"library-path" <PATH> "The library to analyze, repeat to load the libraries it depends on after it (e.g. -L libgame.so -L libcocos2dcpp.so). Libraries inside an APK are selected like app.apk!/lib/armeabi-v7a/libcocos2dcpp.so")
+        .value_parser(clap::value_parser!(std::path::PathBuf))
+        .action(clap::ArgAction::Append)
+        .required(true)
+}
+
+/// Loads the `-L` libraries, the first one from a memory dump with
+/// `--load-base`.
+fn load_library(matches: &clap::ArgMatches) -> Library {
+    let paths: Vec<std::path::PathBuf> = matches
+        .get_many::<std::path::PathBuf>("library-path")
+        .unwrap()
+        .cloned()
+        .collect();
+
+    match matches.get_one::<String>("load-base") {
+        Some(base) => Library::load_memory_dump(
+            &paths,
+            parse_address(base),
+            matches
+                .get_one::<std::path::PathBuf>("symbols-from")
+                .map(|path| path.as_path()),
+        ),
+        None => Library::load(&paths),
+    }
+}
+
+fn select_classes<'a>(
+    library: &'a Library,
+    selectors: &[String],
+    all: bool,
+    regex: bool,
+) -> Vec<(&'a String, &'a String)> {
+    if all {
+        return library.class_index.get_classes();
+    }
+
+    let mut classes: Vec<(&String, &String)> = Vec::new();
+
+    for selector in selectors {
+        let matched = if regex {
+            let pattern =
+                regex::Regex::new(&format!("^(?:{})$", selector)).unwrap_or_else(|error| {
+                    eprintln!("invalid regular expression {:?}: {}", selector, error);
+                    std::process::exit(1);
+                });
+            library
+                .class_index
+                .find_classes(|name| pattern.is_match(name))
+        } else if let Some(class) = library.class_index.resolve(selector, &library.addr_to_sym) {
+            // `Foo<char*>` names a class, it is only a pattern when no class
+            // has that name
+            vec![class]
+        } else if class_index::is_glob(selector) {
+            library
+                .class_index
+                .find_classes(|name| class_index::glob_match(selector, name))
+        } else {
+            eprintln!(
+                "{}",
+                library.class_index.get_unknown_class_message(selector)
+            );
+            std::process::exit(1);
+        };
+
+        if matched.is_empty() {
+            eprintln!("no class matches {:?}", selector);
+        }
+
+        for class in matched {
+            if !classes.contains(&class) {
+                classes.push(class);
+            }
+        }
+    }
+
+    classes
+}
+
+/// `0x84` or `132`
+fn parse_number(text: &str) -> u32 {
+    let parsed = match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+
+    parsed.unwrap_or_else(|_| {
+        eprintln!("expected a number (e.g. 0x84 or 132), got {:?}", text);
+        std::process::exit(1);
+    })
+}
+
+/// A 64-bit address, `0x7a3c200000`
+fn parse_address(text: &str) -> u64 {
+    let hex = text.strip_prefix("0x").unwrap_or(text);
+    u64::from_str_radix(hex, 16).unwrap_or_else(|_| {
+        eprintln!(
+            "expected a hexadecimal address (e.g. 0x7a3c200000), got {:?}",
+            text
+        );
+        std::process::exit(1);
+    })
+}
+
+/// `d8 15 00 40`, `d8150040` or `0xd8, 0x15, 0x00, 0x40`, `-` reads them from
+/// stdin
+fn parse_hex_bytes(text: &str) -> Vec<u8> {
+    let text = match text {
+        "-" => std::io::read_to_string(std::io::stdin()).unwrap_or_else(|error| {
+            eprintln!("failed to read stdin: {}", error);
+            std::process::exit(1);
+        }),
+        _ => text.to_string(),
+    };
+    let digits: String = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .map(|token| token.strip_prefix("0x").unwrap_or(token))
+        .collect();
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|index| {
+            digits
+                .get(index..index + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>()
+        .unwrap_or_else(|| {
+            eprintln!(
+                "expected hexadecimal bytes (e.g. \"d8 15 00 40\"), got {:?}",
+                text.trim()
+            );
+            std::process::exit(1);
+        })
+}
+
+// `cocos2d::CCLayer` -> `cocos2d__CCLayer`
+fn get_output_file_name(class_name: &str) -> String {
+    class_name
+        .replace("::", "__")
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
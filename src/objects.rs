@@ -0,0 +1,286 @@
+use crate::core::CoreDump;
+use crate::fields::ClassFields;
+use crate::hierarchy::Hierarchy;
+use crate::layout::{self, LayoutEntryKind};
+use crate::library::{Library, Module};
+use crate::rtti::Class;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// The vtable group whose address point a vptr holds.
+struct AddressPoint {
+    typeinfo: u32,
+    vtable_address: u32,
+    group: usize,
+    offset_to_top: i32,
+}
+
+/// Where the modules of a library were loaded in a process, to map the
+/// pointers read from its memory back to library addresses.
+pub struct LoadAddresses {
+    // (load address, base in the library, size of its range in the library)
+    modules: Vec<(u64, u32, u32)>,
+}
+
+impl LoadAddresses {
+    /// `get_load_address` gives the address a module was loaded at, modules
+    /// without one are left out.
+    pub fn new(library: &Library, get_load_address: impl Fn(&Module) -> Option<u64>) -> Self {
+        let ends = library
+            .modules
+            .iter()
+            .skip(1)
+            .map(|module| module.base)
+            .chain(std::iter::once(u32::MAX));
+
+        Self {
+            modules: library
+                .modules
+                .iter()
+                .zip(ends)
+                .filter_map(|(module, end)| {
+                    get_load_address(module).map(|load| (load, module.base, end - module.base))
+                })
+                .collect(),
+        }
+    }
+
+    fn get_library_address(&self, addr: u64) -> Option<u32> {
+        self.modules.iter().find_map(|(load, base, size)| {
+            let offset = addr.checked_sub(*load)?;
+            (offset < *size as u64).then(|| base + offset as u32)
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct ObjectClass {
+    /// the address holding the vptr
+    pub address: u64,
+    pub vptr: u64,
+    pub class_name: String,
+    pub vtable_address: u32,
+    /// the vtable group the vptr points into, 0 for the primary one
+    pub group: usize,
+    pub offset_to_top: i32,
+    /// the start of the most derived object
+    pub object_address: u64,
+    /// the base class whose vptr `address` holds, for a secondary vtable group
+    pub subobject: Option<String>,
+    pub inheritance: Class,
+    /// the vptrs and known data members of the object, sorted by offset
+    pub fields: Vec<ObjectField>,
+}
+
+#[derive(Serialize)]
+pub struct ObjectField {
+    /// offset from the start of the object
+    pub offset: u32,
+    pub size: u8,
+    pub type_name: String,
+    pub name: String,
+    /// `None` when the field is outside of the memory that was read
+    pub value: Option<u64>,
+}
+
+/// Identifies the objects with a vptr in the `size` bytes of `memory` at
+/// `addr`: every pointer sized word holding the address point of a vtable
+/// group is a vptr. An object whose secondary vptrs are also in the range is
+/// only reported once, by its first vptr.
+pub fn find_objects(
+    library: &Library,
+    hierarchy: &Hierarchy,
+    fields: &ClassFields,
+    load_addresses: &LoadAddresses,
+    memory: &CoreDump,
+    addr: u64,
+    size: u64,
+) -> Vec<ObjectClass> {
+    let address_points = get_address_points(library);
+    let mut objects: Vec<ObjectClass> = Vec::new();
+
+    for (address, vptr) in memory.read_words(addr, size) {
+        let Some(address_point) = load_addresses
+            .get_library_address(vptr)
+            .and_then(|addr| address_points.get(&addr))
+        else {
+            continue;
+        };
+
+        let object_address = address.wrapping_add_signed(address_point.offset_to_top as i64);
+        if objects.iter().any(|object| {
+            object.object_address == object_address
+                && object.vtable_address == address_point.vtable_address
+        }) {
+            continue;
+        }
+
+        let subobject = (address_point.offset_to_top != 0)
+            .then(|| {
+                layout::get_class_layout(library, hierarchy, address_point.typeinfo, None)
+                    .entries
+                    .into_iter()
+                    .find(|entry| {
+                        entry.kind == LayoutEntryKind::VtablePointer
+                            && entry.offset == Some(-address_point.offset_to_top)
+                    })
+                    .map(|entry| entry.class_name)
+            })
+            .flatten();
+
+        objects.push(ObjectClass {
+            address,
+            vptr,
+            class_name: hierarchy.get_class_name(address_point.typeinfo),
+            vtable_address: address_point.vtable_address,
+            group: address_point.group,
+            offset_to_top: address_point.offset_to_top,
+            object_address,
+            subobject,
+            inheritance: library.get_typeinfo_inheritance(address_point.typeinfo),
+            fields: get_object_fields(library, fields, memory, address_point, object_address),
+        });
+    }
+
+    objects
+}
+
+/// The vptrs of every vtable group and the data members found by
+/// `ClassFields`, with their value in `memory`.
+fn get_object_fields(
+    library: &Library,
+    fields: &ClassFields,
+    memory: &CoreDump,
+    address_point: &AddressPoint,
+    object_address: u64,
+) -> Vec<ObjectField> {
+    let mut object_fields: Vec<ObjectField> = library
+        .get_class_vtable(address_point.vtable_address)
+        .into_iter()
+        .map(|(offset_to_top, _)| ObjectField {
+            offset: -offset_to_top as u32,
+            size: library.pointer_size as u8,
+            type_name: "vptr".to_string(),
+            name: format!("__vtable_{}", -offset_to_top),
+            value: None,
+        })
+        .collect();
+
+    // vptrs come first when a field overlaps one
+    object_fields.extend(
+        fields
+            .get_object_fields(library, address_point.typeinfo)
+            .into_iter()
+            .map(|field| ObjectField {
+                offset: field.offset,
+                size: field.size,
+                type_name: field.type_name,
+                name: field.name,
+                value: None,
+            }),
+    );
+    object_fields.sort_by_key(|field| field.offset);
+
+    for field in &mut object_fields {
+        field.value = memory
+            .read(
+                object_address.wrapping_add(field.offset as u64),
+                field.size as u64,
+            )
+            .map(|bytes| {
+                let mut value = [0u8; 8];
+                value[..bytes.len()].copy_from_slice(bytes);
+                u64::from_le_bytes(value)
+            });
+    }
+
+    object_fields
+}
+
+/// Address point -> vtable group, for every group of every vtable.
+fn get_address_points(library: &Library) -> HashMap<u32, AddressPoint> {
+    let mut address_points = HashMap::new();
+
+    for (vtable_address, _) in library.get_vtables() {
+        let typeinfo = library.get_vtable_typeinfo(vtable_address);
+        for (group, (group_address, offset_to_top, _)) in library
+            .get_vtable_groups(vtable_address)
+            .into_iter()
+            .enumerate()
+        {
+            address_points.insert(
+                group_address + 2 * library.pointer_size,
+                AddressPoint {
+                    typeinfo,
+                    vtable_address,
+                    group,
+                    offset_to_top,
+                },
+            );
+        }
+    }
+
+    address_points
+}
+
+impl ObjectClass {
+    pub fn get_display(&self) -> String {
+        let mut lines = vec![format!("0x{:x}  {}", self.address, self.class_name)];
+
+        lines.push(format!(
+            "    vptr 0x{:x} -> vtable 0x{:x}, group {} (offset-to-top {})",
+            self.vptr, self.vtable_address, self.group, self.offset_to_top
+        ));
+        if let Some(subobject) = &self.subobject {
+            lines.push(format!(
+                "    the {} subobject of the {} at 0x{:x}",
+                subobject, self.class_name, self.object_address
+            ));
+        } else if self.object_address != self.address {
+            lines.push(format!(
+                "    a subobject of the {} at 0x{:x}",
+                self.class_name, self.object_address
+            ));
+        }
+
+        lines.extend(
+            self.inheritance
+                .get_display()
+                .lines()
+                .map(|line| format!("    {}", line)),
+        );
+
+        if !self.fields.is_empty() {
+            lines.push("    fields:".to_string());
+        }
+        for field in &self.fields {
+            let value = match field.value {
+                Some(value) if field.type_name == "float" => {
+                    format!("0x{:x} ({})", value, f32::from_bits(value as u32))
+                }
+                Some(value) => format!("0x{:x}", value),
+                None => "?".to_string(),
+            };
+            lines.push(format!(
+                "    {:>10}  {} {} = {}",
+                format!("0x{:x}", field.offset),
+                field.type_name,
+                field.name,
+                value
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+pub fn get_display(objects: &[ObjectClass]) -> String {
+    if objects.is_empty() {
+        return "no object with a known vptr".to_string();
+    }
+
+    objects
+        .iter()
+        .map(ObjectClass::get_display)
+        .collect::<Vec<String>>()
+        .join("\n\n")
+}
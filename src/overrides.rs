@@ -0,0 +1,190 @@
+use crate::class_index::{demangle, get_thunk_target, normalize_class_name, split_scopes};
+use crate::hierarchy::Hierarchy;
+use crate::library::Library;
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct Implementation {
+    pub class_name: String,
+    /// offset-to-top of the vtable group holding the slot
+    pub offset_to_top: i32,
+    pub slot: usize,
+    /// byte offset of the slot from the vptr
+    pub vptr_offset: u32,
+    pub address: u32,
+    pub symbol: String,
+    /// whether the slot points somewhere else than the declaring class's
+    /// implementation
+    pub overrides: bool,
+}
+
+#[derive(Serialize)]
+pub struct OverrideReport {
+    pub method: String,
+    pub declaring_class: String,
+    pub implementations: Vec<Implementation>,
+}
+
+/// `cocos2d::CCLayer::ccTouchBegan(cocos2d::CCTouch*, cocos2d::CCEvent*)` ->
+/// (`cocos2d::CCLayer`, `ccTouchBegan(cocos2d::CCTouch*, cocos2d::CCEvent*)`)
+pub fn split_method(method: &str) -> Option<(String, String)> {
+    let mut scopes = split_scopes(method);
+    let signature = scopes.pop()?;
+
+    if scopes.is_empty() || !signature.contains('(') {
+        return None;
+    }
+
+    Some((scopes.join("::"), signature.to_string()))
+}
+
+/// The unqualified signature of the function (through thunks) a slot points at.
+fn get_slot_signature(library: &Library, addr: u32) -> Option<String> {
+    let symbol = library.get_symbol_name(addr);
+    let target = get_thunk_target(&symbol).unwrap_or(symbol);
+    let demangled = demangle(&target)?;
+    split_method(&demangled).map(|(_, signature)| normalize_class_name(&signature))
+}
+
+fn get_implementation_symbol(library: &Library, addr: u32) -> String {
+    let symbol = library.get_symbol_name(addr);
+    get_thunk_target(&symbol).unwrap_or(symbol)
+}
+
+pub fn find_overrides(
+    library: &Library,
+    hierarchy: &Hierarchy,
+    method: &str,
+) -> Result<OverrideReport, String> {
+    let (class_selector, signature) = split_method(method).ok_or(format!(
+        "expected a qualified method with its arguments (e.g. cocos2d::CCNode::setTag(int)), got {:?}",
+        method
+    ))?;
+    let method = format!("{}::{}", class_selector, signature);
+    let signature = normalize_class_name(&signature);
+
+    let declaring_typeinfo = hierarchy
+        .find_typeinfo(library, &class_selector)
+        .ok_or_else(|| {
+            library
+                .class_index
+                .get_unknown_class_message(&class_selector)
+        })?;
+    let declaring_class = hierarchy.get_class_name(declaring_typeinfo);
+    let declaring_vtable = hierarchy
+        .get_vtable(declaring_typeinfo)
+        .ok_or(format!("{} has no vtable in this library", declaring_class))?;
+    let declaring_groups = library.get_class_vtable(declaring_vtable);
+
+    // (offset-to-top, slot index, implementation) of every slot of the
+    // declaring class that points at the method
+    let slots: Vec<(i32, usize, String)> = declaring_groups
+        .iter()
+        .flat_map(|(offset_to_top, functions)| {
+            functions
+                .iter()
+                .enumerate()
+                .filter(|(_, addr)| {
+                    get_slot_signature(library, **addr).as_ref() == Some(&signature)
+                })
+                .map(|(slot, addr)| {
+                    (
+                        *offset_to_top,
+                        slot,
+                        get_implementation_symbol(library, *addr),
+                    )
+                })
+        })
+        .collect();
+
+    if slots.is_empty() {
+        return Err(format!(
+            "{} has no virtual function matching {:?}",
+            declaring_class, signature
+        ));
+    }
+
+    let mut implementations = Vec::new();
+    let classes = std::iter::once(declaring_typeinfo).chain(
+        hierarchy
+            .get_derived_flat(declaring_typeinfo, None)
+            .into_iter()
+            .map(|(_, derived)| derived),
+    );
+
+    for typeinfo in classes {
+        let Some(vtable) = hierarchy.get_vtable(typeinfo) else {
+            continue;
+        };
+        // the declaring class's vtable groups are shifted by the offset of
+        // its subobject inside the derived class
+        let Some(base_offset) = hierarchy.get_base_offset(typeinfo, declaring_typeinfo) else {
+            eprintln!(
+                "skipping {}: {} is a virtual base of it",
+                hierarchy.get_class_name(typeinfo),
+                declaring_class
+            );
+            continue;
+        };
+        let groups = library.get_class_vtable(vtable);
+
+        for (offset_to_top, slot, base_implementation) in &slots {
+            let offset_to_top = offset_to_top - base_offset;
+            let Some(addr) = groups
+                .iter()
+                .find(|(group_offset, _)| *group_offset == offset_to_top)
+                .and_then(|(_, functions)| functions.get(*slot))
+            else {
+                continue;
+            };
+
+            implementations.push(Implementation {
+                class_name: hierarchy.get_class_name(typeinfo),
+                offset_to_top,
+                slot: *slot,
+                vptr_offset: library.pointer_size * *slot as u32,
+                address: *addr,
+                symbol: library.get_symbol_name(*addr),
+                overrides: get_implementation_symbol(library, *addr) != *base_implementation,
+            });
+        }
+    }
+
+    Ok(OverrideReport {
+        method,
+        declaring_class,
+        implementations,
+    })
+}
+
+impl OverrideReport {
+    pub fn get_display(&self, show_inherited: bool) -> String {
+        let mut lines = vec![self.method.clone()];
+
+        for implementation in &self.implementations {
+            if !show_inherited
+                && !implementation.overrides
+                && implementation.class_name != self.declaring_class
+            {
+                continue;
+            }
+
+            lines.push(format!(
+                "    {}  [offset-to-top {}, slot {} (vptr+0x{:x})]  0x{:x}  {}{}",
+                implementation.class_name,
+                implementation.offset_to_top,
+                implementation.slot,
+                implementation.vptr_offset,
+                implementation.address,
+                demangle(&implementation.symbol).unwrap_or(implementation.symbol.clone()),
+                if implementation.overrides || implementation.class_name == self.declaring_class {
+                    ""
+                } else {
+                    "  (inherited)"
+                }
+            ));
+        }
+
+        lines.join("\n")
+    }
+}
@@ -0,0 +1,394 @@
+use crate::class_index::{demangle, get_thunk_target};
+use crate::disasm::{self, InstructionSet, Operation, RegisterState};
+use crate::library::Library;
+use crate::overrides::split_method;
+use crate::trivial::{self, Implementation};
+use serde::Serialize;
+use std::collections::HashSet;
+
+const MAX_FUNCTION_SIZE: u32 = 0x400;
+// how many tail calls and returned call results are followed
+const MAX_DEPTH: usize = 2;
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Serialize, Clone)]
+pub struct ReturnType {
+    pub type_name: String,
+    pub confidence: Confidence,
+}
+
+impl ReturnType {
+    fn new(type_name: &str, confidence: Confidence) -> Self {
+        Self {
+            type_name: type_name.to_string(),
+            confidence,
+        }
+    }
+
+    /// The type of a value returned through another function is a step less
+    /// certain.
+    fn forwarded(self) -> Self {
+        Self {
+            confidence: match self.confidence {
+                Confidence::High => Confidence::Medium,
+                _ => Confidence::Low,
+            },
+            ..self
+        }
+    }
+}
+
+impl std::fmt::Display for Confidence {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Confidence::Low => "low",
+            Confidence::Medium => "medium",
+            Confidence::High => "high",
+        })
+    }
+}
+
+/// What the return register holds at a return.
+#[derive(Clone, Copy, PartialEq)]
+enum Value {
+    /// not written since the entry, `this` for member functions
+    Untouched,
+    Constant(u64),
+    Load(u8),
+    Address,
+    /// the result of the call to this function
+    Call(u32),
+    Float,
+    Double,
+    Unknown,
+}
+
+/// Guesses the return type of functions from the way they leave a value in
+/// r0/x0 (or s0/d0/v0) before returning.
+pub struct ReturnTypes {
+    // functions with a covariant return thunk, they return a class pointer
+    covariant_targets: HashSet<u32>,
+}
+
+impl ReturnTypes {
+    pub fn new(library: &Library) -> Self {
+        let covariant_targets = library
+            .addr_to_sym
+            .values()
+            .filter(|symbol| symbol.starts_with("_ZTc"))
+            .filter_map(|symbol| get_thunk_target(symbol))
+            .filter_map(|target| library.sym_to_addr.get(&target))
+            .filter(|addr| **addr != 0)
+            .map(|addr| addr & !1)
+            .collect();
+
+        Self { covariant_targets }
+    }
+
+    pub fn get(&self, library: &Library, addr: u32) -> ReturnType {
+        self.infer(library, addr, 0)
+    }
+
+    fn infer(&self, library: &Library, addr: u32, depth: usize) -> ReturnType {
+        let symbol = library.get_symbol_name(addr);
+
+        // a covariant thunk adjusts the returned pointer, so the function
+        // returns a class pointer. The mangled name does not say which class,
+        // its own class (as for `clone()`) is only the usual case
+        if symbol.starts_with("_ZTc") || self.covariant_targets.contains(&(addr & !1)) {
+            let method = get_thunk_target(&symbol).unwrap_or(symbol.clone());
+            if let Some((class_name, _)) = demangle(&method).as_deref().and_then(split_method) {
+                return ReturnType::new(&format!("{}*", class_name), Confidence::Medium);
+            }
+        }
+
+        // `this` adjusting thunks return what their target returns
+        if depth < MAX_DEPTH
+            && let Some(target) = get_thunk_target(&symbol)
+            && let Some(target_addr) = library.sym_to_addr.get(&target).filter(|addr| **addr != 0)
+        {
+            return self.infer(library, *target_addr, depth + 1);
+        }
+
+        match trivial::classify_implementation(library, addr) {
+            // r0 still holds `this`, which could also be returned on purpose
+            Implementation::Empty => ReturnType::new("void", Confidence::Medium),
+            Implementation::ConstantReturn { value } => {
+                self.merge(library, &[(Value::Constant(value), Value::Unknown)], depth)
+            }
+            Implementation::FieldGetter { size, .. } => {
+                self.merge(library, &[(Value::Load(size), Value::Unknown)], depth)
+            }
+            Implementation::TailCall { target, .. } => {
+                self.merge(library, &[(Value::Call(target), Value::Unknown)], depth)
+            }
+            Implementation::NonTrivial => {
+                let returns = get_returned_values(library, addr);
+                self.merge(library, &returns, depth)
+            }
+        }
+    }
+
+    /// Combines the (r0, r1) values seen at every return.
+    fn merge(&self, library: &Library, returns: &[(Value, Value)], depth: usize) -> ReturnType {
+        let is_32_bit = library.pointer_size == 4;
+        let constants: HashSet<u64> = returns
+            .iter()
+            .filter_map(|(value, _)| match value {
+                Value::Constant(value) => Some(*value),
+                _ => None,
+            })
+            .collect();
+
+        let candidates: Vec<ReturnType> = returns
+            .iter()
+            .filter_map(|(value, high)| match value {
+                Value::Untouched => Some(ReturnType::new("void", Confidence::Medium)),
+                // softfp and 64-bit integers use r0 and r1
+                Value::Constant(_) if is_32_bit && matches!(high, Value::Constant(_)) => {
+                    Some(ReturnType::new("long long", Confidence::Medium))
+                }
+                Value::Constant(value) if *value <= 1 => {
+                    let confidence = if constants.contains(&0) && constants.contains(&1) {
+                        Confidence::High
+                    } else {
+                        Confidence::Medium
+                    };
+                    Some(ReturnType::new("bool", confidence))
+                }
+                Value::Constant(value) if *value > 0xffffffff => {
+                    Some(ReturnType::new("long long", Confidence::Medium))
+                }
+                Value::Constant(_) => Some(ReturnType::new("int", Confidence::Medium)),
+                Value::Load(size) => Some(ReturnType::new(
+                    match size {
+                        1 => "bool",
+                        2 => "short",
+                        8 => "void*",
+                        _ => "int",
+                    },
+                    Confidence::Low,
+                )),
+                Value::Address => Some(ReturnType::new("void*", Confidence::Medium)),
+                // a call through a relocation that was not resolved lands
+                // outside of the code
+                Value::Call(target) => (depth < MAX_DEPTH
+                    && library.is_executable_address(*target))
+                .then(|| self.infer(library, *target, depth + 1).forwarded()),
+                Value::Float => Some(ReturnType::new("float", Confidence::Medium)),
+                Value::Double => Some(ReturnType::new("double", Confidence::Medium)),
+                Value::Unknown => None,
+            })
+            .collect();
+
+        let Some(first) = candidates.first() else {
+            // every return leaves a computed value behind
+            return ReturnType::new("int", Confidence::Low);
+        };
+
+        if candidates
+            .iter()
+            .all(|candidate| candidate.type_name == first.type_name)
+        {
+            let confidence = candidates
+                .iter()
+                .map(|candidate| candidate.confidence)
+                .min()
+                .unwrap_or(Confidence::Low);
+            return ReturnType::new(&first.type_name, confidence);
+        }
+
+        // returns disagree: a floating point result is the strongest hint,
+        // then any value over leaving r0 alone
+        let fallback = candidates
+            .iter()
+            .find(|candidate| candidate.type_name == "float" || candidate.type_name == "double")
+            .or_else(|| {
+                candidates
+                    .iter()
+                    .find(|candidate| candidate.type_name != "void")
+            })
+            .unwrap_or(first);
+        ReturnType::new(&fallback.type_name, Confidence::Low)
+    }
+}
+
+/// The (r0, r1) values at every return of the function, walking it linearly.
+fn get_returned_values(library: &Library, addr: u32) -> Vec<(Value, Value)> {
+    if !library.is_executable_address(addr) {
+        return Vec::new();
+    }
+
+    let set = InstructionSet::of(library, addr);
+    let mut state = RegisterState::new(set);
+    let mut values = [Value::Untouched, Value::Untouched];
+    let mut returns = Vec::new();
+
+    for instruction in disasm::decode_function(library, addr, MAX_FUNCTION_SIZE) {
+        state.step(library, &instruction);
+
+        if let Some(value) = get_float_write(library, &instruction, set) {
+            values = [value, Value::Unknown];
+            continue;
+        }
+
+        match instruction.operation {
+            Operation::Return => {
+                returns.push((values[0], values[1]));
+                // the code after a return is reached by a branch, with
+                // whatever the registers held there
+                if !instruction.conditional {
+                    values = [Value::Unknown, Value::Unknown];
+                }
+            }
+            Operation::Branch {
+                target,
+                link: false,
+            } if !instruction.conditional => {
+                returns.push((Value::Call(target), Value::Unknown));
+                values = [Value::Unknown, Value::Unknown];
+            }
+            Operation::BranchRegister { link: false, .. } if !instruction.conditional => {
+                returns.push((Value::Unknown, Value::Unknown));
+                values = [Value::Unknown, Value::Unknown];
+            }
+            Operation::Branch { target, link: true } => {
+                values = [Value::Call(target), Value::Unknown];
+            }
+            Operation::BranchRegister { link: true, .. } => {
+                values = [Value::Unknown, Value::Unknown];
+            }
+            _ => {
+                if let Some(register @ (0 | 1)) =
+                    disasm::get_written_register(library, &instruction, set)
+                {
+                    values[register as usize] = match instruction.operation {
+                        Operation::Load { size, .. } => Value::Load(size),
+                        Operation::Address { .. } => Value::Address,
+                        // conditional moves of constants are the usual way
+                        // of returning a bool
+                        Operation::MoveImmediate { imm, .. } => Value::Constant(imm),
+                        _ => state.get(register).map_or(Value::Unknown, Value::Constant),
+                    };
+                }
+            }
+        }
+    }
+
+    returns
+}
+
+/// Whether the instruction leaves a floating point result in the return
+/// register: v0 on AArch64, r0 (`vmov r0, s0` / `vmov r0, r1, d0`) for the
+/// softfp ABI of ARM.
+fn get_float_write(
+    library: &Library,
+    instruction: &disasm::Instruction,
+    set: InstructionSet,
+) -> Option<Value> {
+    let word = disasm::get_encoding(library, instruction, set)?;
+
+    match set {
+        InstructionSet::Arm64 => {
+            let is_double = (word >> 22) & 3 == 1;
+            if word & 0x1f != 0 {
+                return None;
+            }
+            if word & 0xff200000 == 0x1e200000 {
+                // conversions to a general purpose register (fcvtzs, fmov
+                // w0, s0) share the scalar floating point encodings
+                let is_conversion = (word >> 10) & 0x3f == 0;
+                if is_conversion && !matches!((word >> 16) & 7, 2 | 3 | 7) {
+                    return None;
+                }
+                return Some(if is_double {
+                    Value::Double
+                } else {
+                    Value::Float
+                });
+            }
+            // ldr s0 / ldr d0, unsigned and unscaled offsets
+            if word & 0x3f400000 == 0x3d400000 || word & 0x3f600c00 == 0x3c400000 {
+                return match word >> 30 {
+                    2 => Some(Value::Float),
+                    3 => Some(Value::Double),
+                    _ => None,
+                };
+            }
+            // movi d0, #0
+            (word == 0x2f00e400).then_some(Value::Double)
+        }
+        InstructionSet::Arm | InstructionSet::Thumb if instruction.size == 4 => {
+            let rt = (word >> 12) & 0xf;
+            if word & 0x0ff00f7f == 0x0e100a10 && rt == 0 {
+                Some(Value::Float)
+            } else if word & 0x0ff00fd0 == 0x0c500b10 && rt == 0 {
+                Some(Value::Double)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_elf::TestElf;
+
+    const RET: u32 = 0xd65f03c0;
+
+    fn describe(return_type: ReturnType) -> String {
+        format!("{} ({})", return_type.type_name, return_type.confidence)
+    }
+
+    #[test]
+    fn arm64_return_types() {
+        let mut elf = TestElf::arm64();
+        // cbz w1, 1f; mov w0, #1; ret; 1: mov w0, wzr; ret
+        let bool_return = elf.add_code(&[0x34000061, 0x52800020, RET, 0x2a1f03e0, RET]);
+        // mov w0, #1
+        let true_return = elf.add_code(&[0x52800020, RET]);
+        // fmov s0, #1.0
+        let float_return = elf.add_code(&[0x1e2e1000, RET]);
+        // bl bool_return; ret
+        let addr = elf.next_address();
+        let forwarded = elf.add_code(&[
+            0x94000000 | (bool_return.wrapping_sub(addr) >> 2) & 0x3ffffff,
+            RET,
+        ]);
+        // ldr x0, [x0, #0x18]
+        let clone = elf.add_code(&[0xf9400c00, RET]);
+        elf.add_symbol("_ZN3Foo5cloneEv", clone, 8);
+        let thunk = elf.add_code(&[RET]);
+        elf.add_symbol("_ZTch0_h16_N3Foo5cloneEv", thunk, 4);
+        let library = elf.load();
+        let return_types = ReturnTypes::new(&library);
+
+        assert_eq!(
+            describe(return_types.get(&library, bool_return)),
+            "bool (high)"
+        );
+        assert_eq!(
+            describe(return_types.get(&library, true_return)),
+            "bool (medium)"
+        );
+        assert_eq!(
+            describe(return_types.get(&library, float_return)),
+            "float (medium)"
+        );
+        assert_eq!(
+            describe(return_types.get(&library, forwarded)),
+            "bool (medium)"
+        );
+        assert_eq!(describe(return_types.get(&library, clone)), "Foo* (medium)");
+    }
+}
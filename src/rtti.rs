@@ -0,0 +1,385 @@
+use crate::binreader::BinReader;
+use crate::class_index::demangle;
+use crate::library::get_file_offset_for_address_under_section;
+use serde::Serialize;
+
+/// Which of the cxxabi typeinfo classes describes a class.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum TypeInfoKind {
+    /// `__class_type_info`: no bases
+    #[serde(rename = "class")]
+    Class,
+    /// `__si_class_type_info`: a single, public, non-virtual base at offset 0
+    #[serde(rename = "si_class")]
+    SingleInheritance,
+    /// `__vmi_class_type_info`: anything else
+    #[serde(rename = "vmi_class")]
+    VirtualMultipleInheritance,
+    #[serde(rename = "unknown")]
+    Unknown,
+}
+
+impl TypeInfoKind {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            TypeInfoKind::Class => "class",
+            TypeInfoKind::SingleInheritance => "si_class",
+            TypeInfoKind::VirtualMultipleInheritance => "vmi_class",
+            TypeInfoKind::Unknown => "unknown",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BaseClassInfo {
+    pub typeinfo: u32,
+    /// offset of the base subobject, or for virtual bases the offset of the
+    /// vbase offset in the vtable
+    pub offset: i32,
+    pub is_virtual: bool,
+    pub is_public: bool,
+}
+
+impl BaseClassInfo {
+    const VIRTUAL_MASK: i64 = 0x1;
+    const PUBLIC_MASK: i64 = 0x2;
+    const OFFSET_SHIFT: i64 = 8;
+
+    /// Decodes a `__base_class_type_info` of a vmi typeinfo, `offset_flags`
+    /// is a `long` (sign extended on 32-bit targets).
+    pub fn new(typeinfo: u32, offset_flags: i64) -> Self {
+        Self {
+            typeinfo,
+            offset: (offset_flags >> Self::OFFSET_SHIFT) as i32,
+            is_virtual: offset_flags & Self::VIRTUAL_MASK != 0,
+            is_public: offset_flags & Self::PUBLIC_MASK != 0,
+        }
+    }
+}
+
+/// A typeinfo parsed according to its kind, with the bases left as addresses.
+#[derive(Debug, Clone, Serialize)]
+pub struct TypeInfo {
+    pub address: u32,
+    /// the mangled type name (e.g. N7cocos2d6CCNodeE)
+    pub name: String,
+    pub kind: TypeInfoKind,
+    pub bases: Vec<BaseClassInfo>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Class {
+    pub name: String,
+    pub base: Vec<Class>,
+}
+
+impl Class {
+    pub fn push_base(&mut self) -> &mut Class {
+        self.base.push(Class::default());
+        self.base.last_mut().unwrap()
+    }
+
+    pub fn get_display(&self) -> String {
+        let mut lines: Vec<String> = Vec::new();
+        self._get_display(&mut lines, 0);
+        lines.join("\n")
+    }
+
+    fn _get_display(&self, buf: &mut Vec<String>, mut level: u32) {
+        let demangled = demangle(&self.name).unwrap_or(self.name.clone());
+        let line = format!(
+            "{}{}",
+            std::iter::repeat_n(" ", 4 * level as usize).collect::<String>(),
+            demangled
+        );
+        buf.push(line);
+
+        level += 1;
+
+        for base in &self.base {
+            base._get_display(buf, level);
+        }
+    }
+}
+
+pub fn handle_typename(
+    reader: &mut BinReader,
+    output: &mut Class,
+    offset: u32,
+    start_data_rel_ro: u32,
+    rtti_class_offsets: &[u32],
+) {
+    reader.set_position(offset + 4);
+
+    let reader_data = reader.get_data();
+    let name = reader
+        .read_cstr(Some(|address: u32| {
+            get_file_offset_for_address_under_section(reader_data, ".rodata", address.into()) as u32
+        }))
+        .expect("failed to read type name")
+        .to_string();
+    let second_field = reader
+        .read_u32()
+        .expect("failed to read dword after type name");
+
+    output.name = name;
+
+    if rtti_class_offsets
+        .iter()
+        .find(|offset| **offset == second_field)
+        .is_some()
+    {
+        // some typeinfos will only provide a reference to the type class
+        // and the type name (see _ZTIN7cocos2d15CCTouchDelegateE).
+        // we cannot just check if second_field is above the start address
+        // of .data.rel.ro because another typeinfo is declared right after.
+        return;
+    }
+
+    if second_field > start_data_rel_ro {
+        // ; reference to rtti's type class
+        // ; type name
+        // ; parent typename < second_field
+        // reference: _ZTIN7cocos2d10CCMenuItemE (1.3)
+        handle_typename(
+            reader,
+            output.push_base(),
+            get_file_offset_for_address_under_section(
+                reader.get_data(),
+                ".data.rel.ro",
+                second_field.into(),
+            )
+            .try_into()
+            .unwrap(),
+            start_data_rel_ro,
+            rtti_class_offsets,
+        );
+    } else {
+        let third_field = reader
+            .read_u32()
+            .expect("failed to read dword after second field");
+
+        if third_field > start_data_rel_ro {
+            // ; reference to rtti's type class
+            // ; type name
+            // -- new vtable --
+            // vtable's offset to this = 0x00000000 < second_field
+            // class's typeinfo < third_field
+            //
+            // we are already over the typeinfo we wanted to read.
+            // reference: _ZTI17TextInputDelegate (1.3)
+        } else {
+            // ; reference to rtti's type class
+            // ; type name
+            // ; attribute < second_field
+            // ; count of base classes < third_field
+            // .. [base classes]
+            //
+            // base class:
+            //     ; base class type info
+            //     ; base class attributes
+            // reference: _ZTIN7cocos2d7CCLayerE (1.3)
+
+            let _attribute = second_field;
+            let base_class_count = third_field;
+
+            for _ in 0..base_class_count {
+                let type_descriptor_address = reader
+                    .read_u32()
+                    .expect("failed to read address to base class typeinfo");
+                let type_descriptor_offset = get_file_offset_for_address_under_section(
+                    reader.get_data(),
+                    ".data.rel.ro",
+                    type_descriptor_address.into(),
+                );
+                let _base_attribute = reader
+                    .read_u32()
+                    .expect("failed to read attribute of base class");
+                let return_offset = reader.get_position();
+                handle_typename(
+                    reader,
+                    output.push_base(),
+                    type_descriptor_offset.try_into().unwrap(),
+                    start_data_rel_ro,
+                    rtti_class_offsets,
+                );
+                reader.set_position(return_offset.try_into().unwrap());
+            }
+        }
+    }
+}
+
+/// Whether the words from the reader position up to the next group of the
+/// vtable are all vcall and vbase offsets, returns the file offset of that
+/// group's offset-to-top. The reader position is left unchanged.
+fn find_next_group(
+    reader: &mut BinReader,
+    class_typeinfo: u32,
+    is_typeinfo: &dyn Fn(u32) -> bool,
+    is_function: &dyn Fn(u32, u32) -> bool,
+    pointer_size: u32,
+) -> Option<u32> {
+    let start = reader.get_position() as u32;
+    let mut position = start;
+
+    let next_group = loop {
+        reader.set_position(position);
+        let Some(word) = reader.read_word(pointer_size) else {
+            break None;
+        };
+        let Some(next_word) = reader.read_word(pointer_size) else {
+            break None;
+        };
+
+        // the offset-to-top of a secondary group is negative
+        if next_word == class_typeinfo && word as i32 <= 0 {
+            break Some(position);
+        }
+        if is_typeinfo(position) || is_function(position, word) {
+            break None;
+        }
+        position += pointer_size;
+    };
+
+    reader.set_position(start);
+    next_group
+}
+
+fn handle_vtable(
+    reader: &mut BinReader,
+    class_typeinfo: u32,
+    is_typeinfo: &dyn Fn(u32) -> bool,
+    is_function: &dyn Fn(u32, u32) -> bool,
+    pointer_size: u32,
+) -> (i32, Vec<u32>) {
+    let pointer_size_i32 = pointer_size as i32;
+    let offset_to_this = reader.read_signed_word(pointer_size).unwrap();
+    reader.set_position_relative(pointer_size_i32); // skip reference to typeinfo
+
+    let mut function_pointers = Vec::new();
+
+    loop {
+        // a secondary group is preceded by the vcall and vbase offsets of
+        // its virtual bases, they are not slots of this group. Without RTTI
+        // the typeinfo of the next group cannot be told apart from a 0.
+        if class_typeinfo != 0
+            && let Some(next_group) = find_next_group(
+                reader,
+                class_typeinfo,
+                is_typeinfo,
+                is_function,
+                pointer_size,
+            )
+        {
+            reader.set_position(next_group);
+            break;
+        }
+
+        let position = reader.get_position() as u32;
+        let Some(addr) = reader.read_word(pointer_size) else {
+            break;
+        };
+        let next_u32 = reader
+            .read_word(pointer_size)
+            .expect("failed to read ahead");
+
+        let in_typeinfo = is_typeinfo(position);
+        let in_offset_to_this = next_u32 == class_typeinfo;
+
+        // a slot importing a function from a library that is not loaded
+        // (`__cxa_pure_virtual` from libc++_shared.so) only holds its addend
+        let is_end = addr == 0 && !is_function(position, addr);
+
+        if in_typeinfo || in_offset_to_this || is_end {
+            reader.set_position_relative(-2 * pointer_size_i32);
+            break;
+        }
+        reader.set_position_relative(-pointer_size_i32);
+
+        function_pointers.push(addr);
+    }
+
+    (offset_to_this, function_pointers)
+}
+
+/// Reads the groups of the vtable whose primary group starts at the file
+/// offset `vtable_addr`, as (file offset, offset-to-top, slots).
+/// `is_typeinfo` tells whether a typeinfo starts at a file offset and
+/// `is_function` whether the word at a file offset can be a slot.
+pub fn get_class_vtable(
+    reader: &mut BinReader,
+    vtable_addr: u32,
+    is_typeinfo: &dyn Fn(u32) -> bool,
+    is_function: &dyn Fn(u32, u32) -> bool,
+    pointer_size: u32,
+) -> Vec<(u32, i32, Vec<u32>)> {
+    let mut result: Vec<(u32, i32, Vec<u32>)> = Vec::new();
+
+    reader.set_position(vtable_addr + pointer_size);
+    let class_typeinfo = reader.read_word(pointer_size).unwrap();
+    reader.set_position(vtable_addr);
+
+    let mut table_offset = vtable_addr;
+
+    loop {
+        reader.set_position(table_offset + pointer_size);
+        let Some(typeinfo_addr) = reader.read_word(pointer_size) else {
+            break;
+        };
+        reader.set_position(table_offset);
+
+        if typeinfo_addr != class_typeinfo {
+            break;
+        }
+
+        let (offset_to_top, functions) = handle_vtable(
+            reader,
+            class_typeinfo,
+            is_typeinfo,
+            is_function,
+            pointer_size,
+        );
+        result.push((table_offset, offset_to_top, functions));
+        table_offset = reader.get_position() as u32;
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_class_vtable_skips_vcall_offsets() {
+        // struct C : B, virtual V, with V at offset 8 in C
+        let typeinfo = 0x500;
+        let words: [u32; 11] = [
+            0,          // group 0: offset-to-top
+            typeinfo,   // typeinfo
+            0x101,      // C::f()
+            0x105,      // C::g()
+            0xfffffff8, // vcall offset of V::h()
+            0,          // vcall offset of V::i()
+            0xfffffff8, // group 1: offset-to-top
+            typeinfo,   // typeinfo
+            0x109,      // virtual thunk to C::h()
+            0, 0,
+        ];
+        let data: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        let mut reader = BinReader::new(&data);
+
+        let groups = get_class_vtable(
+            &mut reader,
+            0,
+            &|_| false,
+            &|_, word| (0x100..0x200).contains(&word),
+            4,
+        );
+
+        assert_eq!(
+            groups,
+            vec![(0x0, 0, vec![0x101, 0x105]), (0x18, -8, vec![0x109])]
+        );
+    }
+}
@@ -0,0 +1,291 @@
+use crate::class_index::{demangle, get_class_name_from_symbol, normalize_class_name};
+use crate::constructors::get_constructor_name;
+use crate::disasm::{self, InstructionSet, Operation, RegisterState};
+use crate::library::Library;
+use crate::overrides::split_method;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+// create() helpers are short, this is plenty to reach the operator new call
+const MAX_FUNCTION_SIZE: u32 = 0x400;
+
+#[derive(Serialize, Clone)]
+pub struct ClassSize {
+    pub size: u32,
+    /// the functions passing this size to operator new
+    pub sources: Vec<String>,
+}
+
+/// Class sizes read from the `operator new(size)` calls of the static
+/// `create()`/`node()`/`createWith*()` helpers, indexed by normalized class
+/// name. Only allocations followed by a constructor call or a vptr store of
+/// the class count, a helper may create objects of other classes.
+pub struct ClassSizes {
+    sizes: HashMap<String, ClassSize>,
+}
+
+impl ClassSizes {
+    pub fn new(library: &Library) -> Self {
+        let operator_new: HashSet<u32> = ["_Znwj", "_Znwm"]
+            .iter()
+            .flat_map(|symbol| library.get_function_addresses(symbol))
+            .collect();
+
+        // address point of the primary vtable group -> normalized class name
+        let address_points: HashMap<u32, String> = library
+            .get_vtables()
+            .into_iter()
+            .filter_map(|(vtable_address, symbol)| {
+                let class_name = symbol
+                    .as_deref()
+                    .and_then(get_class_name_from_symbol)
+                    .or_else(|| {
+                        let typeinfo = library.get_vtable_typeinfo(vtable_address);
+                        get_class_name_from_symbol(&library.get_symbol_name(typeinfo))
+                    })?;
+                let address_point =
+                    library.get_primary_group(vtable_address) + 2 * library.pointer_size;
+                Some((address_point, normalize_class_name(&class_name)))
+            })
+            .collect();
+
+        // class name -> size -> functions
+        let mut votes: HashMap<String, HashMap<u32, Vec<String>>> = HashMap::new();
+
+        if !operator_new.is_empty() {
+            for (symbol, addr) in &library.sym_to_addr {
+                if *addr == 0 || !symbol.starts_with("_ZN") {
+                    continue;
+                }
+                let Some((class_name, function)) = get_factory_class(symbol) else {
+                    continue;
+                };
+                let class_name = normalize_class_name(&class_name);
+                let Some(size) = get_allocation_size(
+                    library,
+                    *addr,
+                    &class_name,
+                    &operator_new,
+                    &address_points,
+                ) else {
+                    continue;
+                };
+
+                votes
+                    .entry(class_name)
+                    .or_default()
+                    .entry(size)
+                    .or_default()
+                    .push(function);
+            }
+        }
+
+        // helpers of a class should agree, when they don't the size most of
+        // them allocate wins
+        let sizes = votes
+            .into_iter()
+            .filter_map(|(class_name, sizes)| {
+                let (size, mut sources) = sizes
+                    .into_iter()
+                    .max_by_key(|(size, sources)| (sources.len(), *size))?;
+                sources.sort();
+                Some((class_name, ClassSize { size, sources }))
+            })
+            .collect();
+
+        Self { sizes }
+    }
+
+    pub fn get(&self, class_name: &str) -> Option<&ClassSize> {
+        self.sizes.get(&normalize_class_name(class_name))
+    }
+
+    pub fn get_size(&self, class_name: &str) -> Option<u32> {
+        self.get(class_name).map(|class_size| class_size.size)
+    }
+}
+
+/// `_ZN7cocos2d7CCLayer6createEv` -> (`cocos2d::CCLayer`, `cocos2d::CCLayer::create()`)
+fn get_factory_class(symbol: &str) -> Option<(String, String)> {
+    let demangled = demangle(symbol)?;
+    let (class_name, signature) = split_method(&demangled)?;
+    let name = &signature[..signature.find('(')?];
+
+    // member functions, which cannot be static, are the const ones
+    let is_factory = name == "create" || name == "node" || name.starts_with("createWith");
+    (is_factory && !signature.ends_with(" const")).then_some((class_name, demangled))
+}
+
+/// The constant in the first argument register when the function first
+/// calls one of `operator_new`, if the object is then constructed as a
+/// `class_name`.
+fn get_allocation_size(
+    library: &Library,
+    addr: u32,
+    class_name: &str,
+    operator_new: &HashSet<u32>,
+    address_points: &HashMap<u32, String>,
+) -> Option<u32> {
+    let set = InstructionSet::of(library, addr);
+    let mut state = RegisterState::new(set);
+    let mut size = None;
+    // the registers holding the allocated object
+    let mut allocation = [false; 32];
+
+    for instruction in disasm::decode_function(library, addr, MAX_FUNCTION_SIZE) {
+        let Some(size) = size else {
+            if let Operation::Branch { target, .. } = instruction.operation
+                && operator_new.contains(&(target & !1))
+            {
+                size = Some(
+                    state
+                        .get(0)
+                        .map(|size| size as u32)
+                        .filter(|size| *size > 0)?,
+                );
+                allocation[0] = true;
+                state.step(library, &instruction);
+                continue;
+            }
+            state.step(library, &instruction);
+            continue;
+        };
+
+        match instruction.operation {
+            // an inlined constructor stores the vptr of the class
+            Operation::Store {
+                rt, rn, offset: 0, ..
+            } if allocation[rn as usize] => {
+                if let Some(stored_class) = state
+                    .get(rt)
+                    .and_then(|value| address_points.get(&(value as u32)))
+                {
+                    return (stored_class == class_name).then_some(size);
+                }
+            }
+            Operation::Branch { target, link: true } if allocation[0] => {
+                let symbol = library.get_symbol_name(target);
+                if let Some((called_class, signature)) =
+                    demangle(&symbol).as_deref().and_then(split_method)
+                    && signature.starts_with(&format!("{}(", get_constructor_name(&called_class)))
+                {
+                    return (normalize_class_name(&called_class) == class_name).then_some(size);
+                }
+            }
+            Operation::Return => return None,
+            _ => {}
+        }
+
+        match instruction.operation {
+            Operation::MoveRegister { rd, rm } => {
+                allocation[rd as usize] = allocation[rm as usize] && !instruction.conditional;
+            }
+            Operation::Branch { link: true, .. } | Operation::BranchRegister { link: true, .. } => {
+                for register in 0..32u8 {
+                    if set.is_caller_saved(register) {
+                        allocation[register as usize] = false;
+                    }
+                }
+            }
+            _ => {
+                if let Some(register) = disasm::get_written_register(library, &instruction, set)
+                    && let Some(slot) = allocation.get_mut(register as usize)
+                {
+                    *slot = false;
+                }
+            }
+        }
+        state.step(library, &instruction);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_elf::TestElf;
+
+    const RET: u32 = 0xd65f03c0;
+
+    // mov w0, #imm
+    fn mov_w0(imm: u32) -> u32 {
+        0x52800000 | imm << 5
+    }
+
+    fn bl(from: u32, to: u32) -> u32 {
+        0x94000000 | (to.wrapping_sub(from) >> 2) & 0x3ffffff
+    }
+
+    #[test]
+    fn sizes_come_from_helpers_constructing_their_class() {
+        let mut elf = TestElf::arm64();
+        let operator_new = elf.add_code(&[RET]);
+        elf.add_symbol("_Znwm", operator_new, 4);
+        let foo_constructor = elf.add_code(&[RET]);
+        elf.add_symbol("_ZN3FooC1Ev", foo_constructor, 4);
+        let bar_constructor = elf.add_code(&[RET]);
+        elf.add_symbol("_ZN3BarC1Ev", bar_constructor, 4);
+        let baz_vtable = elf.add_section(".data.rel.ro", &[0; 32]);
+        elf.add_symbol("_ZTV3Baz", baz_vtable, 32);
+
+        // calls the constructor of its class
+        let addr = elf.next_address();
+        let code = [
+            mov_w0(0x40),
+            bl(addr + 4, operator_new),
+            bl(addr + 8, foo_constructor),
+            RET,
+        ];
+        let addr = elf.add_code(&code);
+        elf.add_symbol("_ZN3Foo6createEv", addr, 16);
+
+        // not a factory of its own class by its name
+        let addr = elf.next_address();
+        let code = [
+            mov_w0(0x60),
+            bl(addr + 4, operator_new),
+            bl(addr + 8, foo_constructor),
+            RET,
+        ];
+        let addr = elf.add_code(&code);
+        elf.add_symbol("_ZN3Foo13createTextureEv", addr, 16);
+
+        // creates an object of another class
+        let addr = elf.next_address();
+        let code = [
+            mov_w0(0x20),
+            bl(addr + 4, operator_new),
+            bl(addr + 8, bar_constructor),
+            RET,
+        ];
+        let addr = elf.add_code(&code);
+        elf.add_symbol("_ZN3Foo13createWithBarEv", addr, 16);
+
+        // inlined constructor: mov x8, #address_point; str x8, [x0]
+        let addr = elf.next_address();
+        let code = [
+            mov_w0(0x30),
+            bl(addr + 4, operator_new),
+            0xd2800008 | (baz_vtable + 16) << 5,
+            0xf9000008,
+            RET,
+        ];
+        let addr = elf.add_code(&code);
+        elf.add_symbol("_ZN3Baz6createEv", addr, 20);
+
+        // nothing tells what the allocation is for
+        let addr = elf.next_address();
+        let code = [mov_w0(0x50), bl(addr + 4, operator_new), RET];
+        let addr = elf.add_code(&code);
+        elf.add_symbol("_ZN3Qux6createEv", addr, 12);
+
+        let sizes = ClassSizes::new(&elf.load());
+
+        assert_eq!(sizes.get_size("Foo"), Some(0x40));
+        assert_eq!(sizes.get("Foo").unwrap().sources, vec!["Foo::create()"]);
+        assert_eq!(sizes.get_size("Bar"), None);
+        assert_eq!(sizes.get_size("Baz"), Some(0x30));
+        assert_eq!(sizes.get_size("Qux"), None);
+    }
+}
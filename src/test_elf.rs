@@ -0,0 +1,210 @@
+//! Small ELF images built in memory for the tests. Everything is mapped by
+//! one executable PT_LOAD at address 0, every section at the address equal
+//! to its file offset.
+
+use crate::disasm::EM_AARCH64;
+use crate::library::Library;
+
+const EM_ARM: u16 = 40;
+// the ELF header and the program header are written there by `build`
+const HEADERS_SIZE: usize = 0x100;
+const SHT_PROGBITS: u32 = 1;
+const SHT_STRTAB: u32 = 3;
+const SHT_DYNSYM: u32 = 11;
+const SHF_ALLOC: u64 = 2;
+const SHF_EXECINSTR: u64 = 4;
+const STT_FUNC: u8 = 2;
+const STB_GLOBAL: u8 = 1;
+
+pub struct TestElf {
+    is_64: bool,
+    data: Vec<u8>,
+    // (name, type, offset, size)
+    sections: Vec<(String, u32, usize, usize)>,
+    // (name, value, size), a value of 0 imports the symbol
+    symbols: Vec<(String, u32, u32)>,
+}
+
+impl TestElf {
+    pub fn arm() -> Self {
+        Self::new(false)
+    }
+
+    pub fn arm64() -> Self {
+        Self::new(true)
+    }
+
+    fn new(is_64: bool) -> Self {
+        Self {
+            is_64,
+            data: vec![0; HEADERS_SIZE],
+            sections: Vec::new(),
+            symbols: Vec::new(),
+        }
+    }
+
+    /// The address the next section will be added at.
+    pub fn next_address(&self) -> u32 {
+        self.data.len().next_multiple_of(16) as u32
+    }
+
+    /// Appends a section and returns its address.
+    pub fn add_section(&mut self, name: &str, data: &[u8]) -> u32 {
+        self.data.resize(self.data.len().next_multiple_of(16), 0);
+        let offset = self.data.len();
+        self.data.extend(data);
+        self.sections
+            .push((name.to_string(), SHT_PROGBITS, offset, data.len()));
+        offset as u32
+    }
+
+    /// Appends a `.text` section of 32-bit instruction words.
+    pub fn add_code(&mut self, words: &[u32]) -> u32 {
+        let data: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        self.add_section(".text", &data)
+    }
+
+    /// Appends a `.text` section of thumb halfwords, returns its address with
+    /// the thumb bit set.
+    pub fn add_thumb_code(&mut self, halves: &[u16]) -> u32 {
+        let data: Vec<u8> = halves.iter().flat_map(|half| half.to_le_bytes()).collect();
+        self.add_section(".text", &data) | 1
+    }
+
+    pub fn add_symbol(&mut self, name: &str, addr: u32, size: u32) {
+        self.symbols.push((name.to_string(), addr, size));
+    }
+
+    pub fn build(mut self) -> Vec<u8> {
+        let load_end = self.data.len();
+
+        let mut dynstr = vec![0u8];
+        let mut dynsym = vec![0u8; self.symbol_size()];
+        for (name, value, size) in &self.symbols {
+            let name_offset = dynstr.len() as u32;
+            dynstr.extend(name.as_bytes());
+            dynstr.push(0);
+            let shndx: u16 = if *value == 0 { 0 } else { 1 };
+            let info = STB_GLOBAL << 4 | STT_FUNC;
+            if self.is_64 {
+                dynsym.extend(name_offset.to_le_bytes());
+                dynsym.extend([info, 0]);
+                dynsym.extend(shndx.to_le_bytes());
+                dynsym.extend((*value as u64).to_le_bytes());
+                dynsym.extend((*size as u64).to_le_bytes());
+            } else {
+                dynsym.extend(name_offset.to_le_bytes());
+                dynsym.extend(value.to_le_bytes());
+                dynsym.extend(size.to_le_bytes());
+                dynsym.extend([info, 0]);
+                dynsym.extend(shndx.to_le_bytes());
+            }
+        }
+        let dynstr_index = self.sections.len() + 1;
+        self.append_table(".dynstr", SHT_STRTAB, &dynstr);
+        self.append_table(".dynsym", SHT_DYNSYM, &dynsym);
+
+        let mut shstrtab = vec![0u8];
+        let mut name_offsets = Vec::new();
+        for name in self
+            .sections
+            .iter()
+            .map(|(name, ..)| name.clone())
+            .chain([".shstrtab".to_string()])
+        {
+            name_offsets.push(shstrtab.len() as u32);
+            shstrtab.extend(name.as_bytes());
+            shstrtab.push(0);
+        }
+        self.append_table(".shstrtab", SHT_STRTAB, &shstrtab);
+
+        self.data.resize(self.data.len().next_multiple_of(8), 0);
+        let section_headers = self.data.len();
+        let header_size = if self.is_64 { 64 } else { 40 };
+        self.data.extend(vec![0; header_size]);
+        for (index, (_, section_type, offset, size)) in self.sections.clone().iter().enumerate() {
+            let flags = match *section_type {
+                SHT_PROGBITS => SHF_ALLOC | SHF_EXECINSTR,
+                _ => 0,
+            };
+            let (link, entry_size) = match *section_type {
+                SHT_DYNSYM => (dynstr_index as u32, self.symbol_size() as u64),
+                _ => (0, 0),
+            };
+            let fields = [
+                (name_offsets[index] as u64, 4),
+                (*section_type as u64, 4),
+                (flags, self.word_size()),
+                (*offset as u64, self.word_size()),
+                (*offset as u64, self.word_size()),
+                (*size as u64, self.word_size()),
+                (link as u64, 4),
+                (0, 4),
+                (1, self.word_size()),
+                (entry_size, self.word_size()),
+            ];
+            for (value, size) in fields {
+                self.data.extend(&value.to_le_bytes()[..size]);
+            }
+        }
+
+        let section_count = self.sections.len() as u16 + 1;
+        let (machine, class) = if self.is_64 {
+            (EM_AARCH64, 2)
+        } else {
+            (EM_ARM, 1)
+        };
+        let mut header = vec![0x7f, b'E', b'L', b'F', class, 1, 1];
+        header.resize(16, 0);
+        header.extend(3u16.to_le_bytes());
+        header.extend(machine.to_le_bytes());
+        header.extend(1u32.to_le_bytes());
+        let program_headers = if self.is_64 { 64 } else { 52 };
+        for value in [0, program_headers, section_headers as u64] {
+            header.extend(&value.to_le_bytes()[..self.word_size()]);
+        }
+        header.extend(0u32.to_le_bytes());
+        let sizes: [u16; 6] = if self.is_64 {
+            [64, 56, 1, 64, section_count, section_count - 1]
+        } else {
+            [52, 32, 1, 40, section_count, section_count - 1]
+        };
+        header.extend(sizes.iter().flat_map(|size| size.to_le_bytes()));
+
+        // PT_LOAD, R+X, covering the headers and the added sections
+        let load_end = load_end as u64;
+        if self.is_64 {
+            header.extend(1u32.to_le_bytes());
+            header.extend(5u32.to_le_bytes());
+            for value in [0, 0, 0, load_end, load_end, 0x1000u64] {
+                header.extend(value.to_le_bytes());
+            }
+        } else {
+            for value in [1, 0, 0, 0, load_end as u32, load_end as u32, 5, 0x1000u32] {
+                header.extend(value.to_le_bytes());
+            }
+        }
+        self.data[..header.len()].copy_from_slice(&header);
+        self.data
+    }
+
+    pub fn load(self) -> Library {
+        Library::from_image(self.build())
+    }
+
+    fn append_table(&mut self, name: &str, section_type: u32, data: &[u8]) {
+        self.data.resize(self.data.len().next_multiple_of(8), 0);
+        let offset = self.data.len();
+        self.data.extend(data);
+        self.sections
+            .push((name.to_string(), section_type, offset, data.len()));
+    }
+
+    fn word_size(&self) -> usize {
+        if self.is_64 { 8 } else { 4 }
+    }
+
+    fn symbol_size(&self) -> usize {
+        if self.is_64 { 24 } else { 16 }
+    }
+}
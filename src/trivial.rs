@@ -0,0 +1,179 @@
+use crate::class_index::demangle;
+use crate::disasm::{self, Operation};
+use crate::library::Library;
+use serde::Serialize;
+
+// the stubs worth telling apart are a couple of instructions long
+const MAX_STUB_SIZE: u32 = 0x10;
+
+/// What the function a vtable slot points at does, when it is simple enough
+/// to tell from its first instructions.
+#[derive(Serialize, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Implementation {
+    /// returns right away
+    Empty,
+    ConstantReturn {
+        value: u64,
+    },
+    /// returns the member at `this + offset`
+    FieldGetter {
+        offset: i64,
+        size: u8,
+    },
+    /// sets up arguments (or adjusts `this`) and jumps to another function
+    TailCall {
+        target: u32,
+        symbol: String,
+    },
+    NonTrivial,
+}
+
+pub fn classify_implementation(library: &Library, addr: u32) -> Implementation {
+    if !library.is_executable_address(addr) || library.plt_symbols.contains_key(&(addr & !1)) {
+        return Implementation::NonTrivial;
+    }
+
+    let instructions = disasm::decode_function(library, addr, MAX_STUB_SIZE);
+    let operations: Vec<&Operation> = instructions
+        .iter()
+        .take_while(|instruction| !instruction.conditional)
+        .map(|instruction| &instruction.operation)
+        .collect();
+
+    match operations.as_slice() {
+        [Operation::Return, ..] => return Implementation::Empty,
+        [
+            Operation::MoveImmediate { rd: 0, imm },
+            Operation::Return,
+            ..,
+        ] => {
+            return Implementation::ConstantReturn { value: *imm };
+        }
+        [
+            Operation::Load {
+                rt: 0,
+                rn: 0,
+                offset,
+                size,
+            },
+            Operation::Return,
+            ..,
+        ] => {
+            return Implementation::FieldGetter {
+                offset: *offset,
+                size: *size,
+            };
+        }
+        _ => {}
+    }
+
+    // argument setup only, then an unconditional jump
+    let jump = operations.iter().find(|operation| {
+        !matches!(
+            operation,
+            Operation::MoveImmediate { .. }
+                | Operation::MoveRegister { .. }
+                | Operation::AddImmediate { .. }
+        )
+    });
+    if let Some(Operation::Branch {
+        target,
+        link: false,
+    }) = jump
+    {
+        return Implementation::TailCall {
+            target: *target,
+            symbol: library.get_symbol_name(*target),
+        };
+    }
+
+    Implementation::NonTrivial
+}
+
+impl Implementation {
+    /// A short description for the comments of the generated headers, `None`
+    /// for non-trivial functions.
+    pub fn get_comment(&self) -> Option<String> {
+        match self {
+            Implementation::Empty => Some("empty".to_string()),
+            Implementation::ConstantReturn { value } => Some(format!("returns {}", value)),
+            Implementation::FieldGetter { offset, .. } => {
+                Some(format!("returns this->field_0x{:x}", offset))
+            }
+            Implementation::TailCall { symbol, .. } => Some(format!(
+                "tail calls {}",
+                demangle(symbol).unwrap_or(symbol.clone())
+            )),
+            Implementation::NonTrivial => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_elf::TestElf;
+
+    const RET: u32 = 0xd65f03c0;
+
+    #[test]
+    fn arm64_stubs() {
+        let mut elf = TestElf::arm64();
+        let empty = elf.add_code(&[RET]);
+        // mov w0, #5
+        let constant = elf.add_code(&[0x528000a0, RET]);
+        // mov w0, wzr
+        let zero = elf.add_code(&[0x2a1f03e0, RET]);
+        // ldr x0, [x0, #0x18]
+        let getter = elf.add_code(&[0xf9400c00, RET]);
+        let target = elf.next_address() + 0x10;
+        // mov x0, x1; b target
+        let tail_call = elf.add_code(&[0xaa0103e0, 0x14000003]);
+        elf.add_symbol("_ZN3Foo3runEv", target, 4);
+        // ldr x0, [x0, #0x18]; add w0, w0, #1
+        let non_trivial = elf.add_code(&[0xf9400c00, 0x11000400, RET]);
+        let library = elf.load();
+
+        assert!(classify_implementation(&library, empty) == Implementation::Empty);
+        assert!(
+            classify_implementation(&library, constant)
+                == Implementation::ConstantReturn { value: 5 }
+        );
+        assert!(
+            classify_implementation(&library, zero) == Implementation::ConstantReturn { value: 0 }
+        );
+        assert!(
+            classify_implementation(&library, getter)
+                == Implementation::FieldGetter {
+                    offset: 0x18,
+                    size: 8
+                }
+        );
+        assert!(
+            classify_implementation(&library, tail_call)
+                == Implementation::TailCall {
+                    target,
+                    symbol: "_ZN3Foo3runEv".to_string()
+                }
+        );
+        assert!(classify_implementation(&library, non_trivial) == Implementation::NonTrivial);
+    }
+
+    #[test]
+    fn thumb_stubs() {
+        let mut elf = TestElf::arm();
+        // movs r0, #1; bx lr
+        let constant = elf.add_thumb_code(&[0x2001, 0x4770]);
+        let library = elf.load();
+
+        assert!(
+            classify_implementation(&library, constant)
+                == Implementation::ConstantReturn { value: 1 }
+        );
+        assert_eq!(
+            Implementation::ConstantReturn { value: 1 }.get_comment(),
+            Some("returns 1".to_string())
+        );
+    }
+}
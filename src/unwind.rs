@@ -0,0 +1,378 @@
+use crate::library::{self, Library, Module};
+use std::collections::HashMap;
+
+// DW_EH_PE pointer encodings used by .eh_frame and .eh_frame_hdr
+const DW_EH_PE_OMIT: u8 = 0xff;
+const DW_EH_PE_PCREL: u8 = 0x10;
+const DW_EH_PE_DATAREL: u8 = 0x30;
+const DW_EH_PE_INDIRECT: u8 = 0x80;
+
+/// The (start, size) of every function described by the unwind tables of
+/// every module, `.ARM.exidx` for ARM32 and `.eh_frame` otherwise, sorted by
+/// start. ARM32 starts have the thumb bit set for thumb code.
+pub fn get_function_ranges(library: &Library) -> Vec<(u32, u32)> {
+    let mut ranges = Vec::new();
+    for module in &library.modules {
+        ranges.extend(get_eh_frame_ranges(library, module));
+        if library.pointer_size == 4 {
+            ranges.extend(get_exidx_ranges(library, module));
+        }
+    }
+
+    ranges.retain(|(start, size)| *size > 0 && library.is_executable_address(*start));
+    // .eh_frame sizes are exact, keep them over the .ARM.exidx ones
+    ranges.sort_by_key(|(start, _)| *start);
+    ranges.dedup_by_key(|(start, _)| *start);
+
+    if library.pointer_size == 4 {
+        for (start, _) in &mut ranges {
+            if is_thumb_function(library, *start) {
+                *start |= 1;
+            }
+        }
+    }
+    ranges
+}
+
+/// The (virtual address, size) of the segment of a module holding an unwind
+/// table, or of its section when there is no such program header.
+fn get_table_range(
+    library: &Library,
+    module: &Module,
+    segment_type: u32,
+    section_name: &str,
+) -> Option<(u32, u32)> {
+    let data = library.get_module_data(module);
+    let elf = elf::ElfBytes::<elf::endian::LittleEndian>::minimal_parse(data).ok()?;
+    let segment = elf.segments().and_then(|segments| {
+        segments
+            .iter()
+            .find(|segment| segment.p_type == segment_type)
+            .map(|segment| (segment.p_vaddr as u32, segment.p_memsz as u32))
+    });
+
+    segment
+        .or_else(|| {
+            library::get_section_by_name(data, section_name)
+                .map(|section| (section.sh_addr as u32, section.sh_size as u32))
+        })
+        .map(|(addr, size)| (module.base + addr, size))
+}
+
+/// `.ARM.exidx` entries are pairs of words sorted by address, the first one
+/// a 31-bit offset from itself to the function. Entries only hold the start,
+/// a function ends where the next one begins.
+fn get_exidx_ranges(library: &Library, module: &Module) -> Vec<(u32, u32)> {
+    let Some((table, size)) =
+        get_table_range(library, module, elf::abi::PT_ARM_EXIDX, ".ARM.exidx")
+    else {
+        return Vec::new();
+    };
+
+    let starts: Vec<u32> = (table..table + size)
+        .step_by(8)
+        .filter_map(|entry| {
+            let word = library.read_u32(entry)?;
+            let offset = ((word << 1) as i32) >> 1;
+            Some(entry.wrapping_add(offset as u32))
+        })
+        .collect();
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, start)| {
+            let end = starts
+                .get(i + 1)
+                .copied()
+                .or_else(|| {
+                    library
+                        .get_executable_range(*start)
+                        .map(|(start, size)| start + size)
+                })
+                .unwrap_or(*start);
+            (*start, end.saturating_sub(*start))
+        })
+        .collect()
+}
+
+/// Walks the CIEs and FDEs of `.eh_frame`, found through `.eh_frame_hdr` so
+/// that section headers are not needed.
+fn get_eh_frame_ranges(library: &Library, module: &Module) -> Vec<(u32, u32)> {
+    let eh_frame =
+        match get_table_range(library, module, elf::abi::PT_GNU_EH_FRAME, ".eh_frame_hdr") {
+            Some((header, _)) => {
+                let mut reader = Reader::new(library, header);
+                let _version = reader.read_u8();
+                let encoding = reader.read_u8().unwrap_or(DW_EH_PE_OMIT);
+                reader.skip(2);
+                reader
+                    .read_encoded(encoding, header)
+                    .map(|addr| (addr, u32::MAX))
+            }
+            None => library::get_section_by_name(library.get_module_data(module), ".eh_frame")
+                .map(|section| (module.base + section.sh_addr as u32, section.sh_size as u32)),
+        };
+    let Some((start, size)) = eh_frame else {
+        return Vec::new();
+    };
+    let end = start.saturating_add(size);
+
+    let mut ranges = Vec::new();
+    // CIE address -> FDE pointer encoding
+    let mut cie_encodings: HashMap<u32, u8> = HashMap::new();
+    let mut reader = Reader::new(library, start);
+
+    while reader.addr < end {
+        let entry = reader.addr;
+        let Some(length) = reader.read_u32() else {
+            break;
+        };
+        // a zero length terminates the table, 64-bit DWARF is not used here
+        if length == 0 || length == u32::MAX {
+            break;
+        }
+        let next = reader.addr.saturating_add(length);
+        let id_addr = reader.addr;
+        let Some(id) = reader.read_u32() else {
+            break;
+        };
+
+        if id == 0 {
+            if let Some(encoding) = read_cie_encoding(&mut reader) {
+                cie_encodings.insert(entry, encoding);
+            }
+        } else {
+            let cie = id_addr.wrapping_sub(id);
+            let encoding = cie_encodings.get(&cie).copied().unwrap_or(0);
+            // the size has the format of the start but is not relative
+            if let Some(function) = reader.read_encoded(encoding, 0)
+                && let Some(size) = reader.read_encoded(encoding & 0x0f, 0)
+                && function != 0
+            {
+                ranges.push((function, size));
+            }
+        }
+
+        reader.addr = next;
+    }
+
+    ranges
+}
+
+/// The FDE pointer encoding of a CIE, from the `R` entry of its
+/// augmentation data.
+fn read_cie_encoding(reader: &mut Reader) -> Option<u8> {
+    let version = reader.read_u8()?;
+    let mut augmentation = Vec::new();
+    while let Some(byte) = reader.read_u8()
+        && byte != 0
+    {
+        augmentation.push(byte);
+    }
+    if augmentation.starts_with(b"eh") {
+        reader.skip(reader.library.pointer_size);
+    }
+    reader.read_uleb128()?; // code alignment
+    reader.read_sleb128()?; // data alignment
+    if version == 1 {
+        reader.read_u8()?;
+    } else {
+        reader.read_uleb128()?;
+    }
+
+    if augmentation.first() != Some(&b'z') {
+        return Some(0);
+    }
+    reader.read_uleb128()?;
+    for character in &augmentation[1..] {
+        match character {
+            b'R' => return reader.read_u8(),
+            b'P' => {
+                let encoding = reader.read_u8()?;
+                reader.read_encoded(encoding, 0)?;
+            }
+            b'L' => {
+                reader.read_u8()?;
+            }
+            _ => {}
+        }
+    }
+    Some(0)
+}
+
+/// Android ARM code is mostly thumb, but the exidx and FDE addresses never
+/// carry the thumb bit. Trust a symbol at the address, otherwise look for the
+/// always condition of ARM instructions in the first word.
+fn is_thumb_function(library: &Library, start: u32) -> bool {
+    if library.addr_to_sym.contains_key(&(start | 1)) {
+        return true;
+    }
+    if library.addr_to_sym.contains_key(&start) {
+        return false;
+    }
+    library
+        .read_u32(start)
+        .is_some_and(|word| word >> 28 != 0xe)
+}
+
+/// Reads the little endian values of the unwind tables at virtual addresses.
+struct Reader<'a> {
+    library: &'a Library,
+    addr: u32,
+}
+
+impl<'a> Reader<'a> {
+    fn new(library: &'a Library, addr: u32) -> Self {
+        Self { library, addr }
+    }
+
+    fn skip(&mut self, size: u32) {
+        self.addr = self.addr.wrapping_add(size);
+    }
+
+    fn read_bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let offset = self.library.get_file_offset(self.addr)? as usize;
+        let bytes = self.library.data.get(offset..offset + N)?;
+        self.addr = self.addr.wrapping_add(N as u32);
+        bytes.try_into().ok()
+    }
+
+    fn read_u8(&mut self) -> Option<u8> {
+        self.read_bytes::<1>().map(|bytes| bytes[0])
+    }
+
+    fn read_u32(&mut self) -> Option<u32> {
+        self.read_bytes().map(u32::from_le_bytes)
+    }
+
+    fn read_uleb128(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.read_u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7f) as u64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+    }
+
+    fn read_sleb128(&mut self) -> Option<i64> {
+        let mut value = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.read_u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7f) as i64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return Some(value);
+            }
+        }
+    }
+
+    /// Reads a pointer in a DW_EH_PE encoding, `data` is the base of
+    /// data relative pointers (the start of `.eh_frame_hdr`).
+    fn read_encoded(&mut self, encoding: u8, data: u32) -> Option<u32> {
+        if encoding == DW_EH_PE_OMIT {
+            return None;
+        }
+        let field = self.addr;
+        let value = match encoding & 0x0f {
+            0x00 if self.library.pointer_size == 8 => u64::from_le_bytes(self.read_bytes()?) as i64,
+            0x00 => self.read_u32()? as i64,
+            0x01 => self.read_uleb128()? as i64,
+            0x02 => u16::from_le_bytes(self.read_bytes()?) as i64,
+            0x03 => self.read_u32()? as i64,
+            0x04 => u64::from_le_bytes(self.read_bytes()?) as i64,
+            0x09 => self.read_sleb128()?,
+            0x0a => i16::from_le_bytes(self.read_bytes()?) as i64,
+            0x0b => i32::from_le_bytes(self.read_bytes()?) as i64,
+            0x0c => i64::from_le_bytes(self.read_bytes()?),
+            _ => return None,
+        };
+        let base = match encoding & 0x70 {
+            DW_EH_PE_PCREL => field,
+            DW_EH_PE_DATAREL => data,
+            _ => 0,
+        };
+        let addr = (base as i64).wrapping_add(value) as u32;
+
+        if encoding & DW_EH_PE_INDIRECT != 0 {
+            return self.library.read_pointer(addr);
+        }
+        Some(addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_elf::TestElf;
+
+    #[test]
+    fn exidx_entries_start_functions() {
+        let mut elf = TestElf::arm();
+        // push {r7, lr}; pop {r7, pc}
+        let thumb_function = elf.add_thumb_code(&[0xb580, 0xbd80]);
+        elf.add_symbol("_ZN3Foo3runEv", thumb_function, 4);
+        // bx lr
+        let arm_function = elf.add_code(&[0xe12fff1e]);
+
+        let table = elf.next_address();
+        let mut exidx = Vec::new();
+        for (i, function) in [thumb_function & !1, arm_function].iter().enumerate() {
+            let entry = table + i as u32 * 8;
+            exidx.extend((function.wrapping_sub(entry) & 0x7fffffff).to_le_bytes());
+            // EXIDX_CANTUNWIND
+            exidx.extend(1u32.to_le_bytes());
+        }
+        elf.add_section(".ARM.exidx", &exidx);
+
+        let ranges = get_function_ranges(&elf.load());
+
+        assert_eq!(ranges.len(), 2);
+        assert_eq!(
+            ranges[0],
+            (thumb_function, arm_function - (thumb_function & !1))
+        );
+        assert_eq!(ranges[1].0, arm_function);
+    }
+
+    #[test]
+    fn eh_frame_fdes_give_function_ranges() {
+        let mut elf = TestElf::arm64();
+        let first = elf.add_code(&[0xd65f03c0]);
+        let second = elf.add_code(&[0xd503201f, 0xd65f03c0]);
+
+        let start = elf.next_address();
+        // version 1, "zR", code alignment 4, data alignment -8, return
+        // address x30, pc relative sdata4 pointers
+        let mut eh_frame = Vec::new();
+        eh_frame.extend(16u32.to_le_bytes());
+        eh_frame.extend(0u32.to_le_bytes());
+        eh_frame.extend([1, b'z', b'R', 0, 4, 0x78, 30, 1, 0x1b, 0, 0, 0]);
+        for (function, size) in [(second, 8u32), (first, 4)] {
+            let entry = start + eh_frame.len() as u32;
+            eh_frame.extend(16u32.to_le_bytes());
+            eh_frame.extend((entry + 4 - start).to_le_bytes());
+            eh_frame.extend(function.wrapping_sub(entry + 8).to_le_bytes());
+            eh_frame.extend(size.to_le_bytes());
+            eh_frame.extend([0, 0, 0, 0]);
+        }
+        eh_frame.extend(0u32.to_le_bytes());
+        elf.add_section(".eh_frame", &eh_frame);
+
+        let ranges = get_function_ranges(&elf.load());
+
+        assert_eq!(ranges, vec![(first, 4), (second, 8)]);
+    }
+}
@@ -0,0 +1,303 @@
+use crate::class_index::demangle;
+use crate::hierarchy::Hierarchy;
+use crate::library::Library;
+use crate::rtti::TypeInfoKind;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingKind {
+    /// a vtable or a base list points at something whose vptr is not one of
+    /// the cxxabi typeinfo vtables
+    NotATypeinfo,
+    /// the base list of a vmi typeinfo runs past its symbol or the file
+    BaseCountOverrun,
+    /// a typeinfo is its own base, directly or not
+    BaseCycle,
+    /// a vtable slot points outside of the executable segments
+    SlotNotExecutable,
+    /// the vtable groups read do not cover the `st_size` of the vtable
+    VtableSize,
+}
+
+impl FindingKind {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            FindingKind::NotATypeinfo => "not_a_typeinfo",
+            FindingKind::BaseCountOverrun => "base_count_overrun",
+            FindingKind::BaseCycle => "base_cycle",
+            FindingKind::SlotNotExecutable => "slot_not_executable",
+            FindingKind::VtableSize => "vtable_size",
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Finding {
+    pub kind: FindingKind,
+    /// the vtable or typeinfo the finding is about
+    pub address: u32,
+    pub class_name: String,
+    pub message: String,
+}
+
+/// Walks every vtable and typeinfo of the library and reports what does not
+/// match the Itanium C++ ABI, sorted by kind and address.
+pub fn validate(library: &Library, hierarchy: &Hierarchy) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    // typeinfo -> what refers to it, in the order they were found
+    let mut references: BTreeMap<u32, String> = BTreeMap::new();
+
+    for (vtable_address, _) in library.get_vtables() {
+        let typeinfo = library.get_vtable_typeinfo(vtable_address);
+        // built without RTTI, or the typeinfo is imported from a library
+        // that is not loaded
+        if typeinfo == 0 {
+            continue;
+        }
+        let class_name = get_class_name(library, hierarchy, typeinfo);
+        references
+            .entry(typeinfo)
+            .or_insert_with(|| format!("the vtable at 0x{:x}", vtable_address));
+
+        let mut end_address = vtable_address;
+        for (group, (group_address, _, functions)) in library
+            .get_vtable_groups(vtable_address)
+            .into_iter()
+            .enumerate()
+        {
+            for (slot, function) in functions.iter().enumerate() {
+                // imports from a library that is not loaded only hold their
+                // addend
+                let slot_address = group_address + (2 + slot as u32) * library.pointer_size;
+                if !library.is_executable_address(*function)
+                    && !library.unresolved_imports.contains_key(&slot_address)
+                {
+                    findings.push(Finding {
+                        kind: FindingKind::SlotNotExecutable,
+                        address: vtable_address,
+                        class_name: class_name.clone(),
+                        message: format!(
+                            "slot {} of group {} points at 0x{:x}, outside of the executable segments",
+                            slot, group, function
+                        ),
+                    });
+                }
+            }
+            end_address = group_address + (2 + functions.len() as u32) * library.pointer_size;
+        }
+
+        let read_size = end_address - vtable_address;
+        if let Some(size) = library.get_symbol_size(vtable_address)
+            && size != read_size
+        {
+            findings.push(Finding {
+                kind: FindingKind::VtableSize,
+                address: vtable_address,
+                class_name,
+                message: format!(
+                    "st_size is 0x{:x} but the vtable groups read are 0x{:x} bytes",
+                    size, read_size
+                ),
+            });
+        }
+    }
+
+    let mut typeinfos: Vec<u32> = library.get_typeinfos();
+    typeinfos.extend(
+        library
+            .sym_to_addr
+            .iter()
+            .filter(|(symbol, addr)| symbol.starts_with("_ZTI") && **addr != 0)
+            .map(|(_, addr)| *addr),
+    );
+    typeinfos.sort();
+    typeinfos.dedup();
+
+    for typeinfo in &typeinfos {
+        if let Some(typeinfo_info) = library.get_typeinfo(*typeinfo) {
+            for (index, base) in typeinfo_info.bases.iter().enumerate() {
+                references.entry(base.typeinfo).or_insert_with(|| {
+                    format!(
+                        "base {} of {}",
+                        index,
+                        get_class_name(library, hierarchy, *typeinfo)
+                    )
+                });
+            }
+        }
+        if library.get_typeinfo_kind(*typeinfo) == TypeInfoKind::VirtualMultipleInheritance {
+            findings.extend(check_base_count(library, hierarchy, *typeinfo));
+        }
+    }
+
+    // imported typeinfos that no loaded library defines are left at 0
+    for (typeinfo, reference) in references.iter().filter(|(typeinfo, _)| **typeinfo != 0) {
+        if library.get_typeinfo_kind(*typeinfo) != TypeInfoKind::Unknown {
+            continue;
+        }
+        let vptr = library.read_pointer(*typeinfo);
+        findings.push(Finding {
+            kind: FindingKind::NotATypeinfo,
+            address: *typeinfo,
+            class_name: get_class_name(library, hierarchy, *typeinfo),
+            message: format!(
+                "referenced by {} as a typeinfo, but its vptr ({}) is not into a cxxabi typeinfo vtable",
+                reference,
+                match vptr {
+                    Some(vptr) => format!("0x{:x}", vptr),
+                    None => "unreadable".to_string(),
+                }
+            ),
+        });
+    }
+
+    findings.extend(find_base_cycles(library, hierarchy, &typeinfos));
+    findings.sort_by_key(|finding| (finding.kind, finding.address));
+    findings
+}
+
+/// The base list of a vmi typeinfo has to fit in its `_ZTI` symbol, or at
+/// least in the file.
+fn check_base_count(library: &Library, hierarchy: &Hierarchy, typeinfo: u32) -> Option<Finding> {
+    let pointer_size = library.pointer_size;
+    let base_count = library.read_u32(typeinfo + 2 * pointer_size + 4)?;
+    let end =
+        (typeinfo + 2 * pointer_size + 8) as u64 + 2 * pointer_size as u64 * base_count as u64;
+
+    let limit = match library.get_symbol_size(typeinfo) {
+        Some(size) => (typeinfo + size) as u64,
+        None if end <= u32::MAX as u64 && library.get_file_offset(end as u32 - 1).is_some() => {
+            return None;
+        }
+        None => {
+            return Some(Finding {
+                kind: FindingKind::BaseCountOverrun,
+                address: typeinfo,
+                class_name: get_class_name(library, hierarchy, typeinfo),
+                message: format!(
+                    "{} bases would run past the end of the file, to 0x{:x}",
+                    base_count, end
+                ),
+            });
+        }
+    };
+
+    (end > limit).then(|| Finding {
+        kind: FindingKind::BaseCountOverrun,
+        address: typeinfo,
+        class_name: get_class_name(library, hierarchy, typeinfo),
+        message: format!(
+            "{} bases end at 0x{:x}, past the end of the typeinfo symbol at 0x{:x}",
+            base_count, end, limit
+        ),
+    })
+}
+
+/// Every cycle of base links, reported once with the typeinfo that was
+/// reached first.
+fn find_base_cycles(library: &Library, hierarchy: &Hierarchy, typeinfos: &[u32]) -> Vec<Finding> {
+    let bases: HashMap<u32, Vec<u32>> = typeinfos
+        .iter()
+        .filter_map(|typeinfo| {
+            library.get_typeinfo(*typeinfo).map(|typeinfo_info| {
+                (
+                    *typeinfo,
+                    typeinfo_info
+                        .bases
+                        .iter()
+                        .map(|base| base.typeinfo)
+                        .collect(),
+                )
+            })
+        })
+        .collect();
+
+    let mut findings = Vec::new();
+    let mut visited: HashSet<u32> = HashSet::new();
+    let mut reported: HashSet<Vec<u32>> = HashSet::new();
+
+    for typeinfo in typeinfos {
+        if visited.contains(typeinfo) {
+            continue;
+        }
+        // depth first, `path` is the chain of derived to base typeinfos
+        let mut path: Vec<u32> = Vec::new();
+        let mut stack: Vec<(u32, usize)> = vec![(*typeinfo, 0)];
+
+        while let Some((current, depth)) = stack.pop() {
+            path.truncate(depth);
+            if let Some(start) = path.iter().position(|addr| *addr == current) {
+                let cycle = path[start..].to_vec();
+                let mut key = cycle.clone();
+                key.sort();
+                if reported.insert(key) {
+                    findings.push(Finding {
+                        kind: FindingKind::BaseCycle,
+                        address: current,
+                        class_name: get_class_name(library, hierarchy, current),
+                        message: format!(
+                            "is its own base: {}",
+                            cycle
+                                .iter()
+                                .chain(std::iter::once(&current))
+                                .map(|addr| get_class_name(library, hierarchy, *addr))
+                                .collect::<Vec<String>>()
+                                .join(" -> ")
+                        ),
+                    });
+                }
+                continue;
+            }
+            if !visited.insert(current) {
+                continue;
+            }
+
+            path.push(current);
+            for base in bases.get(&current).into_iter().flatten().rev() {
+                stack.push((*base, depth + 1));
+            }
+        }
+    }
+
+    findings
+}
+
+/// The class name of a typeinfo, read from its name when it is too broken
+/// for `Hierarchy` to have parsed it.
+fn get_class_name(library: &Library, hierarchy: &Hierarchy, typeinfo: u32) -> String {
+    if hierarchy.typeinfos.contains_key(&typeinfo) {
+        return hierarchy.get_class_name(typeinfo);
+    }
+
+    library
+        .read_pointer(typeinfo + library.pointer_size)
+        .and_then(|name| library.read_cstr(name))
+        .and_then(|name| demangle(&name))
+        .unwrap_or_else(|| hierarchy.get_class_name(typeinfo))
+}
+
+pub fn get_display(findings: &[Finding]) -> String {
+    if findings.is_empty() {
+        return "no anomalies found".to_string();
+    }
+
+    let mut lines: Vec<String> = findings
+        .iter()
+        .map(|finding| {
+            format!(
+                "{:<20} 0x{:<8x} {}: {}",
+                finding.kind.get_name(),
+                finding.address,
+                finding.class_name,
+                finding.message
+            )
+        })
+        .collect();
+    lines.push(match findings.len() {
+        1 => "1 anomaly found".to_string(),
+        count => format!("{} anomalies found", count),
+    });
+    lines.join("\n")
+}
@@ -0,0 +1,127 @@
+use crate::class_index::demangle;
+use crate::hierarchy::Hierarchy;
+use crate::library::Library;
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct CallTarget {
+    pub class_name: String,
+    /// offset-to-top of the vtable group holding the slot
+    pub offset_to_top: i32,
+    pub address: u32,
+    pub symbol: String,
+}
+
+#[derive(Serialize)]
+pub struct VirtualCall {
+    pub class_name: String,
+    /// offset of the subobject whose vptr is loaded
+    pub subobject_offset: i32,
+    /// byte offset from the vptr
+    pub offset: u32,
+    pub slot: usize,
+    pub targets: Vec<CallTarget>,
+}
+
+/// The function a `ldr rX, [vptr, #offset]` call lands on for `class_selector`
+/// and every class deriving from it, `subobject_offset` selects the vptr of
+/// a secondary vtable group.
+pub fn resolve_virtual_call(
+    library: &Library,
+    hierarchy: &Hierarchy,
+    class_selector: &str,
+    subobject_offset: i32,
+    offset: u32,
+) -> Result<VirtualCall, String> {
+    if !offset.is_multiple_of(library.pointer_size) {
+        return Err(format!(
+            "vtable offset 0x{:x} is not a multiple of the slot size",
+            offset
+        ));
+    }
+    let slot = (offset / library.pointer_size) as usize;
+
+    let typeinfo = hierarchy
+        .find_typeinfo(library, class_selector)
+        .ok_or_else(|| {
+            library
+                .class_index
+                .get_unknown_class_message(class_selector)
+        })?;
+    let class_name = hierarchy.get_class_name(typeinfo);
+
+    let mut targets = Vec::new();
+    let classes = std::iter::once(typeinfo).chain(
+        hierarchy
+            .get_derived_flat(typeinfo, None)
+            .into_iter()
+            .map(|(_, derived)| derived),
+    );
+
+    for derived in classes {
+        let Some(vtable) = hierarchy.get_vtable(derived) else {
+            continue;
+        };
+        let Some(base_offset) = hierarchy.get_base_offset(derived, typeinfo) else {
+            eprintln!(
+                "skipping {}: {} is a virtual base of it",
+                hierarchy.get_class_name(derived),
+                class_name
+            );
+            continue;
+        };
+
+        let offset_to_top = -(base_offset + subobject_offset);
+        let Some(addr) = library
+            .get_class_vtable(vtable)
+            .into_iter()
+            .find(|(group_offset, _)| *group_offset == offset_to_top)
+            .and_then(|(_, functions)| functions.get(slot).copied())
+        else {
+            continue;
+        };
+
+        targets.push(CallTarget {
+            class_name: hierarchy.get_class_name(derived),
+            offset_to_top,
+            address: addr,
+            symbol: library.get_symbol_name(addr),
+        });
+    }
+
+    if targets.is_empty() {
+        return Err(format!(
+            "{} has no vtable slot at offset 0x{:x} of the vtable group for subobject offset 0x{:x}",
+            class_name, offset, subobject_offset
+        ));
+    }
+
+    Ok(VirtualCall {
+        class_name,
+        subobject_offset,
+        offset,
+        slot,
+        targets,
+    })
+}
+
+impl VirtualCall {
+    pub fn get_display(&self) -> String {
+        let mut lines = vec![format!(
+            "{}  [subobject +0x{:x}, vptr+0x{:x} (slot {})]",
+            self.class_name, self.subobject_offset, self.offset, self.slot
+        )];
+
+        for target in &self.targets {
+            lines.push(format!(
+                "    {}  [offset-to-top {}]  0x{:x}  {}",
+                target.class_name,
+                target.offset_to_top,
+                target.address,
+                demangle(&target.symbol).unwrap_or(target.symbol.clone())
+            ));
+        }
+
+        lines.join("\n")
+    }
+}